@@ -1,21 +1,154 @@
+use super::layout::{KeyboardLayout, Qwerty};
 use crate::serial_println;
 use conquer_once::spin::OnceCell;
+use core::{
+    pin::Pin,
+    sync::atomic::{AtomicBool, Ordering},
+    task::{Context, Poll},
+};
 use crossbeam_queue::ArrayQueue;
+use futures_util::stream::Stream;
+use futures_util::task::AtomicWaker;
 
 //use OnceCell over lazy_static bc OnceCell type has the advantage that we can ensure that the initialization does not happen in the interrupt handler, thus preventing the interrupt handler from performing a heap allocation
 pub static SCANCODE_QUEUE: OnceCell<ArrayQueue<u8>> = OnceCell::uninit();
+static WAKER: AtomicWaker = AtomicWaker::new();
+//Only warn once so a flood of input doesn't also flood the serial port
+static WARNED: AtomicBool = AtomicBool::new(false);
 
 /// called by the keyboard interrupt handler - must not block or allocate.
 pub fn add_scancode(scancode: u8) {
     if let Ok(queue) = SCANCODE_QUEUE.try_get() {
         if queue.push(scancode).is_err() {
-            serial_println!("WARNING: scancode queue full; dropping keyboard input");
+            if !WARNED.swap(true, Ordering::Relaxed) {
+                serial_println!("WARNING: scancode queue full; dropping keyboard input");
+            }
+        } else {
+            WAKER.wake();
         }
-    } else {
+    } else if !WARNED.swap(true, Ordering::Relaxed) {
         serial_println!("WARNING: scancode queue uninitialized");
     }
 }
 
+/// Asynchronously yields the raw scancodes pushed by the keyboard interrupt handler.
+pub struct ScancodeStream {
+    _private: (), //field to prevent construction of the struct from outside of the module
+}
+
+impl ScancodeStream {
+    pub fn new() -> Self {
+        Self { _private: () }
+    }
+}
+
+/// Yields the characters typed on the keyboard, with modifiers applied. Keys without a character are skipped.
+pub struct CharStream {
+    scancodes: ScancodeStream,
+    decoder: KeyboardDecoder,
+}
+
+impl CharStream {
+    pub fn new() -> Self {
+        Self {
+            scancodes: ScancodeStream::new(),
+            decoder: KeyboardDecoder::new(),
+        }
+    }
+}
+
+impl Default for CharStream {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Stream for CharStream {
+    type Item = char;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<char>> {
+        loop {
+            let scancode = match Pin::new(&mut self.scancodes).poll_next(cx) {
+                Poll::Ready(Some(scancode)) => scancode,
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            };
+            if let Some(DecodedKey::Unicode(character)) = self.decoder.process(scancode) {
+                return Poll::Ready(Some(character));
+            }
+        }
+    }
+}
+
+/// Yields every decoded key press, including keys without a character such as the arrows.
+pub struct KeyStream {
+    scancodes: ScancodeStream,
+    decoder: KeyboardDecoder,
+}
+
+impl KeyStream {
+    pub fn new() -> Self {
+        Self {
+            scancodes: ScancodeStream::new(),
+            decoder: KeyboardDecoder::new(),
+        }
+    }
+}
+
+impl Default for KeyStream {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Stream for KeyStream {
+    type Item = DecodedKey;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<DecodedKey>> {
+        loop {
+            let scancode = match Pin::new(&mut self.scancodes).poll_next(cx) {
+                Poll::Ready(Some(scancode)) => scancode,
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            };
+            if let Some(key) = self.decoder.process(scancode) {
+                return Poll::Ready(Some(key));
+            }
+        }
+    }
+}
+
+impl Default for ScancodeStream {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Stream for ScancodeStream {
+    type Item = u8;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<u8>> {
+        let queue = SCANCODE_QUEUE
+            .try_get()
+            .expect("scancode queue not initialized");
+
+        //fast path - avoids registering the waker when a scancode is already waiting
+        if let Ok(scancode) = queue.pop() {
+            return Poll::Ready(Some(scancode));
+        }
+
+        WAKER.register(cx.waker());
+        //A scancode may have arrived before the waker was registered
+        match queue.pop() {
+            Ok(scancode) => {
+                WAKER.take();
+                Poll::Ready(Some(scancode))
+            }
+            Err(_) => Poll::Pending,
+        }
+    }
+}
+
 #[derive(Debug, PartialEq)]
 pub enum KeyCode {
     AltLeft = 0,
@@ -130,7 +263,7 @@ pub enum KeyState {
     Up,
     Down,
 }
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub struct KeyEvent {
     pub code: KeyCode,
     pub state: KeyState,
@@ -143,6 +276,7 @@ impl KeyEvent {
     }
 }
 //DecodedKey useful for displaying
+#[derive(Debug, PartialEq)]
 pub enum DecodedKey {
     RawKey(KeyCode),
     Unicode(char),
@@ -258,39 +392,168 @@ fn get_key_code(code: u8) -> Result<KeyCode, ()> {
     }
 }
 
-pub struct Keyboard {
-    lshift: bool,
-    rshift: bool,
-    lctrl: bool,
-    rctrl: bool,
-    numlock: bool,
-    capslock: bool,
-    alt_gr: bool,
+//Keys whose scancode follows the 0xE0 prefix, the release code again has the high bit set
+fn get_extended_key_code(code: u8) -> Result<KeyCode, ()> {
+    match code & 0x7F {
+        0x1C => Ok(KeyCode::NumpadEnter),
+        0x1D => Ok(KeyCode::ControlRight),
+        0x35 => Ok(KeyCode::NumpadSlash),
+        0x38 => Ok(KeyCode::AltRight),
+        0x47 => Ok(KeyCode::Home),
+        0x48 => Ok(KeyCode::ArrowUp),
+        0x49 => Ok(KeyCode::PageUp),
+        0x4B => Ok(KeyCode::ArrowLeft),
+        0x4D => Ok(KeyCode::ArrowRight),
+        0x4F => Ok(KeyCode::End),
+        0x50 => Ok(KeyCode::ArrowDown),
+        0x51 => Ok(KeyCode::PageDown),
+        0x52 => Ok(KeyCode::Insert),
+        0x53 => Ok(KeyCode::Delete),
+        0x5B => Ok(KeyCode::WindowsLeft),
+        0x5C => Ok(KeyCode::WindowsRight),
+        0x5D => Ok(KeyCode::Menus),
+        //0x2A and 0x37 make up print screen, which also sends a fake shift - ignored for now
+        _ => Err(()),
+    }
 }
 
-impl Keyboard {
-    pub fn new() -> Self {
+/// Turns a stream of raw scancode set 1 bytes into key events, including the 0xE0 prefixed extended keys,
+/// and keeps track of the modifier keys to translate them into characters.
+pub struct KeyboardDecoder {
+    extended: bool,
+    keyboard: Keyboard,
+    layout: &'static (dyn KeyboardLayout + Sync),
+}
+
+impl KeyboardDecoder {
+    pub const fn new() -> Self {
+        Self {
+            extended: false,
+            keyboard: Keyboard::new(),
+            layout: &Qwerty,
+        }
+    }
+
+    pub fn modifiers(&self) -> Modifiers {
+        self.keyboard.modifiers()
+    }
+
+    pub fn set_layout(&mut self, layout: &'static (dyn KeyboardLayout + Sync)) {
+        self.layout = layout;
+    }
+
+    /// Like `decode`, but also updates the modifier state and translates key presses into characters.
+    pub fn process(&mut self, scancode: u8) -> Option<DecodedKey> {
+        let extended = self.extended;
+        let event = self.decode(scancode)?;
+        //Only presses of keys that aren't modifiers decode to something
+        let decoded = self.keyboard.process_key_ev(event)?;
+        //Extended keys are the same on every layout, so only regular ones go through the layout table
+        if !extended {
+            let modifiers = self.keyboard.modifiers();
+            if let Some(character) = self.layout.translate(scancode, &modifiers) {
+                return Some(DecodedKey::Unicode(character));
+            }
+        }
+        Some(decoded)
+    }
+
+    /// Feeds the decoder one scancode, returning an event once a full key press or release was read.
+    pub fn decode(&mut self, scancode: u8) -> Option<KeyEvent> {
+        if scancode == 0xE0 {
+            self.extended = true;
+            return None;
+        }
+        let state = match scancode & 0x80 {
+            0 => KeyState::Down,
+            _ => KeyState::Up,
+        };
+        let code = if core::mem::take(&mut self.extended) {
+            get_extended_key_code(scancode)
+        } else {
+            get_key_code(scancode & 0x7F)
+        };
+        code.ok().map(|code| KeyEvent::new(code, state))
+    }
+}
+
+impl Default for KeyboardDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Which modifier keys are currently held, plus the state of the lock keys.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Modifiers {
+    pub lshift: bool,
+    pub rshift: bool,
+    pub lctrl: bool,
+    pub rctrl: bool,
+    pub lalt: bool,
+    pub alt_gr: bool,
+    pub numlock: bool,
+    pub capslock: bool,
+}
+
+impl Modifiers {
+    pub const fn new() -> Self {
         Self {
             lshift: false,
             rshift: false,
             lctrl: false,
             rctrl: false,
+            lalt: false,
+            alt_gr: false,
             numlock: false,
             capslock: false,
-            alt_gr: false,
         }
     }
 
-    const fn is_shifted(&self) -> bool {
+    //Either key counts, so releasing one shift while the other is held keeps shift active
+    pub const fn is_shifted(&self) -> bool {
         self.lshift | self.rshift
     }
 
-    const fn is_ctrl(&self) -> bool {
+    pub const fn is_ctrl(&self) -> bool {
         self.lctrl | self.rctrl
     }
 
+    pub const fn is_alt(&self) -> bool {
+        self.lalt | self.alt_gr
+    }
+
+    /// Whether letters are upper case - caps lock inverts shift instead of overriding it.
+    pub const fn is_caps(&self) -> bool {
+        self.is_shifted() ^ self.capslock
+    }
+}
+
+pub struct Keyboard {
+    modifiers: Modifiers,
+}
+
+impl Keyboard {
+    pub const fn new() -> Self {
+        Self {
+            modifiers: Modifiers::new(),
+        }
+    }
+
+    pub fn modifiers(&self) -> Modifiers {
+        self.modifiers
+    }
+
+    const fn is_shifted(&self) -> bool {
+        self.modifiers.is_shifted()
+    }
+
+    const fn is_ctrl(&self) -> bool {
+        self.modifiers.is_ctrl()
+    }
+
     const fn is_caps(&self) -> bool {
-        (self.lshift | self.rshift) ^ self.capslock
+        self.modifiers.is_caps()
     }
 
     pub fn process_key_ev(&mut self, ev: KeyEvent) -> Option<DecodedKey> {
@@ -300,84 +563,98 @@ impl Keyboard {
                 code: KeyCode::ShiftLeft,
                 state: KeyState::Down,
             } => {
-                self.lshift = true;
+                self.modifiers.lshift = true;
                 None
             }
             KeyEvent {
                 code: KeyCode::ShiftRight,
                 state: KeyState::Down,
             } => {
-                self.rshift = true;
+                self.modifiers.rshift = true;
                 None
             }
             KeyEvent {
                 code: KeyCode::ShiftLeft,
                 state: KeyState::Up,
             } => {
-                self.lshift = false;
+                self.modifiers.lshift = false;
                 None
             }
             KeyEvent {
                 code: KeyCode::ShiftRight,
                 state: KeyState::Up,
             } => {
-                self.rshift = false;
+                self.modifiers.rshift = false;
                 None
             }
             KeyEvent {
                 code: KeyCode::CapsLock,
                 state: KeyState::Down,
             } => {
-                self.capslock = !self.capslock;
+                self.modifiers.capslock = !self.modifiers.capslock;
                 None
             }
             KeyEvent {
                 code: KeyCode::NumpadLock,
                 state: KeyState::Down,
             } => {
-                self.numlock = !self.numlock;
+                self.modifiers.numlock = !self.modifiers.numlock;
                 None
             }
             KeyEvent {
                 code: KeyCode::ControlLeft,
                 state: KeyState::Down,
             } => {
-                self.lctrl = true;
+                self.modifiers.lctrl = true;
                 None
             }
             KeyEvent {
                 code: KeyCode::ControlLeft,
                 state: KeyState::Up,
             } => {
-                self.lctrl = false;
+                self.modifiers.lctrl = false;
                 None
             }
             KeyEvent {
                 code: KeyCode::ControlRight,
                 state: KeyState::Down,
             } => {
-                self.rctrl = true;
+                self.modifiers.rctrl = true;
                 None
             }
             KeyEvent {
                 code: KeyCode::ControlRight,
                 state: KeyState::Up,
             } => {
-                self.rctrl = false;
+                self.modifiers.rctrl = false;
+                None
+            }
+            KeyEvent {
+                code: KeyCode::AltLeft,
+                state: KeyState::Down,
+            } => {
+                self.modifiers.lalt = true;
+                None
+            }
+            KeyEvent {
+                code: KeyCode::AltLeft,
+                state: KeyState::Up,
+            } => {
+                self.modifiers.lalt = false;
                 None
             }
             KeyEvent {
                 code: KeyCode::AltRight,
                 state: KeyState::Down,
             } => {
-                self.alt_gr = true;
+                self.modifiers.alt_gr = true;
                 None
             }
             KeyEvent {
                 code: KeyCode::AltRight,
                 state: KeyState::Up,
             } => {
-                self.alt_gr = false;
+                self.modifiers.alt_gr = false;
                 None
             }
             KeyEvent {
@@ -783,21 +1060,21 @@ impl Keyboard {
             KeyCode::NumpadStar => DecodedKey::Unicode('*'),
             KeyCode::NumpadMinus => DecodedKey::Unicode('-'),
             KeyCode::Numpad7 => {
-                if self.numlock {
+                if self.modifiers.numlock {
                     DecodedKey::Unicode('7')
                 } else {
                     DecodedKey::RawKey(KeyCode::Home)
                 }
             }
             KeyCode::Numpad8 => {
-                if self.numlock {
+                if self.modifiers.numlock {
                     DecodedKey::Unicode('8')
                 } else {
                     DecodedKey::RawKey(KeyCode::ArrowUp)
                 }
             }
             KeyCode::Numpad9 => {
-                if self.numlock {
+                if self.modifiers.numlock {
                     DecodedKey::Unicode('9')
                 } else {
                     DecodedKey::RawKey(KeyCode::PageUp)
@@ -805,7 +1082,7 @@ impl Keyboard {
             }
             KeyCode::NumpadPlus => DecodedKey::Unicode('+'),
             KeyCode::Numpad4 => {
-                if self.numlock {
+                if self.modifiers.numlock {
                     DecodedKey::Unicode('4')
                 } else {
                     DecodedKey::RawKey(KeyCode::ArrowLeft)
@@ -813,42 +1090,42 @@ impl Keyboard {
             }
             KeyCode::Numpad5 => DecodedKey::Unicode('5'),
             KeyCode::Numpad6 => {
-                if self.numlock {
+                if self.modifiers.numlock {
                     DecodedKey::Unicode('6')
                 } else {
                     DecodedKey::RawKey(KeyCode::ArrowRight)
                 }
             }
             KeyCode::Numpad1 => {
-                if self.numlock {
+                if self.modifiers.numlock {
                     DecodedKey::Unicode('1')
                 } else {
                     DecodedKey::RawKey(KeyCode::End)
                 }
             }
             KeyCode::Numpad2 => {
-                if self.numlock {
+                if self.modifiers.numlock {
                     DecodedKey::Unicode('2')
                 } else {
                     DecodedKey::RawKey(KeyCode::ArrowDown)
                 }
             }
             KeyCode::Numpad3 => {
-                if self.numlock {
+                if self.modifiers.numlock {
                     DecodedKey::Unicode('3')
                 } else {
                     DecodedKey::RawKey(KeyCode::PageDown)
                 }
             }
             KeyCode::Numpad0 => {
-                if self.numlock {
+                if self.modifiers.numlock {
                     DecodedKey::Unicode('0')
                 } else {
                     DecodedKey::RawKey(KeyCode::Insert)
                 }
             }
             KeyCode::NumpadPeriod => {
-                if self.numlock {
+                if self.modifiers.numlock {
                     DecodedKey::Unicode('.')
                 } else {
                     DecodedKey::Unicode(127.into())
@@ -859,3 +1136,95 @@ impl Keyboard {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{
+        add_scancode, DecodedKey, KeyCode, KeyEvent, KeyState, KeyboardDecoder, ScancodeStream,
+    };
+    use crate::executor::block_on;
+    use crate::io::Dvorak;
+    use futures_util::stream::StreamExt;
+
+    #[test_case]
+    fn scancode_stream_drains_queue() {
+        let mut stream = ScancodeStream::new();
+        for scancode in [0x1E, 0x9E, 0x1C] {
+            add_scancode(scancode);
+        }
+        for scancode in [0x1E, 0x9E, 0x1C] {
+            assert_eq!(block_on(stream.next()), Some(scancode));
+        }
+    }
+
+    #[test_case]
+    fn decoder_press_release() {
+        let mut decoder = KeyboardDecoder::new();
+        assert_eq!(
+            decoder.decode(0x1E),
+            Some(KeyEvent::new(KeyCode::A, KeyState::Down))
+        );
+        assert_eq!(
+            decoder.decode(0x9E),
+            Some(KeyEvent::new(KeyCode::A, KeyState::Up))
+        );
+    }
+
+    #[test_case]
+    fn decoder_extended_keys() {
+        let mut decoder = KeyboardDecoder::new();
+        //The prefix alone doesn't produce an event
+        assert_eq!(decoder.decode(0xE0), None);
+        assert_eq!(
+            decoder.decode(0x48),
+            Some(KeyEvent::new(KeyCode::ArrowUp, KeyState::Down))
+        );
+        //Same code without the prefix is the numpad key
+        assert_eq!(
+            decoder.decode(0x48),
+            Some(KeyEvent::new(KeyCode::Numpad8, KeyState::Down))
+        );
+        decoder.decode(0xE0);
+        assert_eq!(
+            decoder.decode(0x9D),
+            Some(KeyEvent::new(KeyCode::ControlRight, KeyState::Up))
+        );
+    }
+
+    #[test_case]
+    fn decoder_modifiers() {
+        let mut decoder = KeyboardDecoder::new();
+        //Shift+a and Shift+1
+        decoder.process(0x2A);
+        assert_eq!(decoder.process(0x1E), Some(DecodedKey::Unicode('A')));
+        assert_eq!(decoder.process(0x02), Some(DecodedKey::Unicode('!')));
+
+        //Holding both shifts and releasing one keeps shift active
+        decoder.process(0x36);
+        decoder.process(0xAA);
+        assert!(decoder.modifiers().is_shifted());
+        assert_eq!(decoder.process(0x1E), Some(DecodedKey::Unicode('A')));
+        decoder.process(0xB6);
+        assert!(!decoder.modifiers().is_shifted());
+        assert_eq!(decoder.process(0x1E), Some(DecodedKey::Unicode('a')));
+
+        //Caps lock only changes letters, and shift inverts it again
+        decoder.process(0x3A);
+        decoder.process(0xBA);
+        assert_eq!(decoder.process(0x1E), Some(DecodedKey::Unicode('A')));
+        assert_eq!(decoder.process(0x02), Some(DecodedKey::Unicode('1')));
+        decoder.process(0x2A);
+        assert_eq!(decoder.process(0x1E), Some(DecodedKey::Unicode('a')));
+    }
+
+    #[test_case]
+    fn decoder_set_layout() {
+        let mut decoder = KeyboardDecoder::new();
+        assert_eq!(decoder.process(0x10), Some(DecodedKey::Unicode('q')));
+        decoder.set_layout(&Dvorak);
+        //US Dvorak has ' on the key that's q on QWERTY - ';' is on QWERTY's z instead
+        assert_eq!(decoder.process(0x10), Some(DecodedKey::Unicode('\'')));
+        //Keys outside the layout table still decode
+        assert_eq!(decoder.process(0x1C), Some(DecodedKey::Unicode('\n')));
+    }
+}
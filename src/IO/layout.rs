@@ -0,0 +1,238 @@
+use super::Modifiers;
+
+/// Maps the physical keys of scancode set 1 to characters.
+///
+/// Layouts only provide their base table, the handling of Shift, Caps Lock and Ctrl is shared.
+pub trait KeyboardLayout {
+    /// (scancode, character, character with shift) for every key that produces a printable character.
+    fn keys(&self) -> &'static [(u8, char, char)];
+
+    fn translate(&self, scancode: u8, modifiers: &Modifiers) -> Option<char> {
+        let &(_, normal, shifted) = self.keys().iter().find(|(code, _, _)| *code == scancode)?;
+        if normal.is_ascii_alphabetic() && modifiers.is_ctrl() {
+            //Ctrl+letter gives the matching control character, e.g. Ctrl+C is 0x03
+            return Some(((normal as u8) & 0x1F).into());
+        }
+        //Caps Lock only affects letters - on AZERTY that includes the accented ones on the digit row
+        let use_shifted = if normal.is_alphabetic() {
+            modifiers.is_caps()
+        } else {
+            modifiers.is_shifted()
+        };
+        Some(if use_shifted { shifted } else { normal })
+    }
+}
+
+/// US QWERTY.
+pub struct Qwerty;
+/// US Dvorak.
+pub struct Dvorak;
+/// French AZERTY.
+pub struct AzertyFr;
+
+impl KeyboardLayout for Qwerty {
+    fn keys(&self) -> &'static [(u8, char, char)] {
+        QWERTY_KEYS
+    }
+}
+
+impl KeyboardLayout for Dvorak {
+    fn keys(&self) -> &'static [(u8, char, char)] {
+        DVORAK_KEYS
+    }
+}
+
+impl KeyboardLayout for AzertyFr {
+    fn keys(&self) -> &'static [(u8, char, char)] {
+        AZERTY_FR_KEYS
+    }
+}
+
+const QWERTY_KEYS: &[(u8, char, char)] = &[
+    (0x29, '`', '~'),
+    (0x02, '1', '!'),
+    (0x03, '2', '@'),
+    (0x04, '3', '#'),
+    (0x05, '4', '$'),
+    (0x06, '5', '%'),
+    (0x07, '6', '^'),
+    (0x08, '7', '&'),
+    (0x09, '8', '*'),
+    (0x0A, '9', '('),
+    (0x0B, '0', ')'),
+    (0x0C, '-', '_'),
+    (0x0D, '=', '+'),
+    (0x10, 'q', 'Q'),
+    (0x11, 'w', 'W'),
+    (0x12, 'e', 'E'),
+    (0x13, 'r', 'R'),
+    (0x14, 't', 'T'),
+    (0x15, 'y', 'Y'),
+    (0x16, 'u', 'U'),
+    (0x17, 'i', 'I'),
+    (0x18, 'o', 'O'),
+    (0x19, 'p', 'P'),
+    (0x1A, '[', '{'),
+    (0x1B, ']', '}'),
+    (0x1E, 'a', 'A'),
+    (0x1F, 's', 'S'),
+    (0x20, 'd', 'D'),
+    (0x21, 'f', 'F'),
+    (0x22, 'g', 'G'),
+    (0x23, 'h', 'H'),
+    (0x24, 'j', 'J'),
+    (0x25, 'k', 'K'),
+    (0x26, 'l', 'L'),
+    (0x27, ';', ':'),
+    (0x28, '\'', '"'),
+    (0x2B, '\\', '|'),
+    (0x2C, 'z', 'Z'),
+    (0x2D, 'x', 'X'),
+    (0x2E, 'c', 'C'),
+    (0x2F, 'v', 'V'),
+    (0x30, 'b', 'B'),
+    (0x31, 'n', 'N'),
+    (0x32, 'm', 'M'),
+    (0x33, ',', '<'),
+    (0x34, '.', '>'),
+    (0x35, '/', '?'),
+    (0x39, ' ', ' '),
+];
+
+const DVORAK_KEYS: &[(u8, char, char)] = &[
+    (0x29, '`', '~'),
+    (0x02, '1', '!'),
+    (0x03, '2', '@'),
+    (0x04, '3', '#'),
+    (0x05, '4', '$'),
+    (0x06, '5', '%'),
+    (0x07, '6', '^'),
+    (0x08, '7', '&'),
+    (0x09, '8', '*'),
+    (0x0A, '9', '('),
+    (0x0B, '0', ')'),
+    (0x0C, '[', '{'),
+    (0x0D, ']', '}'),
+    (0x10, '\'', '"'),
+    (0x11, ',', '<'),
+    (0x12, '.', '>'),
+    (0x13, 'p', 'P'),
+    (0x14, 'y', 'Y'),
+    (0x15, 'f', 'F'),
+    (0x16, 'g', 'G'),
+    (0x17, 'c', 'C'),
+    (0x18, 'r', 'R'),
+    (0x19, 'l', 'L'),
+    (0x1A, '/', '?'),
+    (0x1B, '=', '+'),
+    (0x1E, 'a', 'A'),
+    (0x1F, 'o', 'O'),
+    (0x20, 'e', 'E'),
+    (0x21, 'u', 'U'),
+    (0x22, 'i', 'I'),
+    (0x23, 'd', 'D'),
+    (0x24, 'h', 'H'),
+    (0x25, 't', 'T'),
+    (0x26, 'n', 'N'),
+    (0x27, 's', 'S'),
+    (0x28, '-', '_'),
+    (0x2B, '\\', '|'),
+    (0x2C, ';', ':'),
+    (0x2D, 'q', 'Q'),
+    (0x2E, 'j', 'J'),
+    (0x2F, 'k', 'K'),
+    (0x30, 'x', 'X'),
+    (0x31, 'b', 'B'),
+    (0x32, 'm', 'M'),
+    (0x33, 'w', 'W'),
+    (0x34, 'v', 'V'),
+    (0x35, 'z', 'Z'),
+    (0x39, ' ', ' '),
+];
+
+const AZERTY_FR_KEYS: &[(u8, char, char)] = &[
+    (0x29, '²', '²'),
+    (0x02, '&', '1'),
+    (0x03, 'é', '2'),
+    (0x04, '"', '3'),
+    (0x05, '\'', '4'),
+    (0x06, '(', '5'),
+    (0x07, '-', '6'),
+    (0x08, 'è', '7'),
+    (0x09, '_', '8'),
+    (0x0A, 'ç', '9'),
+    (0x0B, 'à', '0'),
+    (0x0C, ')', '°'),
+    (0x0D, '=', '+'),
+    (0x10, 'a', 'A'),
+    (0x11, 'z', 'Z'),
+    (0x12, 'e', 'E'),
+    (0x13, 'r', 'R'),
+    (0x14, 't', 'T'),
+    (0x15, 'y', 'Y'),
+    (0x16, 'u', 'U'),
+    (0x17, 'i', 'I'),
+    (0x18, 'o', 'O'),
+    (0x19, 'p', 'P'),
+    (0x1A, '^', '¨'),
+    (0x1B, '$', '£'),
+    (0x1E, 'q', 'Q'),
+    (0x1F, 's', 'S'),
+    (0x20, 'd', 'D'),
+    (0x21, 'f', 'F'),
+    (0x22, 'g', 'G'),
+    (0x23, 'h', 'H'),
+    (0x24, 'j', 'J'),
+    (0x25, 'k', 'K'),
+    (0x26, 'l', 'L'),
+    (0x27, 'm', 'M'),
+    (0x28, 'ù', '%'),
+    (0x2B, '*', 'µ'),
+    (0x2C, 'w', 'W'),
+    (0x2D, 'x', 'X'),
+    (0x2E, 'c', 'C'),
+    (0x2F, 'v', 'V'),
+    (0x30, 'b', 'B'),
+    (0x31, 'n', 'N'),
+    (0x32, ',', '?'),
+    (0x33, ';', '.'),
+    (0x34, ':', '/'),
+    (0x35, '!', '§'),
+    (0x39, ' ', ' '),
+];
+
+#[cfg(test)]
+mod test {
+    use super::{AzertyFr, Dvorak, KeyboardLayout, Qwerty};
+    use crate::io::Modifiers;
+
+    #[test_case]
+    fn qwerty_q_is_dvorak_quote() {
+        let modifiers = Modifiers::new();
+        //0x10 is the key labelled 'q' on QWERTY
+        assert_eq!(Qwerty.translate(0x10, &modifiers), Some('q'));
+        assert_eq!(Dvorak.translate(0x10, &modifiers), Some('\''));
+        assert_eq!(AzertyFr.translate(0x10, &modifiers), Some('a'));
+    }
+
+    #[test_case]
+    fn layouts_share_modifiers() {
+        let modifiers = Modifiers {
+            lshift: true,
+            ..Modifiers::new()
+        };
+        //QWERTY's 'z' key is ';' on Dvorak
+        assert_eq!(Dvorak.translate(0x2C, &modifiers), Some(':'));
+        assert_eq!(AzertyFr.translate(0x03, &modifiers), Some('2'));
+        assert_eq!(
+            Qwerty.translate(
+                0x1E,
+                &Modifiers {
+                    lctrl: true,
+                    ..modifiers
+                }
+            ),
+            Some('\u{1}')
+        );
+    }
+}
@@ -0,0 +1,261 @@
+use super::{DecodedKey, KeyCode};
+use crate::render::{CONSOLE, FRAMEBUFFER};
+use alloc::{collections::VecDeque, string::String};
+use futures_util::stream::{Stream, StreamExt};
+
+const BACKSPACE: char = '\x08';
+
+/// Reads lines of typed characters, echoing them to the render console.
+pub struct LineReader {
+    max_len: usize, //characters typed once a line is this long are dropped
+}
+
+impl LineReader {
+    pub const fn new(max_len: usize) -> Self {
+        Self { max_len }
+    }
+
+    /// Collects characters until Enter is pressed and returns the line without the newline.
+    /// Backspace removes the last character. Returns what was typed so far if the stream ends.
+    pub async fn read_line<S: Stream<Item = char> + Unpin>(&self, chars: &mut S) -> String {
+        let mut line = String::new();
+        let mut len = 0;
+        while let Some(character) = chars.next().await {
+            match character {
+                '\n' => {
+                    echo('\n');
+                    break;
+                }
+                BACKSPACE => {
+                    //Nothing to erase on an empty line
+                    if line.pop().is_some() {
+                        len -= 1;
+                        echo(BACKSPACE);
+                    }
+                }
+                character if len < self.max_len => {
+                    line.push(character);
+                    len += 1;
+                    echo(character);
+                }
+                _ => {}
+            }
+        }
+        line
+    }
+}
+
+/// Previously entered lines, oldest dropped first once `capacity` is reached.
+pub struct History {
+    entries: VecDeque<String>,
+    capacity: usize,
+    position: Option<usize>, //entry being shown, counted back from the newest
+    draft: String,           //what was typed before scrolling back, restored past the newest entry
+}
+
+impl History {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: VecDeque::with_capacity(capacity),
+            capacity,
+            position: None,
+            draft: String::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Adds an entered line and resets navigation. Empty lines aren't kept.
+    pub fn push(&mut self, line: &str) {
+        self.position = None;
+        if line.is_empty() || self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(String::from(line));
+    }
+
+    /// Steps back to an older line, `None` if there is nothing older.
+    pub fn older(&mut self, current: &str) -> Option<&str> {
+        let next = match self.position {
+            None if self.entries.is_empty() => return None,
+            None => {
+                self.draft = String::from(current);
+                0
+            }
+            Some(position) if position + 1 < self.entries.len() => position + 1,
+            Some(_) => return None,
+        };
+        self.position = Some(next);
+        Some(&self.entries[self.entries.len() - 1 - next])
+    }
+
+    /// Steps forward to a newer line, ending with the draft. `None` if not scrolled back at all.
+    pub fn newer(&mut self) -> Option<&str> {
+        match self.position? {
+            0 => {
+                self.position = None;
+                Some(&self.draft)
+            }
+            position => {
+                self.position = Some(position - 1);
+                Some(&self.entries[self.entries.len() - position])
+            }
+        }
+    }
+}
+
+impl LineReader {
+    /// Like `read_line`, but reads decoded keys so the arrow keys can recall lines from `history`.
+    ///The entered line is added to the history
+    pub async fn read_line_with_history<S: Stream<Item = DecodedKey> + Unpin>(
+        &self,
+        keys: &mut S,
+        history: &mut History,
+    ) -> String {
+        let mut line = String::new();
+        while let Some(key) = keys.next().await {
+            let recalled = match key {
+                DecodedKey::Unicode('\n') => {
+                    echo('\n');
+                    break;
+                }
+                DecodedKey::Unicode(BACKSPACE) => {
+                    if line.pop().is_some() {
+                        echo(BACKSPACE);
+                    }
+                    None
+                }
+                DecodedKey::Unicode(character) if line.chars().count() < self.max_len => {
+                    line.push(character);
+                    echo(character);
+                    None
+                }
+                DecodedKey::RawKey(KeyCode::ArrowUp) => history.older(&line).map(String::from),
+                DecodedKey::RawKey(KeyCode::ArrowDown) => history.newer().map(String::from),
+                _ => None,
+            };
+            if let Some(recalled) = recalled {
+                //erase what's on screen and show the recalled line instead
+                for _ in line.chars() {
+                    echo(BACKSPACE);
+                }
+                line = recalled.chars().take(self.max_len).collect();
+                for character in line.chars() {
+                    echo(character);
+                }
+            }
+        }
+        history.push(&line);
+        line
+    }
+}
+
+fn echo(character: char) {
+    //Same lock order as Console's fmt::Write impl
+    let mut console = CONSOLE.lock();
+    let mut framebuffer = FRAMEBUFFER.lock();
+    let mut buffer = [0; 4];
+    console.write_to(&mut framebuffer, character.encode_utf8(&mut buffer));
+    framebuffer.present_dirty();
+}
+
+#[cfg(test)]
+mod test {
+    use super::{DecodedKey, History, KeyCode, LineReader};
+    use crate::executor::block_on;
+    use alloc::vec::Vec;
+    use futures_util::stream;
+
+    #[test_case]
+    fn read_line_with_backspace() {
+        let mut chars = stream::iter("hi\x08x\n".chars());
+        assert_eq!(block_on(LineReader::new(80).read_line(&mut chars)), "hx");
+    }
+
+    #[test_case]
+    fn read_line_edge_cases() {
+        //Backspace on an empty line does nothing and the line is capped at 3 characters
+        let mut chars = stream::iter("\x08abcdef\nrest".chars());
+        assert_eq!(block_on(LineReader::new(3).read_line(&mut chars)), "abc");
+        //The remaining input starts the next line
+        assert_eq!(block_on(LineReader::new(3).read_line(&mut chars)), "res");
+    }
+
+    fn keys(input: &str) -> Vec<DecodedKey> {
+        //'^' and 'v' stand in for the up and down arrows
+        input
+            .chars()
+            .map(|character| match character {
+                '^' => DecodedKey::RawKey(KeyCode::ArrowUp),
+                'v' => DecodedKey::RawKey(KeyCode::ArrowDown),
+                character => DecodedKey::Unicode(character),
+            })
+            .collect()
+    }
+
+    #[test_case]
+    fn history_navigation() {
+        let reader = LineReader::new(80);
+        let mut history = History::new(8);
+        let mut input = stream::iter(keys("ls\necho\n"));
+        assert_eq!(
+            block_on(reader.read_line_with_history(&mut input, &mut history)),
+            "ls"
+        );
+        assert_eq!(
+            block_on(reader.read_line_with_history(&mut input, &mut history)),
+            "echo"
+        );
+
+        //Up twice reaches "ls", past the oldest entry stays put
+        let mut input = stream::iter(keys("^^^\n"));
+        assert_eq!(
+            block_on(reader.read_line_with_history(&mut input, &mut history)),
+            "ls"
+        );
+
+        //Down past the newest entry brings back what was being typed
+        let mut input = stream::iter(keys("ab^vv\n"));
+        assert_eq!(
+            block_on(reader.read_line_with_history(&mut input, &mut history)),
+            "ab"
+        );
+
+        //An edited recall is entered as a new line
+        let mut input = stream::iter(keys("^\x08\x08x\n"));
+        assert_eq!(
+            block_on(reader.read_line_with_history(&mut input, &mut history)),
+            "x"
+        );
+        assert_eq!(history.len(), 5);
+        assert_eq!(history.older(""), Some("x"));
+    }
+
+    #[test_case]
+    fn history_edge_cases() {
+        let mut history = History::new(2);
+        //nothing to recall yet
+        assert_eq!(history.older("typed"), None);
+        assert_eq!(history.newer(), None);
+
+        history.push("a");
+        history.push("");
+        history.push("b");
+        history.push("c");
+        //empty lines are skipped and the oldest entry is dropped at capacity
+        assert_eq!(history.len(), 2);
+        assert_eq!(history.older(""), Some("c"));
+        assert_eq!(history.older(""), Some("b"));
+        assert_eq!(history.older(""), None);
+        assert_eq!(history.newer(), Some("c"));
+    }
+}
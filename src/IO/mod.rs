@@ -1,11 +1,21 @@
 mod keyboard;
+mod layout;
+mod line_reader;
 mod mouse;
 mod serial;
 
 pub use keyboard::{
-    add_scancode, get_key_ev, KeyCode, KeyEvent, KeyState, Keyboard, SCANCODE_QUEUE,
+    add_scancode, get_key_ev, CharStream, DecodedKey, KeyCode, KeyEvent, KeyState, KeyStream,
+    Keyboard, KeyboardDecoder, Modifiers, ScancodeStream, SCANCODE_QUEUE,
 };
+pub use layout::{AzertyFr, Dvorak, KeyboardLayout, Qwerty};
+pub use line_reader::{History, LineReader};
 
 pub use mouse::init_mouse;
-pub use mouse::MOUSE;
-pub use serial::_print;
+pub use mouse::{
+    decode_packet, mouse_interrupt, MouseFlags, MouseState, MouseStream, MOUSE, MOUSE_QUEUE,
+};
+pub use serial::{
+    _print, init_serial, serial_interrupt, serial_read_byte, InvalidBaudRate, SerialPort,
+    SerialStream, COM1, COM2, SERIAL_QUEUE,
+};
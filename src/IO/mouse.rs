@@ -1,5 +1,12 @@
 use bitflags::bitflags;
-use conquer_once::spin::Lazy;
+use conquer_once::spin::{Lazy, OnceCell};
+use core::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+use crossbeam_queue::ArrayQueue;
+use futures_util::stream::Stream;
+use futures_util::task::AtomicWaker;
 use spinning_top::Spinlock;
 use x86_64::instructions::port::Port;
 
@@ -8,6 +15,8 @@ const DATA_PORT_ADDRESS: u16 = 0x60;
 const GET_STATUS_BYTE: u8 = 0x20;
 const SET_STATUS_BYTE: u8 = 0x60;
 const SET_DEFAULTS: u8 = 0xF6;
+const SET_SAMPLE_RATE: u8 = 0xF3;
+const SAMPLE_RATE: u8 = 100; //packets per second
 const ENABLE_PACKET_STREAMING: u8 = 0xF4;
 
 pub static MOUSE: Lazy<Spinlock<Mouse>> = Lazy::new(|| Spinlock::new(Mouse::new()));
@@ -38,6 +47,8 @@ pub fn init_mouse() {
         data_port.write(status & 0xDF);
 
         send_command(&mut command_port, &mut data_port, SET_DEFAULTS).unwrap();
+        send_command(&mut command_port, &mut data_port, SET_SAMPLE_RATE).unwrap();
+        send_command(&mut command_port, &mut data_port, SAMPLE_RATE).unwrap();
         send_command(&mut command_port, &mut data_port, ENABLE_PACKET_STREAMING).unwrap();
     }
 }
@@ -72,57 +83,83 @@ bitflags! {
     }
 }
 
-/// A snapshot of the mouse flags, x delta and y delta.
-#[derive(Debug, Copy, Clone, Default)]
+/// One decoded movement packet.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
 pub struct MouseState {
-    flags: MouseFlags,
-    x: i16,
-    y: i16,
+    pub dx: i16,
+    pub dy: i16,             //positive is up
+    pub buttons: MouseFlags, //only the button bits
 }
-#[allow(dead_code)]
+
 impl MouseState {
     /// Returns true if the left mouse button is currently down.
     pub fn left_button_down(&self) -> bool {
-        self.flags.contains(MouseFlags::LEFT_BUTTON)
+        self.buttons.contains(MouseFlags::LEFT_BUTTON)
     }
 
     /// Returns true if the left mouse button is currently up.
     pub fn left_button_up(&self) -> bool {
-        !self.flags.contains(MouseFlags::LEFT_BUTTON)
+        !self.buttons.contains(MouseFlags::LEFT_BUTTON)
     }
 
     /// Returns true if the right mouse button is currently down.
     pub fn right_button_down(&self) -> bool {
-        self.flags.contains(MouseFlags::RIGHT_BUTTON)
+        self.buttons.contains(MouseFlags::RIGHT_BUTTON)
     }
 
     /// Returns true if the right mouse button is currently up.
     pub fn right_button_up(&self) -> bool {
-        !self.flags.contains(MouseFlags::RIGHT_BUTTON)
+        !self.buttons.contains(MouseFlags::RIGHT_BUTTON)
     }
 
     /// Returns the x delta of the mouse state.
     pub fn get_x(&self) -> i16 {
-        self.x
+        self.dx
     }
 
     /// Returns the y delta of the mouse state.
     pub fn get_y(&self) -> i16 {
-        self.y
+        self.dy
     }
 }
 
+//The deltas are 9 bit two's complement, with the sign bit in the status byte
+fn delta(value: u8, negative: bool) -> i16 {
+    if negative {
+        value as i16 - 0x100
+    } else {
+        value as i16
+    }
+}
+
+/// Decodes a 3 byte packet. None if it's misaligned (the always-one bit is clear) or either delta
+/// overflowed, in which case the deltas are meaningless.
+pub fn decode_packet(packet: [u8; 3]) -> Option<MouseState> {
+    let flags = MouseFlags::from_bits_truncate(packet[0]);
+    if !flags.contains(MouseFlags::ALWAYS_ONE)
+        || flags.intersects(MouseFlags::X_OVERFLOW | MouseFlags::Y_OVERFLOW)
+    {
+        return None;
+    }
+    Some(MouseState {
+        dx: delta(packet[1], flags.contains(MouseFlags::X_SIGN)),
+        dy: delta(packet[2], flags.contains(MouseFlags::Y_SIGN)),
+        buttons: flags
+            & (MouseFlags::LEFT_BUTTON | MouseFlags::RIGHT_BUTTON | MouseFlags::MIDDLE_BUTTON),
+    })
+}
+
 pub struct Mouse {
-    current_packet: u8,
-    current_state: MouseState,
+    packet: [u8; 3],
+    received: usize,
     completed_state: MouseState,
 }
 
 impl Mouse {
     pub fn new() -> Self {
         Self {
-            current_packet: 0,
-            current_state: MouseState::default(),
+            packet: [0; 3],
+            received: 0,
             completed_state: MouseState::default(),
         }
     }
@@ -133,46 +170,118 @@ impl Mouse {
         res
     }
 
-    pub fn process_packet(&mut self, packet: u8) {
-        match self.current_packet {
-            0 => {
-                let flags = MouseFlags::from_bits_truncate(packet);
-                if !flags.contains(MouseFlags::ALWAYS_ONE) {
-                    return;
-                }
-                self.current_state.flags = flags;
-            }
-            1 => self.process_x_movement(packet),
-            2 => {
-                self.process_y_movement(packet);
-                self.completed_state = self.current_state;
-            }
-            _ => unreachable!(),
+    /// Feeds in one byte from the mouse, returning the state once a whole valid packet is in.
+    pub fn process_packet(&mut self, byte: u8) -> Option<MouseState> {
+        //Every packet starts with the always-one bit set, so a byte without it can't be the start
+        //of one - dropping it gets back in sync after a lost byte
+        if self.received == 0
+            && !MouseFlags::from_bits_truncate(byte).contains(MouseFlags::ALWAYS_ONE)
+        {
+            return None;
         }
-        self.current_packet = (self.current_packet + 1) % 3;
+        self.packet[self.received] = byte;
+        self.received += 1;
+        if self.received < 3 {
+            return None;
+        }
+        self.received = 0;
+        let state = decode_packet(self.packet)?;
+        self.completed_state = state;
+        Some(state)
+    }
+}
+
+impl Default for Mouse {
+    fn default() -> Self {
+        Self::new()
     }
+}
+
+pub static MOUSE_QUEUE: OnceCell<ArrayQueue<MouseState>> = OnceCell::uninit();
+static WAKER: AtomicWaker = AtomicWaker::new();
 
-    fn process_x_movement(&mut self, packet: u8) {
-        if !self.current_state.flags.contains(MouseFlags::X_OVERFLOW) {
-            self.current_state.x = if self.current_state.flags.contains(MouseFlags::X_SIGN) {
-                self.sign_extend(packet)
-            } else {
-                packet as i16
-            };
+/// Called by the mouse interrupt handler with the byte it read - must not block or allocate.
+pub fn mouse_interrupt(byte: u8) {
+    if let Some(state) = MOUSE.lock().process_packet(byte) {
+        //Movement is only interesting while it's fresh, so a full queue just drops it
+        if let Ok(queue) = MOUSE_QUEUE.try_get() {
+            if queue.push(state).is_ok() {
+                WAKER.wake();
+            }
         }
     }
+}
+
+/// Asynchronously yields each packet the mouse sends.
+pub struct MouseStream {
+    _private: (),
+}
+
+impl MouseStream {
+    pub fn new() -> Self {
+        Self { _private: () }
+    }
+}
+
+impl Default for MouseStream {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
-    fn process_y_movement(&mut self, packet: u8) {
-        if !self.current_state.flags.contains(MouseFlags::Y_OVERFLOW) {
-            self.current_state.y = if self.current_state.flags.contains(MouseFlags::Y_SIGN) {
-                self.sign_extend(packet)
-            } else {
-                packet as i16
-            };
+impl Stream for MouseStream {
+    type Item = MouseState;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<MouseState>> {
+        let queue = MOUSE_QUEUE.try_get().expect("mouse queue not initialized");
+        if let Ok(state) = queue.pop() {
+            return Poll::Ready(Some(state));
+        }
+
+        WAKER.register(cx.waker());
+        //A packet may have arrived before the waker was registered
+        match queue.pop() {
+            Ok(state) => {
+                WAKER.take();
+                Poll::Ready(Some(state))
+            }
+            Err(_) => Poll::Pending,
         }
     }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test_case]
+    fn decode_known_packets() {
+        //Left button, moved right 5 and down 3
+        assert_eq!(
+            decode_packet([0b0010_1001, 5, 0xFD]),
+            Some(MouseState {
+                dx: 5,
+                dy: -3,
+                buttons: MouseFlags::LEFT_BUTTON,
+            })
+        );
+        //Largest moves either way
+        let state = decode_packet([0b0001_1000, 0x00, 0xFF]).unwrap();
+        assert_eq!((state.dx, state.dy), (-256, 255));
+        //Overflow and a missing always-one bit are discarded
+        assert_eq!(decode_packet([0b0100_1000, 1, 1]), None);
+        assert_eq!(decode_packet([0b0000_0000, 1, 1]), None);
+    }
 
-    fn sign_extend(&self, packet: u8) -> i16 {
-        ((packet as u16) | 0xFF00) as i16
+    #[test_case]
+    fn resync_after_lost_byte() {
+        let mut mouse = Mouse::new();
+        //A stray delta byte without the always-one bit is skipped, then a whole packet decodes
+        assert_eq!(mouse.process_packet(0x05), None);
+        assert_eq!(mouse.process_packet(0b0000_1010), None);
+        assert_eq!(mouse.process_packet(2), None);
+        let state = mouse.process_packet(1).unwrap();
+        assert!(state.right_button_down());
+        assert_eq!((state.dx, state.dy), (2, 1));
     }
 }
@@ -1,7 +1,16 @@
 use bitflags::bitflags;
-use core::fmt::{Result, Write};
+use conquer_once::spin::OnceCell;
+use core::fmt::{self, Result, Write};
+use core::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+use crossbeam_queue::ArrayQueue;
+use futures_util::stream::Stream;
+use futures_util::task::AtomicWaker;
 use lazy_static::lazy_static;
 use spin::Mutex;
+use x86_64::instructions::interrupts;
 use x86_64::instructions::port::{Port, PortReadOnly, PortWriteOnly};
 
 bitflags! {
@@ -14,6 +23,20 @@ bitflags! {
     }
 }
 
+//Frequency the divisor latch divides down from
+const BASE_BAUD: u32 = 115200;
+const DLAB: u8 = 0x80;
+
+/// Returned by `SerialPort::set_baud` for rates the UART can't produce exactly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidBaudRate(pub u32);
+
+impl fmt::Display for InvalidBaudRate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} baud does not evenly divide {}", self.0, BASE_BAUD)
+    }
+}
+
 macro_rules! wait_for {
     ($cond:expr) => {
         while !$cond {
@@ -24,22 +47,23 @@ macro_rules! wait_for {
 
 pub struct SerialPort {
     data: Port<u8>,
-    int_en: PortWriteOnly<u8>,
+    int_en: Port<u8>,
     fifo_ctrl: PortWriteOnly<u8>,
-    line_ctrl: PortWriteOnly<u8>,
-    modem_ctrl: PortWriteOnly<u8>,
+    line_ctrl: Port<u8>,
+    modem_ctrl: Port<u8>,
     line_sts: PortReadOnly<u8>,
 }
 
 impl SerialPort {
+    /// # Safety
     ///Caller must ensure that base points to a valid serial port device
     pub const unsafe fn new(base: u16) -> Self {
         Self {
             data: Port::new(base),
-            int_en: PortWriteOnly::new(base + 1),
+            int_en: Port::new(base + 1),
             fifo_ctrl: PortWriteOnly::new(base + 2),
-            line_ctrl: PortWriteOnly::new(base + 3),
-            modem_ctrl: PortWriteOnly::new(base + 4),
+            line_ctrl: Port::new(base + 3),
+            modem_ctrl: Port::new(base + 4),
             line_sts: PortReadOnly::new(base + 5),
         }
     }
@@ -73,6 +97,52 @@ impl SerialPort {
         }
     }
 
+    /// Sets the baud rate by writing the divisor latch, leaving the line settings as they were.
+    pub fn set_baud(&mut self, rate: u32) -> core::result::Result<(), InvalidBaudRate> {
+        if rate == 0 || !BASE_BAUD.is_multiple_of(rate) || BASE_BAUD / rate > u16::MAX as u32 {
+            return Err(InvalidBaudRate(rate));
+        }
+        let divisor = (BASE_BAUD / rate) as u16;
+        unsafe {
+            let line_ctrl = self.line_ctrl.read();
+            self.line_ctrl.write(line_ctrl | DLAB);
+            self.data.write(divisor as u8);
+            self.int_en.write((divisor >> 8) as u8);
+            self.line_ctrl.write(line_ctrl);
+        }
+        Ok(())
+    }
+
+    /// Reads back the current divisor latch value.
+    pub fn divisor(&mut self) -> u16 {
+        unsafe {
+            let line_ctrl = self.line_ctrl.read();
+            self.line_ctrl.write(line_ctrl | DLAB);
+            let divisor = self.data.read() as u16 | (self.int_en.read() as u16) << 8;
+            self.line_ctrl.write(line_ctrl);
+            divisor
+        }
+    }
+
+    /// Enables or disables the "received data available" interrupt.
+    pub fn set_receive_interrupt(&mut self, enabled: bool) {
+        unsafe {
+            self.int_en.write(enabled as u8);
+        }
+    }
+
+    /// Routes transmitted bytes straight back to the receiver, used to test a port without a host attached.
+    pub fn set_loopback(&mut self, enabled: bool) {
+        unsafe {
+            let modem_ctrl = self.modem_ctrl.read();
+            if enabled {
+                self.modem_ctrl.write(modem_ctrl | 0x10);
+            } else {
+                self.modem_ctrl.write(modem_ctrl & !0x10);
+            }
+        }
+    }
+
     fn line_sts(&mut self) -> LineStsFlags {
         unsafe { LineStsFlags::from_bits_truncate(self.line_sts.read()) }
     }
@@ -97,6 +167,25 @@ impl SerialPort {
     }
 }
 
+impl SerialPort {
+    /// Reads a received byte if one is waiting, without blocking.
+    pub fn try_receive(&mut self) -> Option<u8> {
+        if self.line_sts().contains(LineStsFlags::INPUT_FULL) {
+            Some(unsafe { self.data.read() })
+        } else {
+            None
+        }
+    }
+
+    /// Sends a byte as is, unlike `send` which turns backspace and delete into an erase sequence.
+    pub fn send_raw(&mut self, data: u8) {
+        wait_for!(self.line_sts().contains(LineStsFlags::OUTPUT_EMPTY));
+        unsafe {
+            self.data.write(data);
+        }
+    }
+}
+
 impl Write for SerialPort {
     fn write_str(&mut self, s: &str) -> Result {
         for byte in s.bytes() {
@@ -107,23 +196,120 @@ impl Write for SerialPort {
 }
 
 lazy_static! {
-    pub static ref SERIAL: Mutex<SerialPort> = {
+    /// The port used by `serial_print!` and `serial_println!`.
+    pub static ref COM1: Mutex<SerialPort> = {
         let mut serial_port = unsafe { SerialPort::new(0x3F8) };
         serial_port.init();
         Mutex::new(serial_port)
     };
+    pub static ref COM2: Mutex<SerialPort> = {
+        let mut serial_port = unsafe { SerialPort::new(0x2F8) };
+        serial_port.init();
+        //No handler is installed for IRQ 3, so COM2 is polled only
+        serial_port.set_receive_interrupt(false);
+        Mutex::new(serial_port)
+    };
+}
+
+/// Initializes both serial ports up front rather than on first use.
+pub fn init_serial() {
+    lazy_static::initialize(&COM1);
+    lazy_static::initialize(&COM2);
+}
+
+//Same approach as the scancode queue - initialized in lib::init so the interrupt handler never allocates
+pub static SERIAL_QUEUE: OnceCell<ArrayQueue<u8>> = OnceCell::uninit();
+static WAKER: AtomicWaker = AtomicWaker::new();
+
+/// Reads a byte sent by the host if one is available, `None` otherwise.
+pub fn serial_read_byte() -> Option<u8> {
+    interrupts::without_interrupts(|| COM1.lock().try_receive())
+}
+
+/// called by the serial interrupt handler - must not block or allocate.
+pub fn serial_interrupt() {
+    //Interrupts are already disabled here, and COM1 is only ever locked with interrupts disabled
+    let mut serial = COM1.lock();
+    //The FIFO may hold several bytes by the time the interrupt is handled
+    while let Some(byte) = serial.try_receive() {
+        match SERIAL_QUEUE.try_get() {
+            Ok(queue) => {
+                if queue.push(byte).is_err() {
+                    //Can't print here, the serial port is what's locked
+                    break;
+                }
+            }
+            Err(_) => break,
+        }
+    }
+    WAKER.wake();
+}
+
+/// Asynchronously yields the bytes received on the serial port.
+pub struct SerialStream {
+    _private: (), //field to prevent construction of the struct from outside of the module
+}
+
+impl SerialStream {
+    pub fn new() -> Self {
+        Self { _private: () }
+    }
+}
+
+impl Default for SerialStream {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Stream for SerialStream {
+    type Item = u8;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<u8>> {
+        let queue = SERIAL_QUEUE
+            .try_get()
+            .expect("serial queue not initialized");
+
+        //fast path - avoids registering the waker when a byte is already waiting
+        if let Ok(byte) = queue.pop() {
+            return Poll::Ready(Some(byte));
+        }
+
+        WAKER.register(cx.waker());
+        //A byte may have arrived before the waker was registered
+        match queue.pop() {
+            Ok(byte) => {
+                WAKER.take();
+                Poll::Ready(Some(byte))
+            }
+            Err(_) => Poll::Pending,
+        }
+    }
+}
+
+//Everything printed is also kept in the log ring
+struct Tee<'a> {
+    serial: &'a mut SerialPort,
+    ring: &'a mut crate::log::LogRing,
+}
+
+impl Write for Tee<'_> {
+    fn write_str(&mut self, s: &str) -> Result {
+        self.ring.push(s);
+        self.serial.write_str(s)
+    }
 }
 
 #[doc(hidden)]
 pub fn _print(args: ::core::fmt::Arguments) {
-    use x86_64::instructions::interrupts;
-
     //Make sure no interupts occur during lock to prevent deadlock
     interrupts::without_interrupts(|| {
-        SERIAL
-            .lock()
-            .write_fmt(args)
-            .expect("Printing to serial failed");
+        Tee {
+            serial: &mut COM1.lock(),
+            ring: &mut crate::log::LOG_RING.lock(),
+        }
+        .write_fmt(args)
+        .expect("Printing to serial failed");
     });
 }
 
@@ -143,3 +329,48 @@ macro_rules! serial_println {
     ($fmt:expr, $($arg:tt)*) => ($crate::serial_print!(
         concat!($fmt, "\n"), $($arg)*));
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test_case]
+    fn com2_loopback() {
+        interrupts::without_interrupts(|| {
+            let mut com2 = COM2.lock();
+            com2.set_loopback(true);
+            com2.send_raw(0x5A);
+            //The byte takes a moment to show up on the receiving side
+            let mut received = None;
+            for _ in 0..10_000 {
+                received = com2.try_receive();
+                if received.is_some() {
+                    break;
+                }
+            }
+            com2.set_loopback(false);
+            assert_eq!(received, Some(0x5A));
+        });
+    }
+
+    #[test_case]
+    fn set_baud() {
+        interrupts::without_interrupts(|| {
+            let mut com2 = COM2.lock();
+            com2.set_baud(9600).unwrap();
+            assert_eq!(com2.divisor(), 12);
+            assert_eq!(com2.set_baud(7000), Err(InvalidBaudRate(7000)));
+            assert_eq!(com2.set_baud(0), Err(InvalidBaudRate(0)));
+            //A rejected rate must leave the divisor alone
+            assert_eq!(com2.divisor(), 12);
+            com2.set_baud(38400).unwrap();
+            assert_eq!(com2.divisor(), 3);
+        });
+    }
+
+    #[test_case]
+    fn no_input_without_host_data() {
+        //The test runner never writes to the guest, so nothing should be waiting
+        assert_eq!(serial_read_byte(), None);
+    }
+}
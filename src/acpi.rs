@@ -0,0 +1,409 @@
+use crate::{memory::phys_to_virt, pci};
+use alloc::{collections::BTreeMap, vec::Vec};
+use core::{convert::TryInto, slice};
+use x86_64::{instructions::port::Port, PhysAddr};
+
+const RSDP_SIGNATURE: &[u8; 8] = b"RSD PTR ";
+const RSDP_V1_LENGTH: usize = 20;
+const RSDP_V2_LENGTH: usize = 36;
+//The BIOS data area holds the EBDA's segment, and the RSDP is in the EBDA's first KiB or the BIOS ROM
+const EBDA_POINTER: u64 = 0x40E;
+const EBDA_SEARCH_LENGTH: usize = 1024;
+const BIOS_ROM: u64 = 0xE0000;
+const BIOS_ROM_LENGTH: usize = 0x20000;
+
+const HEADER_LENGTH: usize = 36;
+
+//FADT field offsets
+const FADT_DSDT: usize = 40;
+const FADT_SMI_COMMAND: usize = 48;
+const FADT_ACPI_ENABLE: usize = 52;
+const FADT_PM1A_CONTROL: usize = 64;
+const FADT_PM1B_CONTROL: usize = 68;
+const FADT_FLAGS: usize = 112;
+const FADT_RESET_REGISTER: usize = 116; //a generic address structure
+const FADT_RESET_VALUE: usize = 128;
+
+const RESET_REG_SUPPORTED: u32 = 1 << 10; //FADT flags
+
+//Bits of the PM1 control registers
+const SCI_ENABLE: u16 = 1;
+const SLP_TYP_SHIFT: u16 = 10;
+const SLP_ENABLE: u16 = 1 << 13;
+//How long to wait for the firmware to switch to ACPI mode before giving up
+const ACPI_ENABLE_SPINS: usize = 1_000_000;
+
+fn read_u16(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(bytes[offset..offset + 2].try_into().unwrap())
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+}
+
+//Firmware memory is identity mapped at the physical memory offset like everything else
+unsafe fn physical(addr: PhysAddr, len: usize) -> &'static [u8] {
+    slice::from_raw_parts(phys_to_virt(addr).as_ptr(), len)
+}
+
+/// Whether all the bytes of an ACPI structure sum to zero, as they have to.
+pub fn checksum(bytes: &[u8]) -> bool {
+    bytes.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte)) == 0
+}
+
+fn scan_rsdp(start: u64, len: usize) -> Option<PhysAddr> {
+    let region = unsafe { physical(PhysAddr::new(start), len) };
+    //The RSDP is always 16 byte aligned
+    (0..len.saturating_sub(RSDP_V1_LENGTH))
+        .step_by(16)
+        .find(|&offset| {
+            let candidate = &region[offset..offset + RSDP_V1_LENGTH];
+            candidate.starts_with(RSDP_SIGNATURE) && checksum(candidate)
+        })
+        .map(|offset| PhysAddr::new(start + offset as u64))
+}
+
+/// Finds the root system description pointer in the EBDA or the BIOS ROM.
+pub fn find_rsdp() -> Option<PhysAddr> {
+    let ebda = (read_u16(unsafe { physical(PhysAddr::new(EBDA_POINTER), 2) }, 0) as u64) << 4;
+    (ebda != 0)
+        .then(|| scan_rsdp(ebda, EBDA_SEARCH_LENGTH))
+        .flatten()
+        .or_else(|| scan_rsdp(BIOS_ROM, BIOS_ROM_LENGTH))
+}
+
+/// The whole table starting at `addr`, as long as its header says it is.
+unsafe fn table(addr: PhysAddr) -> &'static [u8] {
+    let length = read_u32(physical(addr, HEADER_LENGTH), 4) as usize;
+    physical(addr, length)
+}
+
+/// The physical address of every table the RSDT (or XSDT on ACPI 2.0+) lists, by signature.
+/// Tables that fail their checksum are left out.
+pub fn tables() -> BTreeMap<[u8; 4], PhysAddr> {
+    let mut tables = BTreeMap::new();
+    let rsdp_addr = match find_rsdp() {
+        Some(addr) => addr,
+        None => return tables,
+    };
+    let rsdp = unsafe { physical(rsdp_addr, RSDP_V1_LENGTH) };
+    let revision = rsdp[15];
+    let extended = unsafe { physical(rsdp_addr, RSDP_V2_LENGTH) };
+    let (root, entry_size) = if revision >= 2 && checksum(extended) {
+        (read_u64(extended, 24), 8)
+    } else {
+        (read_u32(rsdp, 16) as u64, 4)
+    };
+    let root = unsafe { table(PhysAddr::new(root)) };
+    if !checksum(root) {
+        return tables;
+    }
+
+    for entry in root[HEADER_LENGTH..].chunks_exact(entry_size) {
+        let addr = PhysAddr::new(match entry_size {
+            8 => read_u64(entry, 0),
+            _ => read_u32(entry, 0) as u64,
+        });
+        let table = unsafe { table(addr) };
+        if checksum(table) {
+            tables.insert(table[..4].try_into().unwrap(), addr);
+        }
+    }
+    tables
+}
+
+/// Finds a table by its signature, e.g. `b"FACP"`.
+pub fn find_table(signature: &[u8; 4]) -> Option<&'static [u8]> {
+    let addr = *tables().get(signature)?;
+    Some(unsafe { table(addr) })
+}
+
+/// What the FADT says about power management.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fadt {
+    pub smi_command: u16,
+    pub acpi_enable: u8,
+    pub pm1a_control: u16,
+    pub pm1b_control: u16, //0 if there's no second block
+    pub dsdt: PhysAddr,
+    pub reset: Option<ResetRegister>, //only in ACPI 2.0+ tables that say the register is supported
+}
+
+/// Where the reset register is, by address space.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResetAddress {
+    Memory(PhysAddr),
+    Io(u16),
+    //bus 0, with the device, function and offset packed into the address
+    PciConfig { slot: u8, function: u8, offset: u8 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResetRegister {
+    pub address: ResetAddress,
+    pub value: u8,
+}
+
+fn reset_register(fadt: &[u8]) -> Option<ResetRegister> {
+    if fadt.len() <= FADT_RESET_VALUE || read_u32(fadt, FADT_FLAGS) & RESET_REG_SUPPORTED == 0 {
+        return None;
+    }
+    let address = read_u64(fadt, FADT_RESET_REGISTER + 4);
+    let address = match fadt[FADT_RESET_REGISTER] {
+        0 => ResetAddress::Memory(PhysAddr::new(address)),
+        1 => ResetAddress::Io(address as u16),
+        2 => ResetAddress::PciConfig {
+            slot: (address >> 32) as u8,
+            function: (address >> 16) as u8,
+            offset: address as u8,
+        },
+        _ => return None,
+    };
+    Some(ResetRegister {
+        address,
+        value: fadt[FADT_RESET_VALUE],
+    })
+}
+
+pub fn fadt() -> Option<Fadt> {
+    let table = find_table(b"FACP")?;
+    Some(Fadt {
+        smi_command: read_u32(table, FADT_SMI_COMMAND) as u16,
+        acpi_enable: table[FADT_ACPI_ENABLE],
+        pm1a_control: read_u32(table, FADT_PM1A_CONTROL) as u16,
+        pm1b_control: read_u32(table, FADT_PM1B_CONTROL) as u16,
+        dsdt: PhysAddr::new(read_u32(table, FADT_DSDT) as u64),
+        reset: reset_register(table),
+    })
+}
+
+//MADT layout - entries follow the fixed fields
+const MADT_LOCAL_APIC_ADDRESS: usize = 36;
+const MADT_ENTRIES: usize = 44;
+const ENTRY_LOCAL_APIC: u8 = 0;
+const ENTRY_IO_APIC: u8 = 1;
+const ENTRY_LOCAL_APIC_OVERRIDE: u8 = 5;
+const LOCAL_APIC_ENABLED: u32 = 1;
+const LOCAL_APIC_ONLINE_CAPABLE: u32 = 1 << 1; //disabled, but can be brought up later
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LocalApic {
+    pub processor_id: u8,
+    pub apic_id: u8,
+    pub usable: bool, //enabled or online capable
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IoApic {
+    pub id: u8,
+    pub address: PhysAddr,
+    pub gsi_base: u32, //the first global system interrupt it handles
+}
+
+/// The interrupt controllers the MADT describes, one local APIC per processor.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Madt {
+    pub local_apic_address: PhysAddr,
+    pub local_apics: Vec<LocalApic>,
+    pub io_apics: Vec<IoApic>,
+}
+
+impl Madt {
+    /// Parses a MADT table. Entries that run past the end of the table or are too short are skipped.
+    pub fn parse(table: &[u8]) -> Option<Madt> {
+        if !table.starts_with(b"APIC") || table.len() < MADT_ENTRIES {
+            return None;
+        }
+        let mut madt = Madt {
+            local_apic_address: PhysAddr::new(read_u32(table, MADT_LOCAL_APIC_ADDRESS) as u64),
+            local_apics: Vec::new(),
+            io_apics: Vec::new(),
+        };
+        let mut entries = &table[MADT_ENTRIES..];
+        while entries.len() >= 2 {
+            let len = (entries[1] as usize).max(2);
+            let entry = match entries.get(..len) {
+                Some(entry) => entry,
+                None => break,
+            };
+            match entry[0] {
+                ENTRY_LOCAL_APIC if len >= 8 => madt.local_apics.push(LocalApic {
+                    processor_id: entry[2],
+                    apic_id: entry[3],
+                    usable: read_u32(entry, 4) & (LOCAL_APIC_ENABLED | LOCAL_APIC_ONLINE_CAPABLE)
+                        != 0,
+                }),
+                ENTRY_IO_APIC if len >= 12 => madt.io_apics.push(IoApic {
+                    id: entry[2],
+                    address: PhysAddr::new(read_u32(entry, 4) as u64),
+                    gsi_base: read_u32(entry, 8),
+                }),
+                ENTRY_LOCAL_APIC_OVERRIDE if len >= 12 => {
+                    madt.local_apic_address = PhysAddr::new(read_u64(entry, 4))
+                }
+                _ => {}
+            }
+            entries = &entries[len..];
+        }
+        Some(madt)
+    }
+}
+
+pub fn madt() -> Option<Madt> {
+    Madt::parse(find_table(b"APIC")?)
+}
+
+//AML opcodes needed to pick the \_S5 package apart
+const NAME_OP: u8 = 0x08;
+const PACKAGE_OP: u8 = 0x12;
+const BYTE_PREFIX: u8 = 0x0A;
+const ONE_OP: u8 = 0x01;
+
+/// The SLP_TYPa and SLP_TYPb values for the S5 (soft off) state, found in the DSDT's
+/// `Name (\_S5, Package () { a, b, ... })` without a full AML interpreter.
+pub fn s5_sleep_types(dsdt: &[u8]) -> Option<(u16, u16)> {
+    //Other references to \_S5 can come first, the definition is the one after a NameOp
+    let start = (0..dsdt.len().saturating_sub(4)).find(|&i| {
+        let name_op = match i {
+            //The name may be rooted, i.e. `\_S5_`
+            2.. if dsdt[i - 1] == b'\\' => dsdt[i - 2],
+            1.. => dsdt[i - 1],
+            _ => return false,
+        };
+        &dsdt[i..i + 4] == b"_S5_" && name_op == NAME_OP && dsdt[i + 4] == PACKAGE_OP
+    })?;
+    //The package length's top two bits give how many more length bytes follow
+    let pkg_length_bytes = (*dsdt.get(start + 5)? >> 6) as usize + 1;
+    let mut elements = dsdt.get(start + 5 + pkg_length_bytes + 1..)?;
+    let mut element = || {
+        let (value, len) = match *elements.first()? {
+            BYTE_PREFIX => (*elements.get(1)?, 2),
+            op @ (0 | ONE_OP) => (op, 1),
+            _ => return None,
+        };
+        elements = &elements[len..];
+        Some(value as u16)
+    };
+    Some((element()?, element()?))
+}
+
+/// Puts the machine into S5 through the ACPI PM1 control registers. Only returns if that failed,
+/// with whether the sleep registers were written at all.
+pub fn enter_s5() -> bool {
+    let sleep_types =
+        fadt().and_then(|fadt| Some((s5_sleep_types(unsafe { table(fadt.dsdt) })?, fadt)));
+    let ((slp_typ_a, slp_typ_b), fadt) = match sleep_types {
+        Some(found) => found,
+        None => return false,
+    };
+    let mut pm1a = Port::<u16>::new(fadt.pm1a_control);
+    unsafe {
+        //The firmware may have left the machine in legacy mode, where the PM registers are ignored
+        if pm1a.read() & SCI_ENABLE == 0 && fadt.smi_command != 0 {
+            Port::<u8>::new(fadt.smi_command).write(fadt.acpi_enable);
+            let enabled = (0..ACPI_ENABLE_SPINS).any(|_| pm1a.read() & SCI_ENABLE != 0);
+            if !enabled {
+                return false;
+            }
+        }
+        pm1a.write(slp_typ_a << SLP_TYP_SHIFT | SLP_ENABLE);
+        if fadt.pm1b_control != 0 {
+            Port::<u16>::new(fadt.pm1b_control).write(slp_typ_b << SLP_TYP_SHIFT | SLP_ENABLE);
+        }
+    }
+    true
+}
+
+/// Resets the machine through the FADT's reset register. Only returns if that did nothing, with
+/// whether there was a reset register to write.
+pub fn reset() -> bool {
+    let reset = match fadt().and_then(|fadt| fadt.reset) {
+        Some(reset) => reset,
+        None => return false,
+    };
+    unsafe {
+        match reset.address {
+            ResetAddress::Memory(addr) => phys_to_virt(addr)
+                .as_mut_ptr::<u8>()
+                .write_volatile(reset.value),
+            ResetAddress::Io(port) => Port::<u8>::new(port).write(reset.value),
+            ResetAddress::PciConfig {
+                slot,
+                function,
+                offset,
+            } => {
+                let aligned = offset & !3;
+                let shift = (offset & 3) * 8;
+                let dword = pci::config_read_u32(0, slot, function, aligned);
+                let dword = dword & !(0xFF << shift) | (reset.value as u32) << shift;
+                pci::config_write_u32(0, slot, function, aligned, dword);
+            }
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test_case]
+    fn rsdp_discovery() {
+        let rsdp = find_rsdp().expect("no RSDP");
+        let bytes = unsafe { physical(rsdp, RSDP_V1_LENGTH) };
+        assert_eq!(&bytes[..8], RSDP_SIGNATURE);
+        assert!(checksum(bytes));
+        assert!(fadt().is_some());
+    }
+
+    #[test_case]
+    fn table_checksums() {
+        //A MADT with one processor and one IO APIC, checksum byte filled in for a zero sum
+        let mut blob = [0u8; 64];
+        blob[..4].copy_from_slice(b"APIC");
+        blob[4] = 64; //length
+        blob[36..40].copy_from_slice(&0xFEE0_0000u32.to_le_bytes());
+        blob[44..52].copy_from_slice(&[ENTRY_LOCAL_APIC, 8, 0, 0, 1, 0, 0, 0]);
+        blob[52] = ENTRY_IO_APIC;
+        blob[53] = 12;
+        blob[56..60].copy_from_slice(&0xFEC0_0000u32.to_le_bytes());
+        let sum = blob.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte));
+        blob[9] = 0u8.wrapping_sub(sum);
+        assert!(checksum(&blob));
+
+        let madt = Madt::parse(&blob).unwrap();
+        assert_eq!(madt.local_apic_address, PhysAddr::new(0xFEE0_0000));
+        assert_eq!(madt.local_apics.len(), 1);
+        assert!(madt.local_apics[0].usable);
+        assert_eq!(madt.io_apics[0].address, PhysAddr::new(0xFEC0_0000));
+
+        blob[50] ^= 1;
+        assert!(!checksum(&blob));
+    }
+
+    #[test_case]
+    fn table_map() {
+        let tables = tables();
+        assert!(tables.contains_key(b"FACP"));
+        let madt = madt().expect("no MADT");
+        assert!(!madt.local_apics.is_empty());
+        assert!(!madt.io_apics.is_empty());
+    }
+
+    #[test_case]
+    fn s5_package() {
+        //Name (\_S5, Package (0x04) { 0x05, Zero, Zero, Zero }), as QEMU's DSDT has it
+        let aml = [
+            0x10, 0x08, b'\\', b'_', b'S', b'5', b'_', 0x12, 0x07, 0x04, 0x0A, 0x05, 0x00, 0x00,
+            0x00,
+        ];
+        assert_eq!(s5_sleep_types(&aml), Some((5, 0)));
+        assert_eq!(s5_sleep_types(b"no sleep states"), None);
+        //a reference rather than the definition
+        assert_eq!(s5_sleep_types(b"\x70_S5_\x60"), None);
+    }
+}
@@ -0,0 +1,86 @@
+use core::{
+    alloc::Layout,
+    ptr,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+const POISON: u8 = 0xDD;
+//The allocators keep their free list node at the start of a freed block, so poison only what comes after it
+const POISON_OFFSET: usize = 16;
+const RECENT_FREES: usize = 64;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static DETECTED: AtomicUsize = AtomicUsize::new(0);
+
+//A poisoned block alone isn't proof - live data can happen to look the same - so it only counts if it was freed recently too
+struct RecentFrees {
+    ptrs: [usize; RECENT_FREES],
+    next: usize,
+}
+
+static RECENT: spin::Mutex<RecentFrees> = spin::Mutex::new(RecentFrees {
+    ptrs: [0; RECENT_FREES],
+    next: 0,
+});
+
+/// Starts poisoning freed blocks and checking frees against them.
+pub fn enable_double_free_checks() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// Stops the checks again, so frees go back to the fast path.
+pub fn disable_double_free_checks() {
+    ENABLED.store(false, Ordering::Relaxed);
+    //Allocations aren't tracked while off, so these could be live again by the next enable
+    let mut recent = RECENT.lock();
+    recent.ptrs = [0; RECENT_FREES];
+    recent.next = 0;
+}
+
+/// Number of double frees caught so far.
+pub fn double_frees_detected() -> usize {
+    DETECTED.load(Ordering::Relaxed)
+}
+
+pub(super) fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+//the block is live again, so freeing it is fine
+pub(super) fn on_alloc(ptr: *mut u8) {
+    let mut recent = RECENT.lock();
+    for slot in recent.ptrs.iter_mut().filter(|slot| **slot == ptr as usize) {
+        *slot = 0;
+    }
+}
+
+/// Returns true if `ptr` was already freed, in which case the caller must not free it again.
+pub(super) unsafe fn is_double_free(ptr: *mut u8, layout: Layout) -> bool {
+    let recent = RECENT.lock();
+    if !recent.ptrs.contains(&(ptr as usize)) || !is_poisoned(ptr, layout) {
+        return false;
+    }
+    DETECTED.fetch_add(1, Ordering::Relaxed);
+    crate::log_error!("double free of {:p} ({:?})", ptr, layout);
+    true
+}
+
+/// # Safety
+///`ptr` must point to a block of `layout` that was just freed
+pub(super) unsafe fn poison(ptr: *mut u8, layout: Layout) {
+    if layout.size() > POISON_OFFSET {
+        ptr::write_bytes(
+            ptr.add(POISON_OFFSET),
+            POISON,
+            layout.size() - POISON_OFFSET,
+        );
+    }
+    let mut recent = RECENT.lock();
+    let next = recent.next;
+    recent.ptrs[next] = ptr as usize;
+    recent.next = (next + 1) % RECENT_FREES;
+}
+
+unsafe fn is_poisoned(ptr: *mut u8, layout: Layout) -> bool {
+    (POISON_OFFSET..layout.size()).all(|offset| *ptr.add(offset) == POISON)
+}
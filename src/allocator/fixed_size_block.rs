@@ -0,0 +1,98 @@
+use super::{linked_list::LinkedListAllocator, Locked};
+use core::{
+    alloc::{GlobalAlloc, Layout},
+    mem,
+};
+
+//Block sizes double as their alignment, so they must all be powers of 2
+const BLOCK_SIZES: &[usize] = &[8, 16, 32, 64, 128, 256, 512, 1024, 2048];
+
+struct ListNode {
+    next: Option<&'static mut ListNode>,
+}
+
+/// Serves small allocations from per-size free lists, anything larger goes to a linked list allocator.
+pub struct FixedSizeBlockAllocator {
+    list_heads: [Option<&'static mut ListNode>; BLOCK_SIZES.len()],
+    fallback: LinkedListAllocator,
+}
+
+impl FixedSizeBlockAllocator {
+    pub const fn new() -> Self {
+        const EMPTY: Option<&'static mut ListNode> = None;
+        Self {
+            list_heads: [EMPTY; BLOCK_SIZES.len()],
+            fallback: LinkedListAllocator::new(),
+        }
+    }
+
+    /// # Safety
+    ///caller must ensure that the given memory range is unused and must not call this function twice
+    pub unsafe fn init(&mut self, heap_start: usize, heap_size: usize) {
+        self.fallback.init(heap_start, heap_size);
+    }
+
+    /// # Safety
+    ///The region must be unused and must not overlap memory already owned by the allocator
+    pub unsafe fn add_free_region(&mut self, addr: usize, size: usize) {
+        self.fallback.add_free_region(addr, size);
+    }
+
+    //index of the smallest block that fits the layout, None if the fallback has to serve it
+    fn list_index(layout: &Layout) -> Option<usize> {
+        let required_block_size = layout.size().max(layout.align());
+        BLOCK_SIZES.iter().position(|&s| s >= required_block_size)
+    }
+
+    pub fn allocate(&mut self, layout: Layout) -> *mut u8 {
+        match Self::list_index(&layout) {
+            Some(index) => match self.list_heads[index].take() {
+                Some(node) => {
+                    self.list_heads[index] = node.next.take();
+                    node as *mut ListNode as *mut u8
+                }
+                None => {
+                    //no free block of this size yet - carve a new one out of the fallback
+                    let block_size = BLOCK_SIZES[index];
+                    let layout = Layout::from_size_align(block_size, block_size).unwrap();
+                    self.fallback.allocate(layout)
+                }
+            },
+            None => self.fallback.allocate(layout),
+        }
+    }
+
+    /// # Safety
+    ///`ptr` must have been returned by `allocate` on this allocator with the same layout
+    pub unsafe fn deallocate(&mut self, ptr: *mut u8, layout: Layout) {
+        match Self::list_index(&layout) {
+            Some(index) => {
+                //every block is at least as large and aligned as a ListNode
+                assert!(mem::size_of::<ListNode>() <= BLOCK_SIZES[index]);
+                assert!(mem::align_of::<ListNode>() <= BLOCK_SIZES[index]);
+                let new_node = ListNode {
+                    next: self.list_heads[index].take(),
+                };
+                let new_node_ptr = ptr as *mut ListNode;
+                new_node_ptr.write(new_node);
+                self.list_heads[index] = Some(&mut *new_node_ptr);
+            }
+            None => self.fallback.deallocate(ptr, layout),
+        }
+    }
+}
+
+impl Default for FixedSizeBlockAllocator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+unsafe impl GlobalAlloc for Locked<FixedSizeBlockAllocator> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.lock().allocate(layout)
+    }
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.lock().deallocate(ptr, layout)
+    }
+}
@@ -84,6 +84,30 @@ impl LinkedListAllocator {
         Ok(alloc_start)
     }
 
+    pub fn allocate(&mut self, layout: Layout) -> *mut u8 {
+        let (size, align) = Self::size_align(layout);
+
+        if let Some((region, alloc_start)) = self.find_region(size, align) {
+            let alloc_end = alloc_start.checked_add(size).expect("overflow");
+            let excess_size = region.end_addr() - alloc_end;
+            if excess_size > 0 {
+                unsafe { self.add_free_region(alloc_end, excess_size) };
+            }
+            alloc_start as *mut u8
+        } else {
+            null_mut()
+        }
+    }
+
+    /// # Safety
+    ///`ptr` must have been returned by `allocate` on this allocator with the same layout
+    pub unsafe fn deallocate(&mut self, ptr: *mut u8, layout: Layout) {
+        // perform layout adjustments
+        let (size, _) = Self::size_align(layout);
+
+        self.add_free_region(ptr as usize, size)
+    }
+
     //adjsut layout so it can fit list node
     fn size_align(layout: Layout) -> (usize, usize) {
         let layout = layout
@@ -97,24 +121,9 @@ impl LinkedListAllocator {
 
 unsafe impl GlobalAlloc for Locked<LinkedListAllocator> {
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
-        let mut allocator = self.lock();
-        let (size, align) = LinkedListAllocator::size_align(layout);
-
-        if let Some((region, alloc_start)) = allocator.find_region(size, align) {
-            let alloc_end = alloc_start.checked_add(size).expect("overflow");
-            let excess_size = region.end_addr() - alloc_end;
-            if excess_size > 0 {
-                allocator.add_free_region(alloc_end, excess_size);
-            }
-            alloc_start as *mut u8
-        } else {
-            null_mut()
-        }
+        self.lock().allocate(layout)
     }
     unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
-        // perform layout adjustments
-        let (size, _) = LinkedListAllocator::size_align(layout);
-
-        self.lock().add_free_region(ptr as usize, size)
+        self.lock().deallocate(ptr, layout)
     }
 }
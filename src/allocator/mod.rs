@@ -1,43 +1,223 @@
+use crate::memory;
+use core::alloc::{GlobalAlloc, Layout};
+use core::ptr::null_mut;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use x86_64::instructions::interrupts;
 use x86_64::{
     structures::paging::{
-        mapper::MapToError, FrameAllocator, Mapper, Page, PageTableFlags, Size4KiB,
+        mapper::MapToError, FrameAllocator, FrameDeallocator, Mapper, Page, PageTableFlags,
+        Size4KiB,
     },
     VirtAddr,
 };
 
+use self::fixed_size_block::FixedSizeBlockAllocator;
 use self::linked_list::LinkedListAllocator;
 
+mod double_free;
+pub mod fixed_size_block;
 pub mod linked_list;
+mod tracking;
+
+pub use double_free::{
+    disable_double_free_checks, double_frees_detected, enable_double_free_checks,
+};
+pub use tracking::{AllocStats, Tracked};
 
 pub const HEAP_START: usize = 0x_4444_4444_0000;
 pub const HEAP_SIZE: usize = 100 * 16384; // 1600 KiB
+                                          //Smallest amount the heap grows by, so small allocations don't each map a page
+const GROWTH_STEP: usize = 64 * 1024;
+const PAGE_SIZE: usize = 4096;
+
+static HEAP_END: AtomicUsize = AtomicUsize::new(HEAP_START);
+static HEAP_LIMIT: AtomicUsize = AtomicUsize::new(0); //0 while growth is disabled
 
 #[global_allocator]
-static ALLOCATOR: Locked<LinkedListAllocator> = Locked::new(LinkedListAllocator::new());
+static ALLOCATOR: Tracked<Locked<Heap>> = Tracked::new(Locked::new(Heap::Uninit));
+
+/// Which allocator `init_heap` sets the kernel heap up with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HeapKind {
+    #[default]
+    LinkedList,
+    FixedSizeBlock,
+}
+
+/// The kernel heap, dispatching to whichever allocator was picked at init.
+pub enum Heap {
+    Uninit,
+    LinkedList(LinkedListAllocator),
+    FixedSizeBlock(FixedSizeBlockAllocator),
+}
+
+impl Heap {
+    /// # Safety
+    ///caller must ensure that the given memory range is unused and must not call this function twice
+    pub unsafe fn init(&mut self, kind: HeapKind, heap_start: usize, heap_size: usize) {
+        *self = match kind {
+            HeapKind::LinkedList => {
+                let mut allocator = LinkedListAllocator::new();
+                allocator.init(heap_start, heap_size);
+                Heap::LinkedList(allocator)
+            }
+            HeapKind::FixedSizeBlock => {
+                let mut allocator = FixedSizeBlockAllocator::new();
+                allocator.init(heap_start, heap_size);
+                Heap::FixedSizeBlock(allocator)
+            }
+        };
+    }
+
+    fn allocate(&mut self, layout: Layout) -> *mut u8 {
+        match self {
+            Heap::Uninit => null_mut(),
+            Heap::LinkedList(allocator) => allocator.allocate(layout),
+            Heap::FixedSizeBlock(allocator) => allocator.allocate(layout),
+        }
+    }
+
+    unsafe fn add_free_region(&mut self, addr: usize, size: usize) {
+        match self {
+            Heap::Uninit => {}
+            Heap::LinkedList(allocator) => allocator.add_free_region(addr, size),
+            Heap::FixedSizeBlock(allocator) => allocator.add_free_region(addr, size),
+        }
+    }
+
+    //maps more pages after the current end of the heap, big enough to serve `layout` on its own
+    fn grow(&mut self, layout: Layout) -> bool {
+        let end = HEAP_END.load(Ordering::Relaxed);
+        let limit = HEAP_LIMIT.load(Ordering::Relaxed);
+        //the new region isn't merged with the old tail, so it has to fit the allocation with worst case padding
+        let needed = layout.size() + layout.align() + 4 * core::mem::size_of::<usize>();
+        let size = align_up(needed.max(GROWTH_STEP), PAGE_SIZE);
+        if matches!(self, Heap::Uninit) || end + size > limit {
+            return false;
+        }
+
+        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+        //mapped a page at a time, so a failure part way still leaves the pages before it usable
+        let mapped = memory::try_with_memory(|memory| {
+            let mut mapped = 0;
+            while mapped < size {
+                let page = Page::containing_address(VirtAddr::new((end + mapped) as u64));
+                let Some(frame) = memory.frame_allocator.allocate_frame() else {
+                    break;
+                };
+                let result = unsafe {
+                    memory
+                        .mapper
+                        .map_to(page, frame, flags, &mut memory.frame_allocator)
+                };
+                match result {
+                    Ok(flush) => flush.flush(),
+                    Err(_) => {
+                        unsafe { memory.frame_allocator.deallocate_frame(frame) };
+                        break;
+                    }
+                }
+                mapped += PAGE_SIZE;
+            }
+            mapped
+        })
+        .unwrap_or(0);
+
+        //whatever did get mapped joins the heap, so the next growth starts after it instead of hitting mapped pages
+        if mapped > 0 {
+            unsafe { self.add_free_region(end, mapped) };
+            HEAP_END.store(end + mapped, Ordering::Relaxed);
+        }
+        mapped == size
+    }
+
+    pub fn kind(&self) -> Option<HeapKind> {
+        match self {
+            Heap::Uninit => None,
+            Heap::LinkedList(_) => Some(HeapKind::LinkedList),
+            Heap::FixedSizeBlock(_) => Some(HeapKind::FixedSizeBlock),
+        }
+    }
+}
+
+unsafe impl GlobalAlloc for Locked<Heap> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let mut heap = self.lock();
+        let ptr = heap.allocate(layout);
+        if ptr.is_null() && heap.grow(layout) {
+            heap.allocate(layout)
+        } else {
+            ptr
+        }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        match &mut *self.lock() {
+            Heap::Uninit => unreachable!("dealloc before the heap was initialized"),
+            Heap::LinkedList(allocator) => allocator.deallocate(ptr, layout),
+            Heap::FixedSizeBlock(allocator) => allocator.deallocate(ptr, layout),
+        }
+    }
+}
+
+/// The allocator the heap was initialized with, `None` before `init_heap`.
+pub fn heap_kind() -> Option<HeapKind> {
+    ALLOCATOR.inner().lock().kind()
+}
+
+/// Lets the heap grow past `HEAP_SIZE` when it runs out, up to `max_size` bytes in total.
+///Only takes effect once `memory::install` has been called
+pub fn enable_growth(max_size: usize) {
+    HEAP_LIMIT.store(HEAP_START + max_size, Ordering::Relaxed);
+}
+
+/// Bytes currently mapped for the heap, including any growth.
+pub fn heap_size() -> usize {
+    HEAP_END.load(Ordering::Relaxed) - HEAP_START
+}
+
+static OOM_HANDLER: spin::Mutex<fn(Layout)> = spin::Mutex::new(default_oom_handler);
+
+fn default_oom_handler(layout: Layout) {
+    crate::log_error!("allocation of {:?} failed, heap: {:?}", layout, stats());
+}
+
+/// Replaces the function run when an allocation fails. The kernel halts once it returns.
+pub fn set_oom_handler(handler: fn(Layout)) {
+    interrupts::without_interrupts(|| *OOM_HANDLER.lock() = handler);
+}
+
+/// Runs the out of memory handler and halts, called from the `alloc_error_handler`.
+pub fn handle_oom(layout: Layout) -> ! {
+    //The allocator lock was dropped when the failed allocation returned, so the handler is free to allocate
+    let handler = interrupts::without_interrupts(|| *OOM_HANDLER.lock());
+    handler(layout);
+    crate::hlt_loop();
+}
+
+/// Current heap usage counters.
+pub fn stats() -> AllocStats {
+    ALLOCATOR.stats()
+}
 
 pub fn init_heap(
     mapper: &mut impl Mapper<Size4KiB>,
     frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+    kind: HeapKind,
 ) -> Result<(), MapToError<Size4KiB>> {
-    let page_range = {
-        let heap_start = VirtAddr::new(HEAP_START as u64);
-        let heap_end = heap_start + HEAP_SIZE - 1u64;
-        let heap_start_page = Page::containing_address(heap_start);
-        let heap_end_page = Page::containing_address(heap_end);
-        Page::range_inclusive(heap_start_page, heap_end_page)
-    };
-
-    for page in page_range {
-        let frame = frame_allocator
-            .allocate_frame()
-            .ok_or(MapToError::FrameAllocationFailed)?;
-        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
-        unsafe { mapper.map_to(page, frame, flags, frame_allocator)?.flush() };
-    }
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+    memory::map_pages(
+        mapper,
+        frame_allocator,
+        VirtAddr::new(HEAP_START as u64),
+        HEAP_SIZE as u64,
+        flags,
+    )?;
 
     unsafe {
-        ALLOCATOR.lock().init(HEAP_START, HEAP_SIZE);
+        ALLOCATOR.inner().lock().init(kind, HEAP_START, HEAP_SIZE);
     }
+    HEAP_END.store(HEAP_START + HEAP_SIZE, Ordering::Relaxed);
 
     Ok(())
 }
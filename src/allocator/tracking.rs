@@ -0,0 +1,86 @@
+use super::double_free;
+use core::{
+    alloc::{GlobalAlloc, Layout},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// Snapshot of the heap counters, see `allocator::stats`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AllocStats {
+    pub total_allocated: usize, //bytes handed out since boot, never decreases
+    pub in_use: usize,
+    pub peak: usize,
+    pub live_allocations: usize,
+}
+
+/// Wraps an allocator and keeps usage counters for it.
+pub struct Tracked<A> {
+    inner: A,
+    total_allocated: AtomicUsize,
+    in_use: AtomicUsize,
+    peak: AtomicUsize,
+    live_allocations: AtomicUsize,
+}
+
+impl<A> Tracked<A> {
+    pub const fn new(inner: A) -> Self {
+        Self {
+            inner,
+            total_allocated: AtomicUsize::new(0),
+            in_use: AtomicUsize::new(0),
+            peak: AtomicUsize::new(0),
+            live_allocations: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn inner(&self) -> &A {
+        &self.inner
+    }
+
+    pub fn stats(&self) -> AllocStats {
+        AllocStats {
+            total_allocated: self.total_allocated.load(Ordering::Relaxed),
+            in_use: self.in_use.load(Ordering::Relaxed),
+            peak: self.peak.load(Ordering::Relaxed),
+            live_allocations: self.live_allocations.load(Ordering::Relaxed),
+        }
+    }
+
+    fn record_alloc(&self, size: usize) {
+        self.total_allocated.fetch_add(size, Ordering::Relaxed);
+        let in_use = self.in_use.fetch_add(size, Ordering::Relaxed) + size;
+        self.peak.fetch_max(in_use, Ordering::Relaxed);
+        self.live_allocations.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn record_dealloc(&self, size: usize) {
+        self.in_use.fetch_sub(size, Ordering::Relaxed);
+        self.live_allocations.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+//The counters are only touched after the inner allocator returns, so its lock is never held while updating them
+unsafe impl<A: GlobalAlloc> GlobalAlloc for Tracked<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = self.inner.alloc(layout);
+        if !ptr.is_null() {
+            self.record_alloc(layout.size());
+            if double_free::enabled() {
+                double_free::on_alloc(ptr);
+            }
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let checked = double_free::enabled();
+        if checked && double_free::is_double_free(ptr, layout) {
+            return; //freeing it again would corrupt the free list
+        }
+        self.inner.dealloc(ptr, layout);
+        self.record_dealloc(layout.size());
+        if checked {
+            double_free::poison(ptr, layout);
+        }
+    }
+}
@@ -0,0 +1,184 @@
+//! Polled PIO reads from the master drive on the primary ATA bus.
+use core::fmt;
+use spin::Mutex;
+use x86_64::instructions::port::{Port, PortReadOnly, PortWriteOnly};
+
+pub const SECTOR_SIZE: usize = 512;
+
+const LBA28_LIMIT: u64 = 1 << 28;
+const LBA48_LIMIT: u64 = 1 << 48;
+const MAX_LBA28_SECTORS: u16 = 256;
+
+const COMMAND_READ_SECTORS: u8 = 0x20;
+const COMMAND_READ_SECTORS_EXT: u8 = 0x24;
+
+const SELECT_MASTER_LBA: u8 = 0xE0; //LBA28 puts bits 24-27 of the address in the low nibble
+const SELECT_MASTER_LBA48: u8 = 0x40;
+
+const CONTROL_NO_INTERRUPTS: u8 = 1 << 1; //nIEN, there's no IRQ14 handler to acknowledge them
+
+const STATUS_ERR: u8 = 1 << 0;
+const STATUS_DRQ: u8 = 1 << 3;
+const STATUS_DF: u8 = 1 << 5;
+const STATUS_BSY: u8 = 1 << 7;
+const FLOATING_BUS: u8 = 0xFF; //what reads back with nothing attached
+
+const POLL_LIMIT: u32 = 1_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AtaError {
+    NoDevice,
+    BufferTooSmall,
+    LbaOutOfRange,
+    Timeout,
+    /// The drive set the error or drive fault bit, carrying its error register.
+    Device(u8),
+}
+
+impl fmt::Display for AtaError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AtaError::NoDevice => f.write_str("no drive on the primary ATA bus"),
+            AtaError::BufferTooSmall => f.write_str("buffer too small for the sectors read"),
+            AtaError::LbaOutOfRange => f.write_str("sector address out of range"),
+            AtaError::Timeout => f.write_str("drive did not respond"),
+            AtaError::Device(error) => write!(f, "drive error {:#04x}", error),
+        }
+    }
+}
+
+struct Bus {
+    data: Port<u16>,
+    error: PortReadOnly<u8>,
+    sector_count: PortWriteOnly<u8>,
+    lba_low: PortWriteOnly<u8>,
+    lba_mid: PortWriteOnly<u8>,
+    lba_high: PortWriteOnly<u8>,
+    drive: PortWriteOnly<u8>,
+    status: PortReadOnly<u8>, //reading it acknowledges the drive's interrupt
+    command: PortWriteOnly<u8>,
+    alternate_status: PortReadOnly<u8>, //same as status, without the side effect
+    control: PortWriteOnly<u8>,         //same port as alternate_status
+}
+
+static PRIMARY: Mutex<Bus> = Mutex::new(Bus::new(0x1F0, 0x3F6));
+
+impl Bus {
+    const fn new(base: u16, control: u16) -> Bus {
+        Bus {
+            data: Port::new(base),
+            error: PortReadOnly::new(base + 1),
+            sector_count: PortWriteOnly::new(base + 2),
+            lba_low: PortWriteOnly::new(base + 3),
+            lba_mid: PortWriteOnly::new(base + 4),
+            lba_high: PortWriteOnly::new(base + 5),
+            drive: PortWriteOnly::new(base + 6),
+            status: PortReadOnly::new(base + 7),
+            command: PortWriteOnly::new(base + 7),
+            alternate_status: PortReadOnly::new(control),
+            control: PortWriteOnly::new(control),
+        }
+    }
+
+    //Each status read takes ~100ns, and the drive needs 400ns to update it after a command
+    fn delay(&mut self) {
+        for _ in 0..4 {
+            unsafe { self.alternate_status.read() };
+        }
+    }
+
+    //Waits until the drive has a sector to transfer
+    fn wait_for_data(&mut self) -> Result<(), AtaError> {
+        for _ in 0..POLL_LIMIT {
+            let status = unsafe { self.status.read() };
+            if status == FLOATING_BUS {
+                return Err(AtaError::NoDevice);
+            }
+            if status & STATUS_BSY != 0 {
+                continue;
+            }
+            if status & (STATUS_ERR | STATUS_DF) != 0 {
+                return Err(AtaError::Device(unsafe { self.error.read() }));
+            }
+            if status & STATUS_DRQ != 0 {
+                return Ok(());
+            }
+        }
+        Err(AtaError::Timeout)
+    }
+
+    fn issue_read(&mut self, lba: u64, count: u16) {
+        unsafe {
+            self.control.write(CONTROL_NO_INTERRUPTS);
+            if lba + count as u64 <= LBA28_LIMIT && count <= MAX_LBA28_SECTORS {
+                self.drive
+                    .write(SELECT_MASTER_LBA | (lba >> 24) as u8 & 0x0F);
+                self.delay();
+                self.sector_count.write(count as u8); //0 means 256
+                self.lba_low.write(lba as u8);
+                self.lba_mid.write((lba >> 8) as u8);
+                self.lba_high.write((lba >> 16) as u8);
+                self.command.write(COMMAND_READ_SECTORS);
+            } else {
+                self.drive.write(SELECT_MASTER_LBA48);
+                self.delay();
+                //Each register is a two byte FIFO: high bytes first, then low
+                self.sector_count.write((count >> 8) as u8);
+                self.lba_low.write((lba >> 24) as u8);
+                self.lba_mid.write((lba >> 32) as u8);
+                self.lba_high.write((lba >> 40) as u8);
+                self.sector_count.write(count as u8);
+                self.lba_low.write(lba as u8);
+                self.lba_mid.write((lba >> 8) as u8);
+                self.lba_high.write((lba >> 16) as u8);
+                self.command.write(COMMAND_READ_SECTORS_EXT);
+            }
+        }
+        self.delay();
+    }
+}
+
+/// Reads `count` sectors starting at `lba` into the start of `buf`, using LBA48 only when the
+/// range doesn't fit LBA28.
+pub fn read_sectors(lba: u64, count: u16, buf: &mut [u8]) -> Result<(), AtaError> {
+    if buf.len() < count as usize * SECTOR_SIZE {
+        return Err(AtaError::BufferTooSmall);
+    }
+    if lba
+        .checked_add(count as u64)
+        .is_none_or(|end| end > LBA48_LIMIT)
+    {
+        return Err(AtaError::LbaOutOfRange);
+    }
+    if count == 0 {
+        return Ok(());
+    }
+    let mut bus = PRIMARY.lock();
+    bus.issue_read(lba, count);
+    for sector in buf[..count as usize * SECTOR_SIZE].chunks_exact_mut(SECTOR_SIZE) {
+        bus.wait_for_data()?;
+        for word in sector.chunks_exact_mut(2) {
+            word.copy_from_slice(&unsafe { bus.data.read() }.to_le_bytes());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    //QEMU attaches the boot image as the primary master, so sector 0 is the boot sector
+    #[test_case]
+    fn boot_signature() {
+        let mut sector = [0; SECTOR_SIZE];
+        read_sectors(0, 1, &mut sector).unwrap();
+        assert_eq!(&sector[510..], &[0x55, 0xAA]);
+    }
+
+    #[test_case]
+    fn short_buffer() {
+        let mut buf = [0; SECTOR_SIZE];
+        assert_eq!(read_sectors(0, 2, &mut buf), Err(AtaError::BufferTooSmall));
+    }
+}
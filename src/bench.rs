@@ -0,0 +1,81 @@
+//! Benchmarks that run under the test framework.
+//!
+//! Declared as a `#[test_case]` static, the test runner times them after all the tests have passed:
+//! ```ignore
+//! #[test_case]
+//! static BOX_ALLOC: Bench = Bench::new("box_alloc", 10_000, |iterations| {
+//!     for i in 0..iterations {
+//!         black_box(Box::new(i));
+//!     }
+//! });
+//! ```
+use crate::{serial_print, serial_println, time::tsc, Testable};
+use conquer_once::spin::OnceCell;
+
+/// A function timed over `iterations` runs. It's passed the iteration count and does the looping
+/// itself, so the loop isn't a function call per iteration.
+pub struct Bench {
+    name: &'static str,
+    iterations: u64,
+    f: fn(u64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BenchResult {
+    pub cycles_per_iter: u64,
+    pub ns_per_iter: Option<u64>, //None if the TSC frequency couldn't be measured
+}
+
+static TSC_FREQUENCY: OnceCell<Option<u64>> = OnceCell::uninit();
+
+//The rate calibrated at init, or measured once here if the TSC isn't invariant - benchmarks are
+//short enough for that to be close
+fn tsc_frequency() -> Option<u64> {
+    *TSC_FREQUENCY.get_or_init(|| tsc::frequency().or_else(tsc::measure_frequency))
+}
+
+impl Bench {
+    pub const fn new(name: &'static str, iterations: u64, f: fn(u64)) -> Self {
+        assert!(iterations > 0);
+        Self {
+            name,
+            iterations,
+            f,
+        }
+    }
+
+    pub fn measure(&self) -> BenchResult {
+        let tsc_frequency = tsc_frequency();
+        //One untimed run to warm up caches and let the heap grow to its working size
+        (self.f)(1);
+        let start = tsc::now();
+        (self.f)(self.iterations);
+        let cycles = tsc::now() - start;
+        BenchResult {
+            cycles_per_iter: cycles / self.iterations,
+            ns_per_iter: tsc_frequency.map(|frequency| {
+                (cycles as u128 * 1_000_000_000 / (frequency as u128 * self.iterations as u128))
+                    as u64
+            }),
+        }
+    }
+}
+
+impl Testable for Bench {
+    fn run(&self) {
+        serial_print!("bench {}...\t", self.name);
+        let result = self.measure();
+        match result.ns_per_iter {
+            Some(ns) => serial_println!("{} ns/iter ({} cycles)", ns, result.cycles_per_iter),
+            None => serial_println!("{} cycles/iter", result.cycles_per_iter),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn is_bench(&self) -> bool {
+        true
+    }
+}
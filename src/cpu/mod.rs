@@ -0,0 +1,158 @@
+use alloc::string::String;
+use core::arch::x86_64::{__cpuid, CpuidResult};
+use x86_64::registers::control::{Cr0, Cr0Flags, Cr4, Cr4Flags};
+
+pub mod msr;
+
+/// What the CPU reports it supports through CPUID.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CpuFeatures {
+    pub sse: bool,
+    pub sse2: bool,
+    pub avx: bool,
+    pub rdrand: bool,
+    pub rdseed: bool,
+    pub apic: bool,
+    pub x2apic: bool,
+    pub tsc: bool,
+    pub invariant_tsc: bool, //TSC runs at a constant rate regardless of power state
+}
+
+const LEAF_VENDOR: u32 = 0;
+const LEAF_FEATURES: u32 = 1;
+const LEAF_EXTENDED_FEATURES: u32 = 7;
+const LEAF_MAX_EXTENDED: u32 = 0x8000_0000;
+const LEAF_BRAND: u32 = 0x8000_0002; //through 0x8000_0004
+const LEAF_POWER_MANAGEMENT: u32 = 0x8000_0007;
+
+fn bit(value: u32, bit: u32) -> bool {
+    value & (1 << bit) != 0
+}
+
+//Leaves past the maximum return garbage rather than zeroes, so they're checked first
+fn cpuid(leaf: u32) -> Option<CpuidResult> {
+    let max = __cpuid(leaf & LEAF_MAX_EXTENDED).eax;
+    (leaf <= max).then(|| __cpuid(leaf))
+}
+
+pub fn features() -> CpuFeatures {
+    let basic = cpuid(LEAF_FEATURES).unwrap_or(CpuidResult {
+        eax: 0,
+        ebx: 0,
+        ecx: 0,
+        edx: 0,
+    });
+    let extended_ebx = cpuid(LEAF_EXTENDED_FEATURES).map_or(0, |leaf| leaf.ebx);
+    let power_edx = cpuid(LEAF_POWER_MANAGEMENT).map_or(0, |leaf| leaf.edx);
+
+    CpuFeatures {
+        sse: bit(basic.edx, 25),
+        sse2: bit(basic.edx, 26),
+        avx: bit(basic.ecx, 28),
+        rdrand: bit(basic.ecx, 30),
+        rdseed: bit(extended_ebx, 18),
+        apic: bit(basic.edx, 9),
+        x2apic: bit(basic.ecx, 21),
+        tsc: bit(basic.edx, 4),
+        invariant_tsc: bit(power_edx, 8),
+    }
+}
+
+/// Lets SSE/SSE2 instructions run instead of faulting with #UD, returning false if the CPU has
+/// neither. The kernel is compiled with SSE, so the bootloader already turns it on before the kernel
+/// starts - this makes sure of it, including the exception reporting the bootloader doesn't set up.
+pub fn enable_sse() -> bool {
+    let features = features();
+    if !(features.sse && features.sse2) {
+        return false;
+    }
+    unsafe {
+        Cr0::update(|flags| {
+            flags.remove(Cr0Flags::EMULATE_COPROCESSOR | Cr0Flags::TASK_SWITCHED);
+            flags.insert(Cr0Flags::MONITOR_COPROCESSOR);
+        });
+        //OSXMMEXCPT makes unmasked SIMD float exceptions raise #XM rather than #UD
+        Cr4::update(|flags| flags.insert(Cr4Flags::OSFXSR | Cr4Flags::OSXMMEXCPT_ENABLE));
+    }
+    true
+}
+
+fn push_bytes(string: &mut String, register: u32) {
+    for byte in register.to_le_bytes() {
+        if byte != 0 {
+            string.push(byte as char);
+        }
+    }
+}
+
+/// The 12 character vendor ID, e.g. "GenuineIntel".
+pub fn vendor_string() -> String {
+    let leaf = __cpuid(LEAF_VENDOR);
+    let mut vendor = String::with_capacity(12);
+    //The vendor is spelled out in ebx, edx, ecx - in that order
+    for register in [leaf.ebx, leaf.edx, leaf.ecx] {
+        push_bytes(&mut vendor, register);
+    }
+    vendor
+}
+
+/// The processor's marketing name, empty if the CPU doesn't report one.
+pub fn brand_string() -> String {
+    let mut brand = String::with_capacity(48);
+    for leaf in LEAF_BRAND..LEAF_BRAND + 3 {
+        if let Some(leaf) = cpuid(leaf) {
+            for register in [leaf.eax, leaf.ebx, leaf.ecx, leaf.edx] {
+                push_bytes(&mut brand, register);
+            }
+        }
+    }
+    String::from(brand.trim())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::serial_println;
+
+    #[test_case]
+    fn detect_features() {
+        let features = features();
+        serial_println!("{} \"{}\"", vendor_string(), brand_string());
+        serial_println!("{:?}", features);
+        assert!(matches!(
+            vendor_string().as_str(),
+            "GenuineIntel" | "AuthenticAMD" | "TCGTCGTCGTCG" | "KVMKVMKVM" | "HygonGenuine"
+        ));
+        //Every x86_64 CPU has these
+        assert!(features.sse && features.sse2 && features.tsc);
+    }
+
+    fn add_f32x4(a: [f32; 4], b: [f32; 4]) -> [f32; 4] {
+        let mut sum = [0.0; 4];
+        unsafe {
+            core::arch::asm!(
+                "movups {x}, [{a}]",
+                "movups {y}, [{b}]",
+                "addps {x}, {y}",
+                "movups [{sum}], {x}",
+                a = in(reg) a.as_ptr(),
+                b = in(reg) b.as_ptr(),
+                sum = in(reg) sum.as_mut_ptr(),
+                x = out(xmm_reg) _,
+                y = out(xmm_reg) _,
+            );
+        }
+        sum
+    }
+
+    #[test_case]
+    fn sse_enabled() {
+        assert!(Cr4::read().contains(Cr4Flags::OSFXSR));
+        assert!(!Cr0::read().contains(Cr0Flags::EMULATE_COPROCESSOR));
+        let sum = add_f32x4([1.0, 2.0, 3.0, 4.0], [0.5, 0.25, -3.0, 10.0]);
+        assert_eq!(sum, [1.5, 2.25, 0.0, 14.0]);
+        //And plain float math, which the compiler now does in XMM registers too
+        let x = core::hint::black_box(0.75f64);
+        assert_eq!(x * 4.0 - 1.0, 2.0);
+    }
+}
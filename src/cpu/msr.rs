@@ -0,0 +1,49 @@
+use core::arch::asm;
+
+pub const IA32_TSC: u32 = 0x10;
+pub const IA32_APIC_BASE: u32 = 0x1B;
+pub const IA32_PAT: u32 = 0x277;
+pub const IA32_TSC_DEADLINE: u32 = 0x6E0;
+pub const IA32_EFER: u32 = 0xC000_0080;
+pub const IA32_FS_BASE: u32 = 0xC000_0100;
+pub const IA32_GS_BASE: u32 = 0xC000_0101;
+pub const IA32_KERNEL_GS_BASE: u32 = 0xC000_0102;
+
+/// IA32_APIC_BASE bits
+pub const APIC_BASE_BSP: u64 = 1 << 8;
+pub const APIC_BASE_ENABLE: u64 = 1 << 11;
+
+/// Reads a model specific register.
+///
+/// # Safety
+///The MSR must exist on this CPU, reading an unsupported one raises a general protection fault
+pub unsafe fn read(msr: u32) -> u64 {
+    let (low, high): (u32, u32);
+    //rdmsr takes the register in ecx and returns the value split across edx:eax
+    asm!("rdmsr", in("ecx") msr, out("eax") low, out("edx") high, options(nomem, nostack, preserves_flags));
+    (high as u64) << 32 | low as u64
+}
+
+/// Writes a model specific register.
+///
+/// # Safety
+///The MSR must exist and accept `value` - reserved bits set or an unsupported MSR raise a general
+///protection fault - and changing it must not break assumptions elsewhere (e.g. EFER, the APIC base)
+pub unsafe fn write(msr: u32, value: u64) {
+    let low = value as u32;
+    let high = (value >> 32) as u32;
+    asm!("wrmsr", in("ecx") msr, in("eax") low, in("edx") high, options(nostack, preserves_flags));
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test_case]
+    fn apic_base() {
+        let apic_base = unsafe { read(IA32_APIC_BASE) };
+        //The firmware leaves the APIC globally enabled, and the tests run on the bootstrap processor
+        assert_ne!(apic_base & APIC_BASE_ENABLE, 0);
+        assert_ne!(apic_base & APIC_BASE_BSP, 0);
+    }
+}
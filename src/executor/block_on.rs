@@ -0,0 +1,47 @@
+use crate::interrupts::without_interrupts;
+use alloc::{sync::Arc, task::Wake};
+use core::{
+    future::Future,
+    pin::pin,
+    sync::atomic::{AtomicBool, Ordering},
+    task::{Context, Poll, Waker},
+};
+use x86_64::instructions::interrupts::enable_and_hlt;
+
+//Only records that a wakeup happened - block_on itself is the only task
+struct FlagWaker {
+    woken: AtomicBool,
+}
+
+impl Wake for FlagWaker {
+    fn wake(self: Arc<Self>) {
+        self.woken.store(true, Ordering::Release);
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.woken.store(true, Ordering::Release);
+    }
+}
+
+/// Drives a single future to completion without an `Executor`, halting the CPU until the next
+/// interrupt while the future is pending, and returns its output.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+    let flag = Arc::new(FlagWaker {
+        woken: AtomicBool::new(false),
+    });
+    let waker = Waker::from(flag.clone());
+    let mut context = Context::from_waker(&waker);
+
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut context) {
+            return output;
+        }
+        //Same race as Executor::sleep_if_idle - a wakeup between the check and hlt would be missed
+        without_interrupts(|| {
+            if !flag.woken.swap(false, Ordering::AcqRel) {
+                enable_and_hlt();
+            }
+        });
+    }
+}
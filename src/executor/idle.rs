@@ -0,0 +1,90 @@
+//Ticks the measurements are split into - the percentage covers the last one or two of these
+const WINDOW: u64 = 182; //about 10 seconds
+
+/// Splits the executor's time between running tasks and halting, over a rolling window.
+#[derive(Default)]
+pub(super) struct IdleTracker {
+    window_start: u64,
+    idle: u64,
+    busy: u64,
+    //totals of the window before the current one, so the percentage doesn't reset to nothing
+    previous: (u64, u64),
+}
+
+impl IdleTracker {
+    pub(super) fn new(now: u64) -> Self {
+        Self {
+            window_start: now,
+            ..Self::default()
+        }
+    }
+
+    //Tick values are differenced with wrapping_sub, so a counter wrapping between start and end
+    //still gives the right elapsed time
+    pub(super) fn record_idle(&mut self, start: u64, end: u64) {
+        self.roll(end);
+        self.idle += end.wrapping_sub(start);
+    }
+
+    pub(super) fn record_busy(&mut self, start: u64, end: u64) {
+        self.roll(end);
+        self.busy += end.wrapping_sub(start);
+    }
+
+    fn roll(&mut self, now: u64) {
+        let elapsed = now.wrapping_sub(self.window_start);
+        if elapsed < WINDOW {
+            return;
+        }
+        //More than a whole window without a measurement means the old one is stale too
+        self.previous = if elapsed < 2 * WINDOW {
+            (self.idle, self.busy)
+        } else {
+            (0, 0)
+        };
+        self.idle = 0;
+        self.busy = 0;
+        self.window_start = now;
+    }
+
+    pub(super) fn idle_percent(&self) -> Option<u8> {
+        let idle = self.idle + self.previous.0;
+        let total = idle + self.busy + self.previous.1;
+        (total > 0).then(|| (idle * 100 / total) as u8)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test_case]
+    fn idle_accounting() {
+        let mut tracker = IdleTracker::new(0);
+        assert_eq!(tracker.idle_percent(), None);
+        tracker.record_busy(0, 10);
+        tracker.record_idle(10, 40);
+        assert_eq!(tracker.idle_percent(), Some(75));
+
+        //The counter wraps in the middle of a halt
+        let mut tracker = IdleTracker::new(u64::MAX - 5);
+        tracker.record_busy(u64::MAX - 5, u64::MAX - 3);
+        tracker.record_idle(u64::MAX - 3, 2);
+        assert_eq!(tracker.idle_percent(), Some(75));
+    }
+
+    #[test_case]
+    fn idle_window_rolls() {
+        let mut tracker = IdleTracker::new(0);
+        tracker.record_idle(0, 100);
+        //Starts a new window, the last one still counts
+        tracker.record_busy(WINDOW, WINDOW + 100);
+        assert_eq!(tracker.idle_percent(), Some(50));
+        //Two windows on, only the busy one is left
+        tracker.record_busy(2 * WINDOW, 2 * WINDOW + 100);
+        assert_eq!(tracker.idle_percent(), Some(0));
+        //Measured long after, so everything before is forgotten
+        tracker.record_idle(10 * WINDOW, 10 * WINDOW + 1);
+        assert_eq!(tracker.idle_percent(), Some(100));
+    }
+}
@@ -0,0 +1,84 @@
+use alloc::boxed::Box;
+use core::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+//A child future of a combinator - boxed so the combinator itself is Unpin and needs no pin projection
+enum MaybeDone<F: Future> {
+    Pending(Pin<Box<F>>),
+    Done(Option<F::Output>),
+}
+
+impl<F: Future> MaybeDone<F> {
+    fn new(future: F) -> Self {
+        Self::Pending(Box::pin(future))
+    }
+
+    //Returns true once the child has finished - a finished child is never polled again
+    fn poll_child(&mut self, cx: &mut Context) -> bool {
+        match self {
+            Self::Pending(future) => match future.as_mut().poll(cx) {
+                Poll::Ready(output) => {
+                    *self = Self::Done(Some(output));
+                    true
+                }
+                Poll::Pending => false,
+            },
+            Self::Done(_) => true,
+        }
+    }
+
+    fn take_output(&mut self) -> F::Output {
+        match self {
+            Self::Done(output) => output.take().expect("output already taken"),
+            Self::Pending(_) => panic!("child future not done"),
+        }
+    }
+}
+
+/// Polls both futures concurrently and resolves to both outputs once they have completed.
+pub fn join2<A: Future, B: Future>(a: A, b: B) -> Join2<A, B> {
+    Join2 {
+        a: MaybeDone::new(a),
+        b: MaybeDone::new(b),
+    }
+}
+
+pub struct Join2<A: Future, B: Future> {
+    a: MaybeDone<A>,
+    b: MaybeDone<B>,
+}
+
+//Children are boxed and outputs are never pinned, so moving a Join2 is fine
+impl<A: Future, B: Future> Unpin for Join2<A, B> {}
+
+impl<A: Future, B: Future> Future for Join2<A, B> {
+    type Output = (A::Output, B::Output);
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        //Both children get the same waker, so a wakeup from either re-polls the combinator
+        let a_done = self.a.poll_child(cx);
+        let b_done = self.b.poll_child(cx);
+        if a_done && b_done {
+            Poll::Ready((self.a.take_output(), self.b.take_output()))
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+/// Awaits two or three futures concurrently, see `executor::join2`.
+#[macro_export]
+macro_rules! join {
+    ($a:expr, $b:expr $(,)?) => {
+        $crate::executor::join2($a, $b)
+    };
+    ($a:expr, $b:expr, $c:expr $(,)?) => {
+        async {
+            let ((a, b), c) = $crate::executor::join2($crate::executor::join2($a, $b), $c).await;
+            (a, b, c)
+        }
+    };
+}
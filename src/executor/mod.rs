@@ -0,0 +1,751 @@
+use crate::interrupts::{are_enabled, without_interrupts};
+use crate::serial_println;
+use alloc::boxed::Box;
+use alloc::task::Wake;
+use alloc::{
+    collections::{BTreeMap, VecDeque},
+    rc::Rc,
+    sync::Arc,
+    vec::Vec,
+};
+use core::fmt;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use core::task::{Context, Poll, Waker};
+use core::{future::Future, pin::Pin};
+use crossbeam_queue::{ArrayQueue, SegQueue};
+use spin::Mutex;
+use x86_64::instructions::interrupts::enable_and_hlt;
+
+mod block_on;
+mod idle;
+mod join;
+mod select;
+mod task_local;
+mod watchdog;
+
+pub use block_on::block_on;
+pub use join::{join2, Join2};
+pub use select::{select2, Either, Select2};
+pub use task_local::TaskLocal;
+pub use watchdog::{preemption_tick, should_yield};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TaskId(u64);
+
+impl TaskId {
+    fn new() -> Self {
+        //Make sure each ID is unique
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+        Self(NEXT_ID.fetch_add(1, Ordering::Relaxed)) //fetch_add atomically increases the value and returns the previous value in one atomic operation - this ensures each ID is returned once
+    }
+}
+
+/// Ready tasks of a higher priority are always polled before those of a lower one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    High = 0,
+    Normal = 1,
+    Low = 2,
+}
+
+type BoxFuture = Pin<Box<dyn Future<Output = ()>>>;
+
+pub struct Task {
+    id: TaskId,
+    name: &'static str, //only used for debugging output
+    priority: Priority,
+    future: BoxFuture,
+    //set while the task's id sits in a ready queue, so repeated wakeups only enqueue it once
+    queued: Arc<AtomicBool>,
+}
+
+impl Task {
+    pub fn new(future: impl Future<Output = ()> + 'static) -> Self {
+        Self::with_priority(future, Priority::Normal)
+    }
+
+    pub fn with_priority(future: impl Future<Output = ()> + 'static, priority: Priority) -> Self {
+        Self::from_boxed(Box::pin(future), priority)
+    }
+
+    //Used for futures that were already boxed, e.g. by a Spawner, to avoid boxing them twice
+    fn from_boxed(future: BoxFuture, priority: Priority) -> Self {
+        Self {
+            id: TaskId::new(), // new
+            name: "<unnamed>",
+            priority,
+            future,
+            queued: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub fn named(name: &'static str, future: impl Future<Output = ()> + 'static) -> Self {
+        Self {
+            name,
+            ..Self::new(future)
+        }
+    }
+
+    pub fn id(&self) -> TaskId {
+        self.id
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Spawns a fallible future whose `Err` only ends this task instead of the kernel.
+    ///
+    /// Only failures the future returns as `Err(E)` are recoverable: the error is logged together with the
+    /// task's id and the task is removed like any finished task. A real `panic!` inside the future is NOT
+    /// caught - without unwinding in `no_std` it still goes through the global panic handler.
+    pub fn new_isolated<E: fmt::Debug + 'static>(
+        future: impl Future<Output = Result<(), E>> + 'static,
+    ) -> Self {
+        let id = TaskId::new();
+        Self {
+            id,
+            name: "<unnamed>",
+            priority: Priority::Normal,
+            future: Box::pin(async move {
+                if let Err(err) = future.await {
+                    serial_println!("ERROR: task {:?} failed: {:?}", id, err);
+                }
+            }),
+            queued: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    fn poll(&mut self, context: &mut Context) -> Poll<()> {
+        self.future.as_mut().poll(context)
+    }
+}
+
+/// Returned by `Executor::try_spawn` when the task's ready queue is full. Carries the task back to the caller.
+pub struct SpawnError(Task);
+
+impl SpawnError {
+    pub fn into_task(self) -> Task {
+        self.0
+    }
+}
+
+impl fmt::Debug for SpawnError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("SpawnError").field(&self.0.id).finish()
+    }
+}
+
+impl fmt::Display for SpawnError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ready queue full, could not spawn task {:?}", self.0.id)
+    }
+}
+
+//How many finished tasks keep their poll count, so the counts can't grow without bound
+const FINISHED_POLLS_KEPT: usize = 64;
+
+/// Scheduler counters, kept until `Executor::reset_stats` is called.
+/// Poll counts of finished tasks are only kept for the most recent ones.
+#[derive(Default)]
+pub struct ExecutorStats {
+    polls: u64,
+    wakeups: Arc<AtomicU64>, //shared with every TaskWaker, which may run in an interrupt handler
+    task_polls: BTreeMap<TaskId, u64>, //live tasks
+    finished_polls: VecDeque<(TaskId, u64)>, //oldest first
+    overruns: u64,
+    stalls: u64,
+}
+
+impl ExecutorStats {
+    pub fn total_polls(&self) -> u64 {
+        self.polls
+    }
+
+    pub fn total_wakeups(&self) -> u64 {
+        self.wakeups.load(Ordering::Relaxed)
+    }
+
+    pub fn polls(&self, task_id: TaskId) -> u64 {
+        match self.task_polls.get(&task_id) {
+            Some(&polls) => polls,
+            None => self
+                .finished_polls
+                .iter()
+                .find(|(id, _)| *id == task_id)
+                .map_or(0, |&(_, polls)| polls),
+        }
+    }
+
+    //Moves a task's count to the finished ones, dropping the oldest past FINISHED_POLLS_KEPT
+    fn task_removed(&mut self, task_id: TaskId) {
+        if let Some(polls) = self.task_polls.remove(&task_id) {
+            if self.finished_polls.len() == FINISHED_POLLS_KEPT {
+                self.finished_polls.pop_front();
+            }
+            self.finished_polls.push_back((task_id, polls));
+        }
+    }
+
+    /// Number of polls that ran over the poll budget.
+    pub fn overruns(&self) -> u64 {
+        self.overruns
+    }
+
+    /// Number of times the executor sat idle past its idle timeout with tasks still pending.
+    pub fn stalls(&self) -> u64 {
+        self.stalls
+    }
+}
+
+struct TaskWaker {
+    task_id: TaskId,
+    task_queue: Arc<ArrayQueue<TaskId>>,
+    queued: Arc<AtomicBool>,
+    rescan: Arc<AtomicBool>,
+    wakeups: Arc<AtomicU64>,
+}
+
+impl TaskWaker {
+    fn new(
+        task_id: TaskId,
+        task_queue: Arc<ArrayQueue<TaskId>>,
+        queued: Arc<AtomicBool>,
+        rescan: Arc<AtomicBool>,
+        wakeups: Arc<AtomicU64>,
+    ) -> Waker {
+        Waker::from(Arc::new(Self {
+            task_id,
+            task_queue,
+            queued,
+            rescan,
+            wakeups,
+        }))
+    }
+
+    fn wake_task(&self) {
+        self.wakeups.fetch_add(1, Ordering::Relaxed);
+        //Already waiting to be polled - another queue entry would only waste a slot
+        if self.queued.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        //ArrayQueue type modifications only requires a shared reference
+        //A full queue loses this wakeup, so ask the executor to poll every task instead of panicking
+        if self.task_queue.push(self.task_id).is_err() {
+            self.rescan.store(true, Ordering::Release);
+        }
+    }
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_task();
+    }
+
+    //optional but can lead to better performance b.c. it avoids unnecessary reference count modifications
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.wake_task();
+    }
+}
+
+//Shared slot the spawned task writes its output into once it finishes
+struct JoinState<T> {
+    result: Option<T>,
+    waker: Option<Waker>,
+}
+
+/// Resolves to the output of a task spawned with `Executor::spawn_with_handle`.
+pub struct JoinHandle<T> {
+    state: Arc<Mutex<JoinState<T>>>,
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<T> {
+        let mut state = self.state.lock();
+        //The result outlives the task, so this still resolves if the task was already removed
+        match state.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// Cancels a task spawned with `Executor::spawn_cancellable`.
+pub struct CancelHandle {
+    task_id: TaskId,
+    cancel_queue: Arc<SegQueue<TaskId>>,
+}
+
+impl CancelHandle {
+    /// Drops the task on the executor's next scheduling pass. Cancelling a task that already completed does nothing.
+    pub fn cancel(&self) {
+        self.cancel_queue.push(self.task_id);
+    }
+}
+
+/// Cloneable handle that lets running tasks spawn new tasks onto the executor that created it.
+#[derive(Clone)]
+pub struct Spawner {
+    spawn_queue: Rc<SegQueue<BoxFuture>>, //tasks aren't Send, so neither is the handle
+}
+
+impl Spawner {
+    /// Queues the future as a new task, it starts running on the executor's next scheduling pass.
+    pub fn spawn(&self, future: impl Future<Output = ()> + 'static) {
+        self.spawn_queue.push(Box::pin(future));
+    }
+}
+
+/// Cloneable handle that asks the executor that created it to stop `run_until_shutdown`.
+/// `Send` and lock-free, so it can be used from an interrupt handler as well as from a task.
+#[derive(Clone)]
+pub struct ShutdownHandle {
+    shutdown: Arc<AtomicBool>,
+}
+
+impl ShutdownHandle {
+    pub fn request_shutdown(&self) {
+        self.shutdown.store(true, Ordering::Release);
+    }
+}
+
+/// Returns `Pending` once after waking itself, giving other ready tasks a chance to run first.
+pub fn yield_now() -> YieldNow {
+    YieldNow { yielded: false }
+}
+
+pub struct YieldNow {
+    yielded: bool,
+}
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        if self.yielded {
+            return Poll::Ready(());
+        }
+        self.yielded = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+pub struct Executor {
+    tasks: BTreeMap<TaskId, Task>,
+    task_queues: [Arc<ArrayQueue<TaskId>>; 3], //one per Priority - fixed sized ArrayQueue b.c. interrupt handlers should not allocate on push to this queue
+    //spill-over for spawns that find their ArrayQueue full - only touched by the executor itself, never by wakers
+    overflow: [VecDeque<TaskId>; 3],
+    waker_cache: BTreeMap<TaskId, Waker>,
+    //tasks are owned by the executor, so cancel requests are queued and handled in run_ready_tasks
+    //unbounded so a cancel is never dropped
+    cancel_queue: Arc<SegQueue<TaskId>>,
+    //futures queued by Spawners - a task can't call spawn itself b.c. the executor is borrowed while polling it
+    spawn_queue: Rc<SegQueue<BoxFuture>>,
+    //set by a waker whose queue was full - every task gets polled on the next pass
+    rescan: Arc<AtomicBool>,
+    //set through a ShutdownHandle, makes run_until_shutdown return
+    shutdown: Arc<AtomicBool>,
+    stats: ExecutorStats,
+    //max number of tasks polled per pass before returning to the run loop
+    budget: usize,
+    //ticks a single poll may take before the watchdog reports it, None turns the watchdog off
+    poll_budget: Option<u64>,
+    //ticks without a single poll before pending tasks are reported as stuck, None never reports
+    idle_timeout: Option<u64>,
+    last_progress: u64,   //tick of the last pass that polled anything
+    stall_reported: bool, //only reported once per idle stretch
+    idle: idle::IdleTracker,
+}
+
+const DEFAULT_BUDGET: usize = 256;
+
+impl Executor {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::with_budget(DEFAULT_BUDGET)
+    }
+
+    /// Creates an executor that polls at most `budget` tasks per scheduling pass, so a burst of
+    /// self-waking tasks can't keep the run loop from reaching `sleep_if_idle`.
+    #[must_use]
+    pub fn with_budget(budget: usize) -> Self {
+        assert!(budget > 0, "executor budget must be non-zero");
+        Self {
+            tasks: BTreeMap::new(),
+            task_queues: [
+                Arc::new(ArrayQueue::new(100)),
+                Arc::new(ArrayQueue::new(100)),
+                Arc::new(ArrayQueue::new(100)),
+            ],
+            overflow: [VecDeque::new(), VecDeque::new(), VecDeque::new()],
+            waker_cache: BTreeMap::new(),
+            cancel_queue: Arc::new(SegQueue::new()),
+            spawn_queue: Rc::new(SegQueue::new()),
+            rescan: Arc::new(AtomicBool::new(false)),
+            shutdown: Arc::new(AtomicBool::new(false)),
+            stats: ExecutorStats::default(),
+            budget,
+            poll_budget: None,
+            idle_timeout: None,
+            last_progress: crate::time::ticks(),
+            stall_reported: false,
+            idle: idle::IdleTracker::new(crate::time::ticks()),
+        }
+    }
+
+    /// Sets how many ticks the executor may go without polling anything while tasks are pending
+    /// before it reports them over serial as likely deadlocked. Off (None) by default, since only
+    /// the time since the last poll is measured - a task waiting on a keyboard read or a long
+    /// `Delay` looks the same as one that will never be woken.
+    pub fn set_idle_timeout(&mut self, ticks: Option<u64>) {
+        assert!(ticks != Some(0), "idle timeout must be non-zero");
+        self.idle_timeout = ticks;
+    }
+
+    /// Sets how many timer ticks a single poll may run before the task is reported over serial.
+    /// While over budget, `should_yield` tells the task to give up the CPU.
+    pub fn set_poll_budget(&mut self, ticks: Option<u64>) {
+        assert!(ticks != Some(0), "poll budget must be non-zero");
+        self.poll_budget = ticks;
+    }
+
+    /// A handle for tasks and interrupt handlers to stop `run_until_shutdown` with, since neither
+    /// can reach the executor itself.
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        ShutdownHandle {
+            shutdown: self.shutdown.clone(),
+        }
+    }
+
+    pub fn request_shutdown(&self) {
+        self.shutdown.store(true, Ordering::Release);
+    }
+
+    /// Spawns the task, queueing it on a heap-allocated overflow queue once the fixed ready queue is full.
+    /// Overflowed tasks are moved into the fixed queue as it drains, so the 101st ready task still runs.
+    pub fn spawn(&mut self, task: Task) {
+        if let Err(SpawnError(task)) = self.try_spawn(task) {
+            //The overflow allocates, so it must never be reached from an interrupt handler
+            debug_assert!(are_enabled(), "spawn overflowed from interrupt context");
+            self.overflow[task.priority as usize].push_back(task.id);
+            self.tasks.insert(task.id, task);
+        }
+    }
+
+    /// Spawns the task only if there's room in its fixed ready queue, handing it back otherwise.
+    pub fn try_spawn(&mut self, task: Task) -> Result<(), SpawnError> {
+        if self.tasks.contains_key(&task.id) {
+            panic!("task with same ID already in tasks");
+        }
+        if self.task_queues[task.priority as usize]
+            .push(task.id)
+            .is_err()
+        {
+            return Err(SpawnError(task));
+        }
+        self.tasks.insert(task.id, task);
+        Ok(())
+    }
+
+    pub fn spawner(&self) -> Spawner {
+        Spawner {
+            spawn_queue: self.spawn_queue.clone(),
+        }
+    }
+
+    pub fn spawn_with_handle<T: 'static>(
+        &mut self,
+        future: impl Future<Output = T> + 'static,
+    ) -> JoinHandle<T> {
+        let state = Arc::new(Mutex::new(JoinState {
+            result: None,
+            waker: None,
+        }));
+        let task_state = state.clone();
+        self.spawn(Task::new(async move {
+            let result = future.await;
+            let waker = {
+                let mut state = task_state.lock();
+                state.result = Some(result);
+                state.waker.take()
+            };
+            //Wake outside of the lock so the handle can be polled right away
+            if let Some(waker) = waker {
+                waker.wake();
+            }
+        }));
+        JoinHandle { state }
+    }
+
+    pub fn spawn_cancellable(&mut self, task: Task) -> CancelHandle {
+        let task_id = task.id;
+        self.spawn(task);
+        CancelHandle {
+            task_id,
+            cancel_queue: self.cancel_queue.clone(),
+        }
+    }
+
+    fn cancel_tasks(&mut self) {
+        while let Ok(task_id) = self.cancel_queue.pop() {
+            //Dropping the task runs the future's destructor - None means it already completed
+            if self.tasks.remove(&task_id).is_some() {
+                self.stats.task_removed(task_id);
+            }
+            self.waker_cache.remove(&task_id);
+            task_local::clear(task_id);
+        }
+    }
+
+    fn poll_task(&mut self, task_id: TaskId) {
+        let task = match self.tasks.get_mut(&task_id) {
+            Some(task) => task,
+            None => return, // task no longer exists
+        };
+        //The waker re-enqueues onto the queue matching the task's priority
+        let task_queue = &self.task_queues[task.priority as usize];
+        let rescan = &self.rescan;
+        let wakeups = &self.stats.wakeups;
+        let waker = self.waker_cache.entry(task_id).or_insert_with(|| {
+            TaskWaker::new(
+                task_id,
+                task_queue.clone(),
+                task.queued.clone(),
+                rescan.clone(),
+                wakeups.clone(),
+            )
+        });
+        //Cleared before polling so a wakeup during the poll queues the task again
+        task.queued.store(false, Ordering::Release);
+        let mut context = Context::from_waker(waker);
+        self.stats.polls += 1;
+        *self.stats.task_polls.entry(task_id).or_insert(0) += 1;
+        let previous_task = task_local::set_current(Some(task_id));
+        let watchdog = watchdog::begin_poll(self.poll_budget);
+        let poll = task.poll(&mut context);
+        let elapsed = watchdog::end_poll(watchdog);
+        task_local::set_current(previous_task);
+        if let Some(budget) = self.poll_budget.filter(|&budget| elapsed > budget) {
+            self.stats.overruns += 1;
+            serial_println!(
+                "WARNING: task {:?} ({}) ran for {} ticks without yielding, budget is {}",
+                task_id,
+                task.name,
+                elapsed,
+                budget
+            );
+        }
+        match poll {
+            Poll::Ready(()) => {
+                // task done -> remove it, its cached waker and its task locals
+                self.tasks.remove(&task_id);
+                self.waker_cache.remove(&task_id);
+                task_local::clear(task_id);
+                self.stats.task_removed(task_id);
+            }
+            Poll::Pending => {}
+        }
+    }
+
+    //Moves overflowed tasks into the fixed queues as far as there is room
+    fn drain_overflow(&mut self) {
+        for (overflow, task_queue) in self.overflow.iter_mut().zip(&self.task_queues) {
+            while let Some(&task_id) = overflow.front() {
+                if task_queue.push(task_id).is_err() {
+                    break;
+                }
+                overflow.pop_front();
+            }
+        }
+    }
+
+    /// Runs a single scheduling pass and returns the number of tasks polled, which only exceeds the budget
+    /// when a rescan of all tasks was requested.
+    /// Tasks still queued once the budget is used up are polled first on the next pass.
+    pub fn run_ready_tasks(&mut self) -> usize {
+        while let Ok(future) = self.spawn_queue.pop() {
+            self.spawn(Task::from_boxed(future, Priority::Normal));
+        }
+        self.cancel_tasks();
+        self.drain_overflow();
+        //Only poll the tasks that were ready when the pass started - a task that wakes itself while
+        //being polled (e.g. yield_now) goes back on its queue and runs again on the next pass
+        let ready: [usize; 3] = core::array::from_fn(|priority| self.task_queues[priority].len());
+        let mut polled = 0;
+        //Queues are ordered by priority, so High tasks are polled before Normal before Low
+        'pass: for (priority, count) in ready.into_iter().enumerate() {
+            for _ in 0..count {
+                if polled == self.budget {
+                    break 'pass;
+                }
+                match self.task_queues[priority].pop() {
+                    Ok(task_id) => self.poll_task(task_id),
+                    Err(_) => break,
+                }
+                polled += 1;
+            }
+        }
+        //Some wakeup was dropped on a full queue, so poll everything - a spurious poll is harmless
+        //Rare enough that it isn't held to the budget, which could otherwise delay it indefinitely
+        if self.rescan.swap(false, Ordering::Acquire) {
+            let task_ids: Vec<TaskId> = self.tasks.keys().copied().collect();
+            polled += task_ids.len();
+            for task_id in task_ids {
+                self.poll_task(task_id);
+            }
+        }
+        if polled > 0 {
+            self.last_progress = crate::time::ticks();
+            self.stall_reported = false;
+        }
+        polled
+    }
+
+    //Nothing was polled for the whole idle timeout, yet tasks are left - nothing may ever wake them
+    fn check_stalled(&mut self) {
+        let idle = crate::time::ticks() - self.last_progress;
+        let timeout = match self.idle_timeout {
+            Some(timeout) => timeout,
+            None => return,
+        };
+        if self.stall_reported || self.tasks.is_empty() || idle < timeout {
+            return;
+        }
+        self.stall_reported = true;
+        self.stats.stalls += 1;
+        serial_println!(
+            "WARNING: executor idle for {} ticks, {} task(s) may never be woken:",
+            idle,
+            self.tasks.len()
+        );
+        for (id, task) in &self.tasks {
+            serial_println!("  {:?} {}", id, task.name);
+        }
+    }
+
+    fn has_ready_tasks(&self) -> bool {
+        self.task_queues.iter().any(|queue| !queue.is_empty())
+            || self.overflow.iter().any(|queue| !queue.is_empty())
+            || !self.cancel_queue.is_empty()
+            || !self.spawn_queue.is_empty()
+            || self.rescan.load(Ordering::Acquire)
+    }
+
+    fn sleep_if_idle(&mut self) {
+        let start = crate::time::ticks();
+        //Checked with interrupts off so a wakeup can't land between the check and hlt -
+        //enable_and_hlt turns them back on atomically with halting
+        without_interrupts(|| {
+            if !self.has_ready_tasks() && !self.shutdown.load(Ordering::Acquire) {
+                enable_and_hlt();
+            }
+        });
+        self.idle.record_idle(start, crate::time::ticks());
+    }
+
+    //A pass of run_ready_tasks, counted as busy time
+    fn run_pass(&mut self) {
+        let start = crate::time::ticks();
+        self.run_ready_tasks();
+        self.idle.record_busy(start, crate::time::ticks());
+    }
+
+    /// How much of the last 10-20 seconds the executor spent halted waiting for work rather than
+    /// running tasks, None before anything was measured. Measured in whole ticks, so it's only
+    /// meaningful over many of them.
+    pub fn idle_percent(&self) -> Option<u8> {
+        self.idle.idle_percent()
+    }
+
+    pub fn run(&mut self) -> ! {
+        loop {
+            self.run_pass();
+            self.check_stalled();
+            self.sleep_if_idle()
+        }
+    }
+
+    /// Like `run`, but returns once a shutdown was requested and the tasks that were ready at that
+    /// point have had their poll. Tasks that haven't completed stay spawned, and the request is
+    /// cleared so the executor can be run again.
+    pub fn run_until_shutdown(&mut self) {
+        loop {
+            self.run_pass();
+            //Swapped after the pass, so a request made during it still lets the pass finish
+            if self.shutdown.swap(false, Ordering::AcqRel) {
+                return;
+            }
+            self.check_stalled();
+            self.sleep_if_idle()
+        }
+    }
+
+    /// Number of spawned tasks that haven't completed or been cancelled yet.
+    pub fn task_count(&self) -> usize {
+        self.tasks.len()
+    }
+
+    /// True once there are no tasks left and nothing queued to run.
+    pub fn is_idle(&self) -> bool {
+        self.tasks.is_empty() && !self.has_ready_tasks()
+    }
+
+    pub fn stats(&self) -> &ExecutorStats {
+        &self.stats
+    }
+
+    pub fn reset_stats(&mut self) {
+        self.stats.polls = 0;
+        //Existing wakers keep their handle to the counter, so clear it in place
+        self.stats.wakeups.store(0, Ordering::Relaxed);
+        self.stats.task_polls.clear();
+        self.stats.finished_polls.clear();
+        self.stats.overruns = 0;
+        self.stats.stalls = 0;
+    }
+
+    /// Prints the id and name of every task that hasn't completed yet.
+    pub fn dump_tasks(&self) {
+        serial_println!("{} live task(s):", self.tasks.len());
+        for (id, task) in &self.tasks {
+            serial_println!("  {:?} {}", id, task.name);
+        }
+    }
+
+    //Need access to run_ready_tasks to actually finish the test instead of looping endlessly
+    //Runs passes until no task is ready, tasks waiting on an interrupt are left pending
+    pub fn test_run(&mut self) {
+        while self.has_ready_tasks() {
+            self.run_ready_tasks();
+        }
+        self.check_stalled();
+    }
+}
+
+impl Default for Executor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::ShouldPanic;
+
+    #[test_case]
+    static DUPLICATE_TASK_ID: ShouldPanic = ShouldPanic::new("duplicate_task_id", || {
+        let mut executor = Executor::new();
+        let task = Task::new(async {});
+        let mut duplicate = Task::new(async {});
+        duplicate.id = task.id;
+        executor.spawn(task);
+        executor.spawn(duplicate);
+    });
+}
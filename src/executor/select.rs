@@ -0,0 +1,49 @@
+use alloc::boxed::Box;
+use core::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+/// Output of `select2`, tagged with the branch that finished first.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Either<A, B> {
+    Left(A),
+    Right(B),
+}
+
+/// Polls both futures and resolves to the output of whichever completes first, dropping the other one.
+/// If both are ready on the same poll, `a` wins.
+pub fn select2<A: Future, B: Future>(a: A, b: B) -> Select2<A, B> {
+    Select2 {
+        inner: Some((Box::pin(a), Box::pin(b))),
+    }
+}
+
+type Branches<A, B> = (Pin<Box<A>>, Pin<Box<B>>);
+
+pub struct Select2<A: Future, B: Future> {
+    inner: Option<Branches<A, B>>, //None once a branch finished and both were dropped
+}
+
+impl<A: Future, B: Future> Future for Select2<A, B> {
+    type Output = Either<A::Output, B::Output>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let (a, b) = self
+            .inner
+            .as_mut()
+            .expect("Select2 polled after completion");
+        //Both children get the same waker, so a wakeup from either re-polls the combinator
+        let output = if let Poll::Ready(output) = a.as_mut().poll(cx) {
+            Either::Left(output)
+        } else if let Poll::Ready(output) = b.as_mut().poll(cx) {
+            Either::Right(output)
+        } else {
+            return Poll::Pending;
+        };
+        //Drop the losing future right away so its destructor runs before the caller continues
+        self.inner = None;
+        Poll::Ready(output)
+    }
+}
@@ -0,0 +1,63 @@
+use super::TaskId;
+use alloc::{boxed::Box, collections::BTreeMap, vec::Vec};
+use core::any::Any;
+use spin::Mutex;
+
+//Set by the executor around each poll, so code running inside a task can find its slots
+static CURRENT_TASK: Mutex<Option<TaskId>> = Mutex::new(None);
+//Keyed by the owning task and the address of the TaskLocal static
+static STORE: Mutex<BTreeMap<(TaskId, usize), Box<dyn Any + Send>>> = Mutex::new(BTreeMap::new());
+
+/// Per-task storage, declared with `task_local!`. Each task lazily gets its own value on first access.
+pub struct TaskLocal<T: 'static> {
+    init: fn() -> T,
+}
+
+impl<T: Send + 'static> TaskLocal<T> {
+    #[doc(hidden)]
+    pub const fn new(init: fn() -> T) -> Self {
+        Self { init }
+    }
+
+    /// Runs `f` on the current task's value. Panics when called outside of a task being polled.
+    pub fn with<R>(&'static self, f: impl FnOnce(&mut T) -> R) -> R {
+        let task_id = CURRENT_TASK
+            .lock()
+            .expect("TaskLocal accessed outside of a task");
+        let key = (task_id, self as *const Self as usize);
+        //The value is taken out while f runs so f can access other task locals without deadlocking
+        let mut value = STORE
+            .lock()
+            .remove(&key)
+            .unwrap_or_else(|| Box::new((self.init)()));
+        let result = f(value.downcast_mut().expect("task local type mismatch"));
+        STORE.lock().insert(key, value);
+        result
+    }
+}
+
+//Returns the previous task so nested polls (e.g. block_on inside a task) can restore it
+pub(super) fn set_current(task_id: Option<TaskId>) -> Option<TaskId> {
+    core::mem::replace(&mut *CURRENT_TASK.lock(), task_id)
+}
+
+//Drops every slot of a task that was removed from the executor
+pub(super) fn clear(task_id: TaskId) {
+    let mut store = STORE.lock();
+    let keys: Vec<_> = store
+        .range((task_id, 0)..=(task_id, usize::MAX))
+        .map(|(key, _)| *key)
+        .collect();
+    for key in keys {
+        store.remove(&key);
+    }
+}
+
+/// Declares a `TaskLocal` static, e.g. `task_local! { static COUNTER: u32 = 0; }`
+#[macro_export]
+macro_rules! task_local {
+    ($(#[$attr:meta])* $vis:vis static $name:ident: $t:ty = $init:expr;) => {
+        $(#[$attr])*
+        $vis static $name: $crate::executor::TaskLocal<$t> = $crate::executor::TaskLocal::new(|| $init);
+    };
+}
@@ -0,0 +1,51 @@
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+const NOT_POLLING: u64 = u64::MAX;
+
+//Tick the poll in progress started at and the budget it runs under (0 for none)
+static POLL_START: AtomicU64 = AtomicU64::new(NOT_POLLING);
+static POLL_BUDGET: AtomicU64 = AtomicU64::new(0);
+//Set by the timer interrupt once the poll in progress has gone over its budget
+static PREEMPT: AtomicBool = AtomicBool::new(false);
+
+/// The watchdog state a poll replaced, handed back to `end_poll` so nested polls restore it.
+pub(super) struct PollGuard {
+    start: u64,
+    budget: u64,
+    preempt: bool,
+}
+
+pub(super) fn begin_poll(budget: Option<u64>) -> PollGuard {
+    let guard = PollGuard {
+        start: POLL_START.load(Ordering::Relaxed),
+        budget: POLL_BUDGET.load(Ordering::Relaxed),
+        preempt: PREEMPT.swap(false, Ordering::Relaxed),
+    };
+    POLL_BUDGET.store(budget.unwrap_or(0), Ordering::Relaxed);
+    POLL_START.store(crate::time::ticks(), Ordering::Relaxed);
+    guard
+}
+
+/// Returns how many ticks the poll took.
+pub(super) fn end_poll(guard: PollGuard) -> u64 {
+    let elapsed = crate::time::ticks() - POLL_START.load(Ordering::Relaxed);
+    POLL_START.store(guard.start, Ordering::Relaxed);
+    POLL_BUDGET.store(guard.budget, Ordering::Relaxed);
+    PREEMPT.store(guard.preempt, Ordering::Relaxed);
+    elapsed
+}
+
+/// Called from the timer interrupt with the new tick count.
+pub fn preemption_tick(now: u64) {
+    let start = POLL_START.load(Ordering::Relaxed);
+    let budget = POLL_BUDGET.load(Ordering::Relaxed);
+    if start != NOT_POLLING && budget != 0 && now - start > budget {
+        PREEMPT.store(true, Ordering::Relaxed);
+    }
+}
+
+/// A preemption point: true once the task being polled has run past the executor's poll budget,
+/// so a long running loop can check this and `yield_now().await`.
+pub fn should_yield() -> bool {
+    PREEMPT.load(Ordering::Relaxed)
+}
@@ -0,0 +1,146 @@
+//! Entry stubs for the fatal faults that capture every general purpose register before any
+//! Rust code gets to clobber them, so the panic can show the full machine state.
+use core::arch::global_asm;
+use core::fmt;
+
+/// The registers as the entry stubs leave them on the stack, lowest address first.
+/// From `error_code` on it's what the CPU pushed itself.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Registers {
+    pub r15: u64,
+    pub r14: u64,
+    pub r13: u64,
+    pub r12: u64,
+    pub r11: u64,
+    pub r10: u64,
+    pub r9: u64,
+    pub r8: u64,
+    pub rbp: u64,
+    pub rdi: u64,
+    pub rsi: u64,
+    pub rdx: u64,
+    pub rcx: u64,
+    pub rbx: u64,
+    pub rax: u64,
+    pub error_code: u64,
+    pub rip: u64,
+    pub cs: u64,
+    pub rflags: u64,
+    pub rsp: u64,
+    pub ss: u64,
+}
+
+impl fmt::Display for Registers {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let rows = [
+            [("RAX", self.rax), ("RBX", self.rbx), ("RCX", self.rcx)],
+            [("RDX", self.rdx), ("RSI", self.rsi), ("RDI", self.rdi)],
+            [("RBP", self.rbp), ("RSP", self.rsp), (" R8", self.r8)],
+            [(" R9", self.r9), ("R10", self.r10), ("R11", self.r11)],
+            [("R12", self.r12), ("R13", self.r13), ("R14", self.r14)],
+        ];
+        for row in rows {
+            let [(a, a_value), (b, b_value), (c, c_value)] = row;
+            writeln!(
+                f,
+                "{}={:016x} {}={:016x} {}={:016x}",
+                a, a_value, b, b_value, c, c_value
+            )?;
+        }
+        writeln!(
+            f,
+            "R15={:016x} RIP={:016x} RFL={:016x}",
+            self.r15, self.rip, self.rflags
+        )?;
+        write!(
+            f,
+            " CS={:04x} SS={:04x} ERR={:#x}",
+            self.cs, self.ss, self.error_code
+        )
+    }
+}
+
+//Which fault a stub was entered for, passed on to fatal_fault
+const DOUBLE_FAULT: u64 = 0;
+const GENERAL_PROTECTION: u64 = 1;
+
+extern "C" {
+    fn double_fault_entry();
+    fn general_protection_entry();
+}
+
+//Both faults push an error code, so the stack holds a full Registers once the GPRs are pushed.
+//That's 21 qwords below the 16 byte aligned frame, so 8 more keep the call aligned
+macro_rules! fault_entry {
+    ($name:literal, $kind:expr) => {
+        global_asm!(
+            concat!(".global ", $name),
+            concat!($name, ":"),
+            "push rax",
+            "push rbx",
+            "push rcx",
+            "push rdx",
+            "push rsi",
+            "push rdi",
+            "push rbp",
+            "push r8",
+            "push r9",
+            "push r10",
+            "push r11",
+            "push r12",
+            "push r13",
+            "push r14",
+            "push r15",
+            "mov rdi, rsp",
+            "mov rsi, {kind}",
+            "sub rsp, 8",
+            "call {handler}",
+            kind = const $kind,
+            handler = sym fatal_fault,
+        );
+    };
+}
+
+fault_entry!("double_fault_entry", DOUBLE_FAULT);
+fault_entry!("general_protection_entry", GENERAL_PROTECTION);
+
+extern "C" fn fatal_fault(registers: &Registers, kind: u64) -> ! {
+    let name = match kind {
+        DOUBLE_FAULT => "DOUBLE FAULT",
+        _ => "GENERAL PROTECTION FAULT",
+    };
+    panic!("EXCEPTION: {}\n{}", name, registers);
+}
+
+/// Addresses of the double fault and general protection fault entry stubs, for the IDT.
+pub fn entry_points() -> (u64, u64) {
+    (
+        double_fault_entry as unsafe extern "C" fn() as usize as u64,
+        general_protection_entry as unsafe extern "C" fn() as usize as u64,
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use alloc::{format, vec::Vec};
+
+    #[test_case]
+    fn register_layout() {
+        let registers = Registers {
+            rax: 0x1234,
+            r15: u64::MAX,
+            rip: 0xFFFF_8000_0010_0000,
+            cs: 0x8,
+            error_code: 0x18,
+            ..Registers::default()
+        };
+        let dump = format!("{}", registers);
+        let lines: Vec<&str> = dump.lines().collect();
+        assert_eq!(lines.len(), 7);
+        assert!(lines[0].starts_with("RAX=0000000000001234 RBX=0000000000000000"));
+        assert!(lines[5].starts_with("R15=ffffffffffffffff RIP=ffff800000100000"));
+        assert_eq!(lines[6], " CS=0008 SS=0000 ERR=0x18");
+    }
+}
@@ -0,0 +1,183 @@
+use super::{ramfs::RAMFS, FsError};
+use alloc::{string::String, vec::Vec};
+use bitflags::bitflags;
+use spin::Mutex;
+
+bitflags! {
+    /// How a file is opened
+    pub struct OpenFlags: u8 {
+        const READ = 1;
+        const WRITE = 1 << 1;
+        const CREATE = 1 << 2; //create the file if it doesn't exist
+        const TRUNCATE = 1 << 3;
+        const APPEND = 1 << 4; //every write goes to the end of the file
+    }
+}
+
+/// A file descriptor returned by `open`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Fd(pub usize);
+
+struct OpenFile {
+    path: String,
+    flags: OpenFlags,
+    cursor: usize,
+}
+
+//Indexed by descriptor - closed slots are reused lowest first, like POSIX
+static DESCRIPTORS: Mutex<Vec<Option<OpenFile>>> = Mutex::new(Vec::new());
+
+fn with_file<R>(fd: Fd, f: impl FnOnce(&mut OpenFile) -> Result<R, FsError>) -> Result<R, FsError> {
+    let mut descriptors = DESCRIPTORS.lock();
+    match descriptors.get_mut(fd.0) {
+        Some(Some(file)) => f(file),
+        _ => Err(FsError::BadDescriptor),
+    }
+}
+
+pub fn open(path: &str, flags: OpenFlags) -> Result<Fd, FsError> {
+    {
+        //Lock order is always descriptors then filesystem, so the filesystem lock is dropped first
+        let mut fs = RAMFS.lock();
+        let contents = fs.contents_mut(path, flags.contains(OpenFlags::CREATE))?;
+        if flags.contains(OpenFlags::TRUNCATE) {
+            contents.clear();
+        }
+    }
+
+    let file = OpenFile {
+        path: String::from(path),
+        flags,
+        cursor: 0,
+    };
+    let mut descriptors = DESCRIPTORS.lock();
+    let fd = match descriptors.iter().position(Option::is_none) {
+        Some(fd) => {
+            descriptors[fd] = Some(file);
+            fd
+        }
+        None => {
+            descriptors.push(Some(file));
+            descriptors.len() - 1
+        }
+    };
+    Ok(Fd(fd))
+}
+
+/// Reads from the cursor into `buf`, returning how many bytes were read. 0 at the end of the file.
+pub fn read(fd: Fd, buf: &mut [u8]) -> Result<usize, FsError> {
+    with_file(fd, |file| {
+        if !file.flags.contains(OpenFlags::READ) {
+            return Err(FsError::PermissionDenied);
+        }
+        let mut fs = RAMFS.lock();
+        let contents = fs.contents_mut(&file.path, false)?;
+        //A cursor seeked past the end reads nothing, just like one at the end
+        if file.cursor >= contents.len() {
+            return Ok(0);
+        }
+        let count = (contents.len() - file.cursor).min(buf.len());
+        buf[..count].copy_from_slice(&contents[file.cursor..file.cursor + count]);
+        file.cursor += count;
+        Ok(count)
+    })
+}
+
+/// Writes `buf` at the cursor, extending the file (zero filled) if the cursor is past its end.
+pub fn write(fd: Fd, buf: &[u8]) -> Result<usize, FsError> {
+    with_file(fd, |file| {
+        if !file.flags.contains(OpenFlags::WRITE) {
+            return Err(FsError::PermissionDenied);
+        }
+        let mut fs = RAMFS.lock();
+        let contents = fs.contents_mut(&file.path, false)?;
+        if file.flags.contains(OpenFlags::APPEND) {
+            file.cursor = contents.len();
+        }
+        let end = file.cursor + buf.len();
+        if contents.len() < end {
+            contents.resize(end, 0);
+        }
+        contents[file.cursor..end].copy_from_slice(buf);
+        file.cursor = end;
+        Ok(buf.len())
+    })
+}
+
+/// Moves the cursor to `pos` bytes from the start. Seeking past the end is allowed.
+pub fn seek(fd: Fd, pos: usize) -> Result<(), FsError> {
+    with_file(fd, |file| {
+        file.cursor = pos;
+        Ok(())
+    })
+}
+
+pub fn close(fd: Fd) -> Result<(), FsError> {
+    let mut descriptors = DESCRIPTORS.lock();
+    match descriptors.get_mut(fd.0) {
+        Some(slot @ Some(_)) => {
+            *slot = None;
+            Ok(())
+        }
+        _ => Err(FsError::BadDescriptor),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fs::ramfs;
+
+    #[test_case]
+    fn cursor_semantics() {
+        ramfs::create_dir_all("/tmp/fd_test").unwrap();
+        let fd = open(
+            "/tmp/fd_test/file",
+            OpenFlags::READ | OpenFlags::WRITE | OpenFlags::CREATE,
+        )
+        .unwrap();
+        assert_eq!(write(fd, b"hello world").unwrap(), 11);
+
+        let mut buf = [0; 5];
+        //the cursor is at the end after writing
+        assert_eq!(read(fd, &mut buf).unwrap(), 0);
+        seek(fd, 6).unwrap();
+        assert_eq!(read(fd, &mut buf).unwrap(), 5);
+        assert_eq!(&buf, b"world");
+
+        //writing past the end extends the file, with a gap of zeroes
+        seek(fd, 13).unwrap();
+        write(fd, b"!").unwrap();
+        assert_eq!(
+            ramfs::read("/tmp/fd_test/file").unwrap(),
+            b"hello world\0\0!"
+        );
+        //reading past the end gives nothing rather than failing
+        seek(fd, 100).unwrap();
+        assert_eq!(read(fd, &mut buf).unwrap(), 0);
+
+        close(fd).unwrap();
+        assert_eq!(read(fd, &mut buf), Err(FsError::BadDescriptor));
+        assert_eq!(close(fd), Err(FsError::BadDescriptor));
+    }
+
+    #[test_case]
+    fn open_flags() {
+        ramfs::create_dir_all("/tmp/fd_test").unwrap();
+        assert_eq!(
+            open("/tmp/fd_test/missing", OpenFlags::READ),
+            Err(FsError::NotFound)
+        );
+        ramfs::write("/tmp/fd_test/log", b"one").unwrap();
+
+        let fd = open("/tmp/fd_test/log", OpenFlags::WRITE | OpenFlags::APPEND).unwrap();
+        write(fd, b"two").unwrap();
+        assert_eq!(read(fd, &mut [0; 4]), Err(FsError::PermissionDenied));
+        close(fd).unwrap();
+        assert_eq!(ramfs::read("/tmp/fd_test/log").unwrap(), b"onetwo");
+
+        let fd = open("/tmp/fd_test/log", OpenFlags::WRITE | OpenFlags::TRUNCATE).unwrap();
+        close(fd).unwrap();
+        assert!(ramfs::read("/tmp/fd_test/log").unwrap().is_empty());
+    }
+}
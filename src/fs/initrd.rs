@@ -0,0 +1,122 @@
+//! Initial ramdisk archives.
+//!
+//! The format is a 4 byte magic followed by entries until the end of the data. Each entry
+//! is a little endian u16 path length, a little endian u32 data length, then the absolute
+//! path (UTF-8) and the file contents. Parent directories are created as needed.
+use super::{ramfs::RAMFS, FsError};
+use alloc::vec::Vec;
+use core::{convert::TryInto, fmt, str};
+
+pub const MAGIC: &[u8; 4] = b"FINR";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InitrdError {
+    BadMagic,
+    Truncated,   //an entry runs past the end of the data
+    InvalidUtf8, //a path isn't UTF-8
+    Fs(FsError),
+}
+
+impl fmt::Display for InitrdError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InitrdError::BadMagic => f.write_str("not an initrd archive"),
+            InitrdError::Truncated => f.write_str("truncated initrd archive"),
+            InitrdError::InvalidUtf8 => f.write_str("initrd path is not UTF-8"),
+            InitrdError::Fs(error) => write!(f, "initrd: {}", error),
+        }
+    }
+}
+
+fn take<'a>(data: &mut &'a [u8], len: usize) -> Result<&'a [u8], InitrdError> {
+    if data.len() < len {
+        return Err(InitrdError::Truncated);
+    }
+    let (head, tail) = data.split_at(len);
+    *data = tail;
+    Ok(head)
+}
+
+fn parse(data: &[u8]) -> Result<Vec<(&str, &[u8])>, InitrdError> {
+    let mut data = data
+        .strip_prefix(MAGIC.as_ref())
+        .ok_or(InitrdError::BadMagic)?;
+    let mut entries = Vec::new();
+    while !data.is_empty() {
+        let path_len = u16::from_le_bytes(take(&mut data, 2)?.try_into().unwrap());
+        let data_len = u32::from_le_bytes(take(&mut data, 4)?.try_into().unwrap());
+        let path = str::from_utf8(take(&mut data, path_len as usize)?)
+            .map_err(|_| InitrdError::InvalidUtf8)?;
+        entries.push((path, take(&mut data, data_len as usize)?));
+    }
+    Ok(entries)
+}
+
+/// Unpacks an initrd archive into the ramfs, returning how many files it held.
+/// The whole archive is validated first so a corrupt one leaves the ramfs untouched.
+pub fn mount_initrd(data: &[u8]) -> Result<usize, InitrdError> {
+    let entries = parse(data)?;
+    let mut fs = RAMFS.lock();
+    for &(path, contents) in &entries {
+        if let Some((parent, _)) = path.rsplit_once('/') {
+            if !parent.is_empty() {
+                fs.create_dir_all(parent).map_err(InitrdError::Fs)?;
+            }
+        }
+        fs.write(path, contents).map_err(InitrdError::Fs)?;
+    }
+    Ok(entries.len())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fs::ramfs;
+
+    fn entry(archive: &mut Vec<u8>, path: &str, data: &[u8]) {
+        archive.extend_from_slice(&(path.len() as u16).to_le_bytes());
+        archive.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        archive.extend_from_slice(path.as_bytes());
+        archive.extend_from_slice(data);
+    }
+
+    #[test_case]
+    fn mount_archive() {
+        let mut archive = Vec::from(MAGIC.as_ref());
+        entry(&mut archive, "/initrd_test/config", b"level=debug");
+        entry(
+            &mut archive,
+            "/initrd_test/fonts/8x16.psf",
+            &[0x36, 0x04, 0, 16],
+        );
+        entry(&mut archive, "/initrd_test/empty", b"");
+
+        assert_eq!(mount_initrd(&archive), Ok(3));
+        assert_eq!(ramfs::read("/initrd_test/config").unwrap(), b"level=debug");
+        assert_eq!(
+            ramfs::read("/initrd_test/fonts/8x16.psf").unwrap(),
+            [0x36, 0x04, 0, 16]
+        );
+        assert!(ramfs::read("/initrd_test/empty").unwrap().is_empty());
+    }
+
+    #[test_case]
+    fn corrupt_archive() {
+        assert_eq!(mount_initrd(b"TAR!"), Err(InitrdError::BadMagic));
+
+        let mut archive = Vec::from(MAGIC.as_ref());
+        entry(&mut archive, "/initrd_corrupt/first", b"ok");
+        entry(&mut archive, "/initrd_corrupt/second", b"cut short");
+        archive.truncate(archive.len() - 3);
+        assert_eq!(mount_initrd(&archive), Err(InitrdError::Truncated));
+        //nothing is unpacked from a corrupt archive
+        assert_eq!(ramfs::list("/initrd_corrupt"), Err(FsError::NotFound));
+
+        let mut archive = Vec::from(MAGIC.as_ref());
+        entry(&mut archive, "relative", b"");
+        assert_eq!(
+            mount_initrd(&archive),
+            Err(InitrdError::Fs(FsError::InvalidPath))
+        );
+    }
+}
@@ -0,0 +1,36 @@
+use core::fmt;
+
+mod fd;
+mod initrd;
+pub mod ramfs;
+
+pub use fd::{close, open, read, seek, write, Fd, OpenFlags};
+pub use initrd::{mount_initrd, InitrdError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FsError {
+    NotFound,
+    AlreadyExists,
+    NotADirectory,
+    IsADirectory,
+    DirectoryNotEmpty,
+    InvalidPath, //paths have to be absolute
+    BadDescriptor,
+    PermissionDenied, //read or write on a descriptor not opened for it
+}
+
+impl fmt::Display for FsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let message = match self {
+            FsError::NotFound => "no such file or directory",
+            FsError::AlreadyExists => "file exists",
+            FsError::NotADirectory => "not a directory",
+            FsError::IsADirectory => "is a directory",
+            FsError::DirectoryNotEmpty => "directory not empty",
+            FsError::InvalidPath => "invalid path",
+            FsError::BadDescriptor => "bad file descriptor",
+            FsError::PermissionDenied => "permission denied",
+        };
+        f.write_str(message)
+    }
+}
@@ -0,0 +1,244 @@
+use super::FsError;
+use alloc::{collections::BTreeMap, string::String, vec::Vec};
+use spin::Mutex;
+
+enum Node {
+    File(Vec<u8>),
+    Dir(BTreeMap<String, Node>),
+}
+
+/// A filesystem kept entirely in memory, as a tree of directories and files.
+pub struct RamFs {
+    root: Node,
+}
+
+/// The filesystem behind the free functions in this module.
+pub static RAMFS: Mutex<RamFs> = Mutex::new(RamFs::new());
+
+//Splits an absolute path into its components, empty components (from "//" or a trailing '/') are skipped
+fn components(path: &str) -> Result<Vec<&str>, FsError> {
+    let rest = path.strip_prefix('/').ok_or(FsError::InvalidPath)?;
+    Ok(rest.split('/').filter(|part| !part.is_empty()).collect())
+}
+
+//splits off the last component, which can't be the root
+fn split_last(path: &str) -> Result<(Vec<&str>, &str), FsError> {
+    let mut parts = components(path)?;
+    let name = parts.pop().ok_or(FsError::InvalidPath)?;
+    Ok((parts, name))
+}
+
+impl RamFs {
+    pub const fn new() -> Self {
+        Self {
+            root: Node::Dir(BTreeMap::new()),
+        }
+    }
+
+    fn node(&self, parts: &[&str]) -> Result<&Node, FsError> {
+        let mut node = &self.root;
+        for part in parts {
+            node = match node {
+                Node::Dir(children) => children.get(*part).ok_or(FsError::NotFound)?,
+                Node::File(_) => return Err(FsError::NotADirectory),
+            };
+        }
+        Ok(node)
+    }
+
+    fn dir_mut(&mut self, parts: &[&str]) -> Result<&mut BTreeMap<String, Node>, FsError> {
+        let mut node = &mut self.root;
+        for part in parts {
+            node = match node {
+                Node::Dir(children) => children.get_mut(*part).ok_or(FsError::NotFound)?,
+                Node::File(_) => return Err(FsError::NotADirectory),
+            };
+        }
+        match node {
+            Node::Dir(children) => Ok(children),
+            Node::File(_) => Err(FsError::NotADirectory),
+        }
+    }
+
+    fn insert(&mut self, path: &str, node: Node) -> Result<(), FsError> {
+        let (parent, name) = split_last(path)?;
+        let children = self.dir_mut(&parent)?;
+        if children.contains_key(name) {
+            return Err(FsError::AlreadyExists);
+        }
+        children.insert(String::from(name), node);
+        Ok(())
+    }
+
+    /// Creates an empty file. The parent directory has to exist.
+    pub fn create(&mut self, path: &str) -> Result<(), FsError> {
+        self.insert(path, Node::File(Vec::new()))
+    }
+
+    /// Creates an empty directory. The parent directory has to exist.
+    pub fn create_dir(&mut self, path: &str) -> Result<(), FsError> {
+        self.insert(path, Node::Dir(BTreeMap::new()))
+    }
+
+    /// Creates every missing directory along `path`.
+    pub fn create_dir_all(&mut self, path: &str) -> Result<(), FsError> {
+        let parts = components(path)?;
+        let mut children = self.dir_mut(&[])?;
+        for part in parts {
+            let node = children
+                .entry(String::from(part))
+                .or_insert_with(|| Node::Dir(BTreeMap::new()));
+            children = match node {
+                Node::Dir(children) => children,
+                Node::File(_) => return Err(FsError::NotADirectory),
+            };
+        }
+        Ok(())
+    }
+
+    /// Replaces the contents of a file, creating it if it doesn't exist yet.
+    pub fn write(&mut self, path: &str, data: &[u8]) -> Result<(), FsError> {
+        let contents = self.contents_mut(path, true)?;
+        contents.clear();
+        contents.extend_from_slice(data);
+        Ok(())
+    }
+
+    pub fn read(&self, path: &str) -> Result<Vec<u8>, FsError> {
+        match self.node(&components(path)?)? {
+            Node::File(contents) => Ok(contents.clone()),
+            Node::Dir(_) => Err(FsError::IsADirectory),
+        }
+    }
+
+    /// The file's contents to modify in place, optionally creating the file first.
+    pub(super) fn contents_mut(
+        &mut self,
+        path: &str,
+        create: bool,
+    ) -> Result<&mut Vec<u8>, FsError> {
+        let (parent, name) = split_last(path)?;
+        let children = self.dir_mut(&parent)?;
+        if create && !children.contains_key(name) {
+            children.insert(String::from(name), Node::File(Vec::new()));
+        }
+        match children.get_mut(name).ok_or(FsError::NotFound)? {
+            Node::File(contents) => Ok(contents),
+            Node::Dir(_) => Err(FsError::IsADirectory),
+        }
+    }
+
+    /// Removes a file or an empty directory.
+    pub fn remove(&mut self, path: &str) -> Result<(), FsError> {
+        let (parent, name) = split_last(path)?;
+        let children = self.dir_mut(&parent)?;
+        match children.get(name).ok_or(FsError::NotFound)? {
+            Node::Dir(grandchildren) if !grandchildren.is_empty() => {
+                return Err(FsError::DirectoryNotEmpty)
+            }
+            _ => {}
+        }
+        children.remove(name);
+        Ok(())
+    }
+
+    /// Names of the entries in a directory, sorted.
+    pub fn list(&self, path: &str) -> Result<Vec<String>, FsError> {
+        match self.node(&components(path)?)? {
+            Node::Dir(children) => Ok(children.keys().cloned().collect()),
+            Node::File(_) => Err(FsError::NotADirectory),
+        }
+    }
+
+    pub fn is_dir(&self, path: &str) -> Result<bool, FsError> {
+        Ok(matches!(self.node(&components(path)?)?, Node::Dir(_)))
+    }
+}
+
+impl Default for RamFs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub fn create(path: &str) -> Result<(), FsError> {
+    RAMFS.lock().create(path)
+}
+
+pub fn create_dir(path: &str) -> Result<(), FsError> {
+    RAMFS.lock().create_dir(path)
+}
+
+pub fn create_dir_all(path: &str) -> Result<(), FsError> {
+    RAMFS.lock().create_dir_all(path)
+}
+
+pub fn write(path: &str, data: &[u8]) -> Result<(), FsError> {
+    RAMFS.lock().write(path, data)
+}
+
+pub fn read(path: &str) -> Result<Vec<u8>, FsError> {
+    RAMFS.lock().read(path)
+}
+
+pub fn remove(path: &str) -> Result<(), FsError> {
+    RAMFS.lock().remove(path)
+}
+
+pub fn list(path: &str) -> Result<Vec<String>, FsError> {
+    RAMFS.lock().list(path)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test_case]
+    fn nested_directories() {
+        let mut fs = RamFs::new();
+        fs.create_dir("/a").unwrap();
+        fs.create_dir("/a/b").unwrap();
+        fs.create_dir_all("/a/b/c/d").unwrap();
+        fs.create("/a/b/file").unwrap();
+        assert_eq!(fs.list("/a/b").unwrap(), ["c", "file"]);
+        assert_eq!(fs.list("/a/b/c").unwrap(), ["d"]);
+        assert_eq!(fs.list("/").unwrap(), ["a"]);
+        assert_eq!(fs.create_dir("/a"), Err(FsError::AlreadyExists));
+        assert_eq!(fs.create("/missing/file"), Err(FsError::NotFound));
+    }
+
+    #[test_case]
+    fn file_round_trip() {
+        let mut fs = RamFs::new();
+        fs.create_dir("/etc").unwrap();
+        fs.write("/etc/motd", b"hello").unwrap();
+        assert_eq!(fs.read("/etc/motd").unwrap(), b"hello");
+        fs.write("/etc/motd", b"bye").unwrap();
+        assert_eq!(fs.read("//etc//motd").unwrap(), b"bye");
+
+        assert_eq!(fs.remove("/etc"), Err(FsError::DirectoryNotEmpty));
+        fs.remove("/etc/motd").unwrap();
+        assert_eq!(fs.read("/etc/motd"), Err(FsError::NotFound));
+        fs.remove("/etc").unwrap();
+    }
+
+    #[test_case]
+    fn wrong_node_kind() {
+        let mut fs = RamFs::new();
+        fs.create_dir("/dir").unwrap();
+        fs.create("/file").unwrap();
+        assert_eq!(fs.read("/dir"), Err(FsError::IsADirectory));
+        assert_eq!(fs.list("/file"), Err(FsError::NotADirectory));
+        assert_eq!(fs.create("/file/child"), Err(FsError::NotADirectory));
+        assert_eq!(fs.read("relative"), Err(FsError::InvalidPath));
+    }
+
+    #[test_case]
+    fn global_filesystem() {
+        create_dir_all("/tmp/ramfs_test").unwrap();
+        write("/tmp/ramfs_test/data", &[1, 2, 3]).unwrap();
+        assert_eq!(read("/tmp/ramfs_test/data").unwrap(), [1, 2, 3]);
+        remove("/tmp/ramfs_test/data").unwrap();
+        assert!(list("/tmp/ramfs_test").unwrap().is_empty());
+    }
+}
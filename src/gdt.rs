@@ -3,7 +3,14 @@ use x86_64::structures::gdt::{Descriptor, GlobalDescriptorTable, SegmentSelector
 use x86_64::structures::tss::TaskStateSegment;
 use x86_64::VirtAddr;
 
+//Stack layout:
+//  kernel stack    - set up by the bootloader, grows down towards
+//  guard page      - the page below it, left unmapped by memory::install so an overflow page faults
+//  IST stacks      - statics below, so the page fault and double fault handlers still have a usable
+//                    stack when the kernel stack is exhausted
+//A fault taken while already on an IST stack restarts at its top, so the handlers must not fault themselves
 pub const DOUBLE_FAULT_IST_INDEX: u16 = 0;
+pub const PAGE_FAULT_IST_INDEX: u16 = 1;
 
 lazy_static! {
     static ref TSS: TaskStateSegment = {
@@ -12,8 +19,16 @@ lazy_static! {
             const STACK_SIZE: usize = 4096 * 5;
             static mut STACK: [u8; STACK_SIZE] = [0; STACK_SIZE];
 
-            let stack_start = VirtAddr::from_ptr(unsafe { &STACK });
-            
+            let stack_start = VirtAddr::from_ptr(&raw const STACK);
+
+            stack_start + STACK_SIZE
+        };
+        tss.interrupt_stack_table[PAGE_FAULT_IST_INDEX as usize] = {
+            const STACK_SIZE: usize = 4096 * 5;
+            static mut STACK: [u8; STACK_SIZE] = [0; STACK_SIZE];
+
+            let stack_start = VirtAddr::from_ptr(&raw const STACK);
+
             stack_start + STACK_SIZE
         };
         tss
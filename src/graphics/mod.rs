@@ -1,7 +1,7 @@
-mod colors;
+pub(crate) mod colors;
 mod configuration;
-mod lines;
+pub(crate) mod lines;
 mod registers;
 mod vga;
 
-pub use vga::VGA;
+pub use vga::{is_graphics_mode, VGA};
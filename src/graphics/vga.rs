@@ -13,6 +13,7 @@ use crate::graphics::{
 use alloc::{boxed::Box, string::String};
 use conquer_once::spin::Lazy;
 use core::ptr::copy_nonoverlapping;
+use core::sync::atomic::{AtomicBool, Ordering};
 use font8x8::UnicodeFonts;
 use lazy_static::lazy_static;
 use spinning_top::Spinlock;
@@ -28,6 +29,14 @@ const WIDTH: usize = 320;
 const HEIGHT: usize = 200;
 const SIZE: usize = WIDTH * HEIGHT;
 
+static MODE_SET: AtomicBool = AtomicBool::new(false);
+
+/// Whether `Vga::setup` has switched the card to graphics mode, until then the screen shows text
+/// mode and drawing to the framebuffer isn't visible.
+pub fn is_graphics_mode() -> bool {
+    MODE_SET.load(Ordering::Relaxed)
+}
+
 /// Provides mutable access to the vga graphics card.
 pub static VGA: Lazy<Spinlock<Vga>> = Lazy::new(|| Spinlock::new(Vga::new()));
 
@@ -67,12 +76,18 @@ impl Vga {
         self.color_palette_registers.load_palette(&DEFAULT_PALETTE);
         self.clear_screen(0);
         self.swap_buffers();
+        MODE_SET.store(true, Ordering::Relaxed);
     }
 
     pub fn get_buffer(&self) -> *mut u8 {
         DOUBLE_BUFFER.as_ptr() as *mut u8
     }
 
+    /// The frame drawn so far, one palette index per pixel.
+    pub fn back_buffer(&self) -> &[u8] {
+        &DOUBLE_BUFFER[..]
+    }
+
     /// Returns the current `EmulationMode` as determined by the miscellaneous output register.
     pub fn get_emulation_mode(&mut self) -> EmulationMode {
         EmulationMode::from(self.general_registers.read_msr() & 0x1)
@@ -1,16 +1,17 @@
 use crate::gdt;
-use crate::hlt_loop;
-use crate::io::MOUSE;
 use crate::serial_println;
+use core::sync::atomic::{AtomicU64, Ordering};
 use lazy_static::lazy_static;
 use spin;
 use x86_64::instructions::port::Port;
 use x86_64::instructions::port::PortReadOnly;
 use x86_64::structures::idt::PageFaultErrorCode;
 use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame};
+use x86_64::VirtAddr;
 
 const CMD_INIT: u8 = 0x11;
 const CMD_END_OF_INTERRUPT: u8 = 0x20;
+const CMD_READ_ISR: u8 = 0x0B; //OCW3, the next read of the command port returns the in-service register
 const MODE_8086: u8 = 0x01;
 
 struct Pic {
@@ -29,6 +30,12 @@ impl Pic {
         self.command.write(CMD_END_OF_INTERRUPT);
     }
 
+    //Bit n is set while the PIC considers its IRQ n to be in service
+    unsafe fn read_isr(&mut self) -> u8 {
+        self.command.write(CMD_READ_ISR);
+        self.command.read()
+    }
+
     //Interupt mask allows for certain IRQs to be disabled
 
     unsafe fn read_mask(&mut self) -> u8 {
@@ -108,6 +115,11 @@ impl ChainedPics {
         self.pics[1].write_mask(mask2);
     }
 
+    //In-service registers of the master and the slave, only needed by the spurious IRQ handlers
+    unsafe fn read_isrs(&mut self) -> [u8; 2] {
+        [self.pics[0].read_isr(), self.pics[1].read_isr()]
+    }
+
     fn handles_interrupt(&self, interrupt_id: u8) -> bool {
         self.pics.iter().any(|p| p.handles_interrupt(interrupt_id))
     }
@@ -122,6 +134,32 @@ impl ChainedPics {
     }
 }
 
+/// Which PICs to acknowledge for an interrupt on IRQ7 or IRQ15.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpuriousEoi {
+    None,
+    Master,
+    Both,
+}
+
+/// Decides how to acknowledge IRQ7 or IRQ15 given the PICs' in-service registers.
+///
+/// The lowest priority IRQ of each PIC is also where it reports a spurious interrupt, one whose
+/// request went away before the CPU acknowledged it. A real one shows up as in service. A spurious one
+/// doesn't and must not get an end of interrupt, which would instead end whatever else is in
+/// service. When the slave's IRQ15 is spurious the master still really handled the cascade on
+/// IRQ2, so the master is acknowledged anyway.
+pub fn spurious_eoi(irq: u8, isrs: [u8; 2]) -> SpuriousEoi {
+    const LOWEST_PRIORITY: u8 = 1 << 7;
+    match irq {
+        7 if isrs[0] & LOWEST_PRIORITY != 0 => SpuriousEoi::Master,
+        7 => SpuriousEoi::None,
+        15 if isrs[1] & LOWEST_PRIORITY != 0 => SpuriousEoi::Both,
+        15 => SpuriousEoi::Master,
+        _ => panic!("IRQ{} can't be spurious", irq),
+    }
+}
+
 pub const PIC_1_OFFSET: u8 = 32;
 pub const PIC_2_OFFSET: u8 = PIC_1_OFFSET + 8;
 
@@ -132,8 +170,13 @@ pub static PICS: spin::Mutex<ChainedPics> =
 #[repr(u8)]
 pub enum InterruptIndex {
     Timer = PIC_1_OFFSET,
-    Keyboard, //Defaults to Timer + 1 (33)
+    Keyboard,                   //Defaults to Timer + 1 (33)
+    Serial1 = PIC_1_OFFSET + 4, //COM1
+    Irq7 = PIC_1_OFFSET + 7,    //also where the master PIC reports spurious interrupts
     Mouse = PIC_1_OFFSET + 12,
+    Irq15 = PIC_1_OFFSET + 15,    //same for the slave
+    ApicTimer = PIC_2_OFFSET + 8, //first vector past the PICs
+    ApicSpurious = 0xFF,
 }
 
 impl InterruptIndex {
@@ -146,19 +189,73 @@ impl InterruptIndex {
     }
 }
 
+/// Whether maskable interrupts are currently enabled, i.e. the IF bit of RFLAGS.
+pub fn are_enabled() -> bool {
+    use x86_64::registers::rflags::{self, RFlags};
+    rflags::read().contains(RFlags::INTERRUPT_FLAG)
+}
+
+/// Runs `f` with interrupts disabled, then puts them back the way they were - so nesting inside
+/// an interrupt handler or another critical section leaves them off.
+pub fn without_interrupts<R>(f: impl FnOnce() -> R) -> R {
+    let were_enabled = are_enabled();
+    if were_enabled {
+        x86_64::instructions::interrupts::disable();
+    }
+    let result = f();
+    if were_enabled {
+        x86_64::instructions::interrupts::enable();
+    }
+    result
+}
+
+const VECTORS: usize = 256;
+
+//How often each vector has fired, bumped first thing in every handler
+static COUNTS: [AtomicU64; VECTORS] = [const { AtomicU64::new(0) }; VECTORS];
+
+#[inline]
+fn count(vector: u8) {
+    COUNTS[vector as usize].fetch_add(1, Ordering::Relaxed);
+}
+
+/// How many times each interrupt vector has fired since boot, indexed by vector.
+pub fn stats() -> [u64; VECTORS] {
+    core::array::from_fn(|vector| COUNTS[vector].load(Ordering::Relaxed))
+}
+
+/// How many times one of the hardware interrupts has fired since boot.
+pub fn interrupt_count(index: InterruptIndex) -> u64 {
+    COUNTS[index.as_usize()].load(Ordering::Relaxed)
+}
+
 lazy_static! {
     static ref IDT: InterruptDescriptorTable = {
         let mut idt = InterruptDescriptorTable::new();
         idt.breakpoint.set_handler_fn(breakpoint_handler);
-        idt.page_fault.set_handler_fn(page_fault_handler);
+        unsafe {
+            idt.page_fault
+                .set_handler_fn(page_fault_handler)
+                .set_stack_index(gdt::PAGE_FAULT_IST_INDEX);
+        }
+        //Both go through stubs that save all the registers first, see crate::fault
+        let (double_fault, general_protection) = crate::fault::entry_points();
         unsafe {
             idt.double_fault
-                .set_handler_fn(double_fault_handler)
+                .set_handler_addr(VirtAddr::new(double_fault))
                 .set_stack_index(gdt::DOUBLE_FAULT_IST_INDEX);
+            idt.general_protection_fault
+                .set_handler_addr(VirtAddr::new(general_protection));
         }
         idt[InterruptIndex::Timer.as_usize()].set_handler_fn(timer_interrupt_handler);
         idt[InterruptIndex::Keyboard.as_usize()].set_handler_fn(keyboard_interrupt_handler);
+        idt[InterruptIndex::Serial1.as_usize()].set_handler_fn(serial_interrupt_handler);
         idt[InterruptIndex::Mouse.as_usize()].set_handler_fn(mouse_interrupt_handler);
+        idt[InterruptIndex::Irq7.as_usize()].set_handler_fn(irq7_handler);
+        idt[InterruptIndex::Irq15.as_usize()].set_handler_fn(irq15_handler);
+        idt[InterruptIndex::ApicTimer.as_usize()].set_handler_fn(apic_timer_interrupt_handler);
+        idt[InterruptIndex::ApicSpurious.as_usize()]
+            .set_handler_fn(apic_spurious_interrupt_handler);
         idt
     };
 }
@@ -167,13 +264,57 @@ pub fn init_idt() {
     IDT.load();
 }
 
+const BREAKPOINT_VECTOR: u8 = 3;
+const PAGE_FAULT_VECTOR: u8 = 14;
+
 extern "x86-interrupt" fn breakpoint_handler(stack_frame: InterruptStackFrame) {
+    count(BREAKPOINT_VECTOR);
     serial_println!("EXCEPTION: BREAKPOINT\n{:#?}", stack_frame);
 }
 
 #[test_case]
 fn test_breakpoint_exception() {
+    let before = stats()[BREAKPOINT_VECTOR as usize];
     x86_64::instructions::interrupts::int3();
+    assert_eq!(stats()[BREAKPOINT_VECTOR as usize], before + 1);
+}
+
+#[test_case]
+fn test_nested_without_interrupts() {
+    assert!(are_enabled());
+    without_interrupts(|| {
+        assert!(!are_enabled());
+        without_interrupts(|| assert!(!are_enabled()));
+        //The inner one found them disabled, so it mustn't have turned them back on
+        assert!(!are_enabled());
+    });
+    assert!(are_enabled());
+}
+
+#[test_case]
+fn test_spurious_eoi() {
+    //Only what's in service on bit 7 matters, other IRQs may be in service too
+    assert_eq!(spurious_eoi(7, [0x80, 0]), SpuriousEoi::Master);
+    assert_eq!(spurious_eoi(7, [0x00, 0]), SpuriousEoi::None);
+    assert_eq!(spurious_eoi(7, [0x01, 0xFF]), SpuriousEoi::None);
+    assert_eq!(spurious_eoi(15, [0x04, 0x80]), SpuriousEoi::Both);
+    assert_eq!(spurious_eoi(15, [0x04, 0x00]), SpuriousEoi::Master);
+    assert_eq!(spurious_eoi(15, [0x04, 0x7F]), SpuriousEoi::Master);
+}
+
+#[test_case]
+fn test_timer_count() {
+    use x86_64::instructions::interrupts::without_interrupts;
+
+    //Either timer may be the one driving ticks, and with interrupts off neither is mid-handler
+    let timer_count =
+        || interrupt_count(InterruptIndex::Timer) + interrupt_count(InterruptIndex::ApicTimer);
+    let (start_count, start_ticks) = without_interrupts(|| (timer_count(), crate::time::ticks()));
+    while crate::time::ticks() < start_ticks + 3 {
+        x86_64::instructions::hlt();
+    }
+    let (count, ticks) = without_interrupts(|| (timer_count(), crate::time::ticks()));
+    assert_eq!(count - start_count, ticks - start_ticks);
 }
 
 extern "x86-interrupt" fn page_fault_handler(
@@ -182,30 +323,42 @@ extern "x86-interrupt" fn page_fault_handler(
 ) {
     use x86_64::registers::control::Cr2;
 
-    serial_println!("EXCEPTION: PAGE FAULT");
-    serial_println!("Accessed Address: {:?}", Cr2::read());
-    serial_println!("Error Code: {:?}", error_code);
-    serial_println!("{:#?}", stack_frame);
-    hlt_loop();
-}
+    count(PAGE_FAULT_VECTOR);
+    let addr = Cr2::read();
+    //Only a missing page can be filled in - protection violations are always fatal
+    if !error_code.contains(PageFaultErrorCode::PROTECTION_VIOLATION)
+        && crate::memory::handle_demand_fault(addr)
+    {
+        return;
+    }
 
-extern "x86-interrupt" fn double_fault_handler(
-    stack_frame: InterruptStackFrame,
-    _error_code: u64,
-) -> ! {
-    panic!("EXCEPTION: DOUBLE FAULT\n{:#?}", stack_frame);
+    if crate::memory::is_stack_guard(addr) {
+        panic!(
+            "EXCEPTION: STACK OVERFLOW\nAccessed Address: {:?}\n{:#?}",
+            addr, stack_frame
+        );
+    }
+
+    panic!(
+        "EXCEPTION: PAGE FAULT\nAccessed Address: {:?}\nError Code: {:?}\n{:#?}",
+        addr, error_code, stack_frame
+    );
 }
 
 extern "x86-interrupt" fn timer_interrupt_handler(_stack_frame: InterruptStackFrame) {
-    crate::timer::tick();
+    count(InterruptIndex::Timer.as_u8());
+    crate::time::tick();
 
     unsafe {
         PICS.lock()
             .notify_end_of_interrupt(InterruptIndex::Timer.as_u8());
     }
+    //Last, since it may not return until this thread's next turn
+    crate::thread::preempt();
 }
 
 extern "x86-interrupt" fn keyboard_interrupt_handler(_stack_frame: InterruptStackFrame) {
+    count(InterruptIndex::Keyboard.as_u8());
     let mut port = Port::new(0x60);
     let scancode: u8 = unsafe { port.read() };
 
@@ -217,10 +370,54 @@ extern "x86-interrupt" fn keyboard_interrupt_handler(_stack_frame: InterruptStac
     }
 }
 
+extern "x86-interrupt" fn apic_timer_interrupt_handler(_stack_frame: InterruptStackFrame) {
+    count(InterruptIndex::ApicTimer.as_u8());
+    crate::time::tick();
+    crate::time::apic::end_of_interrupt();
+    crate::thread::preempt();
+}
+
+//Nothing is attached to either IRQ, so all they need is the right end of interrupt
+fn pic_spurious_interrupt(irq: u8) {
+    let mut pics = PICS.lock();
+    match spurious_eoi(irq, unsafe { pics.read_isrs() }) {
+        SpuriousEoi::None => {}
+        SpuriousEoi::Master => unsafe { pics.pics[0].end_of_interrupt() },
+        SpuriousEoi::Both => unsafe { pics.notify_end_of_interrupt(InterruptIndex::Irq15.as_u8()) },
+    }
+}
+
+extern "x86-interrupt" fn irq7_handler(_stack_frame: InterruptStackFrame) {
+    count(InterruptIndex::Irq7.as_u8());
+    pic_spurious_interrupt(7);
+}
+
+extern "x86-interrupt" fn irq15_handler(_stack_frame: InterruptStackFrame) {
+    count(InterruptIndex::Irq15.as_u8());
+    pic_spurious_interrupt(15);
+}
+
+//The APIC's spurious vector is never in service, so unlike every other APIC interrupt it must not
+//get an end of interrupt
+extern "x86-interrupt" fn apic_spurious_interrupt_handler(_stack_frame: InterruptStackFrame) {
+    count(InterruptIndex::ApicSpurious.as_u8());
+}
+
+extern "x86-interrupt" fn serial_interrupt_handler(_stack_frame: InterruptStackFrame) {
+    count(InterruptIndex::Serial1.as_u8());
+    crate::io::serial_interrupt();
+
+    unsafe {
+        PICS.lock()
+            .notify_end_of_interrupt(InterruptIndex::Serial1.as_u8());
+    }
+}
+
 extern "x86-interrupt" fn mouse_interrupt_handler(_stack_frame: InterruptStackFrame) {
+    count(InterruptIndex::Mouse.as_u8());
     let mut port = PortReadOnly::new(0x60);
     let packet: u8 = unsafe { port.read() };
-    MOUSE.lock().process_packet(packet);
+    crate::io::mouse_interrupt(packet);
 
     unsafe {
         PICS.lock()
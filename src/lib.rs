@@ -9,15 +9,29 @@
 
 extern crate alloc;
 
+pub mod acpi;
 pub mod allocator;
+pub mod ata;
+pub mod bench;
+pub mod cpu;
 pub mod executor;
+pub mod fault;
+pub mod fs;
 pub mod gdt;
 pub mod graphics;
 pub mod interrupts;
 pub mod io;
+pub mod log;
 pub mod memory;
+pub mod pci;
+pub mod power;
 pub mod render;
-pub mod timer;
+pub mod rng;
+pub mod shell;
+pub mod sync;
+pub mod testing;
+pub mod thread;
+pub mod time;
 
 use bootloader::BootInfo;
 use core::panic::PanicInfo;
@@ -28,11 +42,18 @@ use x86_64::VirtAddr;
 
 #[alloc_error_handler]
 fn alloc_error_handler(layout: alloc::alloc::Layout) -> ! {
-    panic!("allocation error: {:?}", layout)
+    allocator::handle_oom(layout)
 }
 
 pub trait Testable {
     fn run(&self);
+
+    fn name(&self) -> &'static str;
+
+    /// Benchmarks are run after all the tests, see `bench::Bench`.
+    fn is_bench(&self) -> bool {
+        false
+    }
 }
 
 impl<T> Testable for T
@@ -40,21 +61,23 @@ where
     T: Fn(),
 {
     fn run(&self) {
-        serial_print!("{}...\t", core::any::type_name::<T>());
+        serial_print!("{}...\t", self.name());
         self();
         serial_println!("[ok]");
     }
+
+    fn name(&self) -> &'static str {
+        core::any::type_name::<T>()
+    }
 }
 
 pub fn test_runner(tests: &[&dyn Testable]) {
-    serial_println!("Running {} tests", tests.len());
-    for test in tests {
-        test.run();
-    }
-    exit_qemu(QemuExitCode::Success);
+    testing::run_tests(tests)
 }
 
 pub fn test_panic_handler(info: &PanicInfo) -> ! {
+    //A should_panic test passed, carry on with the rest
+    testing::resume_after_expected_panic();
     serial_println!("[failed]\n");
     serial_println!("Error: {}\n", info);
     exit_qemu(QemuExitCode::Failed);
@@ -102,8 +125,23 @@ fn panic(info: &PanicInfo) -> ! {
     test_panic_handler(info)
 }
 
+/// Optional parts of `init_with`, all off for `init`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct InitOptions {
+    /// Move the tick interrupt to the local APIC timer, staying on the PIT if it can't be calibrated.
+    pub apic_timer: bool,
+}
+
 pub fn init(boot_info: &'static BootInfo) {
+    init_with(boot_info, InitOptions::default());
+}
+
+pub fn init_with(boot_info: &'static BootInfo, options: InitOptions) {
+    //Before anything else, since the compiler is free to use SSE anywhere
+    assert!(cpu::enable_sse(), "SSE2 is required");
+
     //Interupts Initilization
+    crate::io::init_serial();
     gdt::init();
     interrupts::init_idt();
     unsafe { interrupts::PICS.lock().initialize() };
@@ -113,14 +151,33 @@ pub fn init(boot_info: &'static BootInfo) {
     let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
     let mut mapper = unsafe { memory::init(phys_mem_offset) };
     let mut frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map) };
-    allocator::init_heap(&mut mapper, &mut frame_allocator).expect("heap initialization failed");
+    allocator::init_heap(
+        &mut mapper,
+        &mut frame_allocator,
+        allocator::HeapKind::default(),
+    )
+    .expect("heap initialization failed");
+    memory::install(mapper, frame_allocator);
 
     crate::io::SCANCODE_QUEUE
         .try_init_once(|| ArrayQueue::new(100))
         .expect("ScancodeQueue already initialized");
+    crate::io::SERIAL_QUEUE
+        .try_init_once(|| ArrayQueue::new(100))
+        .expect("SerialQueue already initialized");
+    crate::io::MOUSE_QUEUE
+        .try_init_once(|| ArrayQueue::new(100))
+        .expect("MouseQueue already initialized");
 
     io::init_mouse();
 
     //Graphics Initilization
     VGA.lock().setup();
+
+    //Needs the timer running, so after interrupts are enabled
+    time::tsc::calibrate();
+    //Calibrated against the PIT, so last. A failed start leaves the PIT ticking
+    if options.apic_timer && !time::apic::start() {
+        serial_println!("APIC timer unavailable, staying on the PIT");
+    }
 }
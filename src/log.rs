@@ -0,0 +1,223 @@
+use crate::sync::IrqSpinLock;
+use core::fmt;
+use core::str;
+use core::sync::atomic::{AtomicU8, Ordering};
+
+/// Severity of a log message, most severe first.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+}
+
+impl LogLevel {
+    pub fn tag(self) -> &'static str {
+        match self {
+            LogLevel::Error => "ERROR",
+            LogLevel::Warn => "WARN",
+            LogLevel::Info => "INFO",
+            LogLevel::Debug => "DEBUG",
+        }
+    }
+
+    fn from_u8(level: u8) -> Self {
+        match level {
+            0 => LogLevel::Error,
+            1 => LogLevel::Warn,
+            2 => LogLevel::Info,
+            _ => LogLevel::Debug,
+        }
+    }
+}
+
+static LOG_LEVEL: AtomicU8 = AtomicU8::new(LogLevel::Info as u8);
+
+/// Messages less severe than `level` are dropped from now on.
+pub fn set_log_level(level: LogLevel) {
+    LOG_LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+pub fn log_level() -> LogLevel {
+    LogLevel::from_u8(LOG_LEVEL.load(Ordering::Relaxed))
+}
+
+/// Whether a message at `level` would currently be printed.
+#[inline]
+pub fn enabled(level: LogLevel) -> bool {
+    level as u8 <= LOG_LEVEL.load(Ordering::Relaxed)
+}
+
+#[doc(hidden)]
+pub fn _log(level: LogLevel, args: fmt::Arguments) {
+    crate::serial_println!("[{}] {}", level.tag(), args);
+}
+
+//The level check comes first so a suppressed message never evaluates or formats its arguments
+#[doc(hidden)]
+#[macro_export]
+macro_rules! log_at {
+    ($level:expr, $($arg:tt)*) => {
+        if $crate::log::enabled($level) {
+            $crate::log::_log($level, format_args!($($arg)*));
+        }
+    };
+}
+
+#[macro_export]
+macro_rules! log_error {
+    ($($arg:tt)*) => ($crate::log_at!($crate::log::LogLevel::Error, $($arg)*));
+}
+
+#[macro_export]
+macro_rules! log_warn {
+    ($($arg:tt)*) => ($crate::log_at!($crate::log::LogLevel::Warn, $($arg)*));
+}
+
+#[macro_export]
+macro_rules! log_info {
+    ($($arg:tt)*) => ($crate::log_at!($crate::log::LogLevel::Info, $($arg)*));
+}
+
+#[macro_export]
+macro_rules! log_debug {
+    ($($arg:tt)*) => ($crate::log_at!($crate::log::LogLevel::Debug, $($arg)*));
+}
+
+/// Largest capacity the log ring can be given - its buffer is static so it works before the heap does.
+pub const LOG_RING_MAX: usize = 16 * 1024;
+
+/// The most recent serial output, kept so it can be printed again after a fault.
+///Oldest lines are dropped whole once the ring is over capacity
+pub struct LogRing {
+    buf: [u8; LOG_RING_MAX],
+    len: usize,
+    capacity: usize,
+}
+
+impl LogRing {
+    pub const fn new(capacity: usize) -> Self {
+        assert!(capacity <= LOG_RING_MAX);
+        Self {
+            buf: [0; LOG_RING_MAX],
+            len: 0,
+            capacity,
+        }
+    }
+
+    //Drops everything up to and including the first newline, or everything if there is none
+    fn discard_oldest(&mut self) {
+        let end = self.buf[..self.len]
+            .iter()
+            .position(|&byte| byte == b'\n')
+            .map_or(self.len, |newline| newline + 1);
+        self.buf.copy_within(end..self.len, 0);
+        self.len -= end;
+    }
+
+    pub fn push(&mut self, s: &str) {
+        let mut s = s;
+        if s.len() > self.capacity {
+            //Only the end of it fits, cut at a character boundary to keep the contents valid UTF-8
+            let mut start = s.len() - self.capacity;
+            while !s.is_char_boundary(start) {
+                start += 1;
+            }
+            s = &s[start..];
+            self.len = 0;
+        }
+        while self.len + s.len() > self.capacity {
+            self.discard_oldest();
+        }
+        self.buf[self.len..self.len + s.len()].copy_from_slice(s.as_bytes());
+        self.len += s.len();
+    }
+
+    pub fn set_capacity(&mut self, capacity: usize) {
+        assert!(
+            capacity <= LOG_RING_MAX,
+            "log ring capacity over LOG_RING_MAX"
+        );
+        self.capacity = capacity;
+        while self.len > capacity {
+            self.discard_oldest();
+        }
+    }
+
+    pub fn contents(&self) -> &str {
+        //Only whole strs go in and cuts are made at character boundaries
+        str::from_utf8(&self.buf[..self.len]).unwrap_or_default()
+    }
+}
+
+pub(crate) static LOG_RING: IrqSpinLock<LogRing> = IrqSpinLock::new(LogRing::new(LOG_RING_MAX));
+
+/// Sets how many bytes of output the log ring keeps, up to `LOG_RING_MAX`.
+pub fn set_ring_capacity(bytes: usize) {
+    LOG_RING.lock().set_capacity(bytes);
+}
+
+/// Writes the output kept in the log ring to `out`, oldest first - e.g. to the serial port
+/// after a fault to see what led up to it.
+pub fn dump_ring(out: &mut impl fmt::Write) -> fmt::Result {
+    out.write_str(LOG_RING.lock().contents())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use core::sync::atomic::AtomicUsize;
+
+    static EVALUATED: AtomicUsize = AtomicUsize::new(0);
+
+    fn counted() -> usize {
+        EVALUATED.fetch_add(1, Ordering::Relaxed)
+    }
+
+    #[test_case]
+    fn warn_suppresses_info() {
+        let previous = log_level();
+        set_log_level(LogLevel::Warn);
+        assert!(enabled(LogLevel::Error));
+        assert!(enabled(LogLevel::Warn));
+        assert!(!enabled(LogLevel::Info));
+
+        EVALUATED.store(0, Ordering::Relaxed);
+        log_info!("suppressed {}", counted());
+        log_debug!("suppressed {}", counted());
+        assert_eq!(EVALUATED.load(Ordering::Relaxed), 0);
+        log_warn!("printed {}", counted());
+        assert_eq!(EVALUATED.load(Ordering::Relaxed), 1);
+
+        set_log_level(previous);
+    }
+
+    #[test_case]
+    fn ring_keeps_recent_entries() {
+        use alloc::string::String;
+
+        set_ring_capacity(64);
+        for i in 0..20 {
+            crate::serial_println!("ring entry {}", i);
+        }
+        let mut dump = String::new();
+        dump_ring(&mut dump).unwrap();
+        set_ring_capacity(LOG_RING_MAX);
+
+        assert!(dump.len() <= 64);
+        assert!(dump.ends_with("ring entry 19\n"));
+        assert!(dump.starts_with("ring entry"));
+        assert!(!dump.contains("ring entry 0\n"));
+    }
+
+    #[test_case]
+    fn ring_cuts_oversized_entries() {
+        let mut ring = LogRing::new(8);
+        ring.push("first\n");
+        ring.push("a long entry that doesn't fit \u{e9}\u{e9}\n");
+        assert!(ring.contents().len() <= 8);
+        assert!(ring.contents().ends_with("\u{e9}\u{e9}\n"));
+    }
+}
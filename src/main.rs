@@ -10,6 +10,7 @@ use bootloader::{entry_point, BootInfo};
 use core::panic::PanicInfo;
 use finn_os::executor::{Executor, Task};
 use finn_os::render::render;
+use finn_os::time::run_timeouts;
 
 entry_point!(kernel_main);
 
@@ -21,6 +22,7 @@ fn kernel_main(boot_info: &'static BootInfo) -> ! {
 
     let mut executor = Executor::new();
     executor.spawn(Task::new(render()));
+    executor.spawn(Task::new(run_timeouts()));
 
     executor.run();
 }
@@ -29,7 +31,10 @@ fn kernel_main(boot_info: &'static BootInfo) -> ! {
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
     use finn_os::serial_println;
+    //Nothing else gets to run, e.g. a thread drawing over the message
+    x86_64::instructions::interrupts::disable();
     serial_println!("{}", info);
+    finn_os::render::show_panic(info);
     finn_os::hlt_loop();
 }
 
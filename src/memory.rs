@@ -1,11 +1,180 @@
+use alloc::vec::Vec;
 use bootloader::bootinfo::{MemoryMap, MemoryRegionType};
+use conquer_once::spin::OnceCell;
+use core::sync::atomic::{AtomicU64, Ordering};
+use spin::Mutex;
+use x86_64::instructions::interrupts;
 use x86_64::registers::control::Cr3;
 use x86_64::{
-    structures::paging::{FrameAllocator, PageTable, Size4KiB},
-    structures::paging::{OffsetPageTable, PhysFrame},
+    structures::paging::{mapper::MapToError, FrameAllocator, FrameDeallocator, Mapper, Page},
+    structures::paging::{
+        OffsetPageTable, PageTable, PageTableFlags, PhysFrame, Size4KiB, Translate,
+    },
     PhysAddr, VirtAddr,
 };
 
+/// The page tables and frame allocator, kept around after boot for code that maps memory later on.
+pub struct MemoryContext {
+    pub mapper: OffsetPageTable<'static>,
+    pub frame_allocator: BootInfoFrameAllocator,
+}
+
+static MEMORY: OnceCell<Mutex<MemoryContext>> = OnceCell::uninit();
+
+//Start of the unmapped page below the kernel stack, 0 until install has found it
+static STACK_GUARD: AtomicU64 = AtomicU64::new(0);
+//The bootloader's stack is far smaller, this only bounds the walk if no gap is found
+const MAX_STACK_PAGES: u64 = 1024;
+
+/// Hands the mapper and frame allocator over once the boot time mappings are done.
+pub fn install(mut mapper: OffsetPageTable<'static>, frame_allocator: BootInfoFrameAllocator) {
+    guard_kernel_stack(&mut mapper);
+    MEMORY
+        .try_init_once(|| {
+            Mutex::new(MemoryContext {
+                mapper,
+                frame_allocator,
+            })
+        })
+        .expect("memory context already installed");
+}
+
+//walks down from the current stack page to the end of the stack's mappings and makes sure the page below is unmapped
+fn guard_kernel_stack(mapper: &mut OffsetPageTable<'static>) {
+    let rsp: u64;
+    unsafe { core::arch::asm!("mov {}, rsp", out(reg) rsp) };
+    let mut bottom = Page::<Size4KiB>::containing_address(VirtAddr::new(rsp));
+    for _ in 0..MAX_STACK_PAGES {
+        if mapper
+            .translate_addr((bottom - 1).start_address())
+            .is_none()
+        {
+            break;
+        }
+        bottom -= 1;
+    }
+
+    let guard = bottom - 1;
+    //Only reached if the stack runs straight into other mappings - give up its lowest page instead
+    if let Ok((_, flush)) = mapper.unmap(guard) {
+        flush.flush();
+    }
+    STACK_GUARD.store(guard.start_address().as_u64(), Ordering::Relaxed);
+}
+
+/// Whether `addr` is in the guard page below the kernel stack, i.e. the stack overflowed.
+pub fn is_stack_guard(addr: VirtAddr) -> bool {
+    let guard = STACK_GUARD.load(Ordering::Relaxed);
+    guard != 0 && (guard..guard + PAGE_SIZE).contains(&addr.as_u64())
+}
+
+/// Runs `f` with the memory context, `None` if it hasn't been installed yet.
+pub fn with_memory<R>(f: impl FnOnce(&mut MemoryContext) -> R) -> Option<R> {
+    let memory = MEMORY.try_get().ok()?;
+    Some(interrupts::without_interrupts(|| f(&mut memory.lock())))
+}
+
+/// Like `with_memory`, but also gives up if the context is already locked.
+///Used from the allocator, where waiting on a caller that holds the lock would deadlock
+pub fn try_with_memory<R>(f: impl FnOnce(&mut MemoryContext) -> R) -> Option<R> {
+    let memory = MEMORY.try_get().ok()?;
+    interrupts::without_interrupts(|| {
+        let mut memory = memory.try_lock()?;
+        Some(f(&mut memory))
+    })
+}
+
+//Demand-zero regions - a fixed array since they're looked up from the page fault handler
+const DEMAND_REGIONS: usize = 8;
+static DEMAND: Mutex<[Option<(u64, u64)>; DEMAND_REGIONS]> = Mutex::new([None; DEMAND_REGIONS]);
+
+/// Registers `size` bytes at `start` to be backed by zeroed frames the first time each page is touched.
+///Returns false if every slot is taken
+pub fn register_demand_region(start: VirtAddr, size: u64) -> bool {
+    interrupts::without_interrupts(|| {
+        let mut regions = DEMAND.lock();
+        match regions.iter_mut().find(|region| region.is_none()) {
+            Some(slot) => {
+                *slot = Some((start.as_u64(), start.as_u64() + size));
+                true
+            }
+            None => false,
+        }
+    })
+}
+
+/// Maps a zeroed frame for `addr` if it lies in a demand region. Called from the page fault handler.
+pub fn handle_demand_fault(addr: VirtAddr) -> bool {
+    //interrupts are already disabled in the handler
+    let in_region = DEMAND
+        .try_lock()
+        .map(|regions| {
+            regions
+                .iter()
+                .flatten()
+                .any(|&(start, end)| (start..end).contains(&addr.as_u64()))
+        })
+        .unwrap_or(false);
+    if !in_region {
+        return false;
+    }
+
+    let page = Page::<Size4KiB>::containing_address(addr);
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+    //try_ - the fault may have come from code that holds the memory lock
+    let mapped = try_with_memory(|memory| {
+        let frame = memory.frame_allocator.allocate_frame()?;
+        unsafe {
+            memory
+                .mapper
+                .map_to(page, frame, flags, &mut memory.frame_allocator)
+                .ok()?
+                .flush();
+        }
+        Some(())
+    });
+    if !matches!(mapped, Some(Some(()))) {
+        return false;
+    }
+
+    //the frame may hold anything, so clear it through its new mapping
+    unsafe {
+        page.start_address()
+            .as_mut_ptr::<u8>()
+            .write_bytes(0, PAGE_SIZE as usize)
+    };
+    true
+}
+
+/// Physical frame usage, `None` before `install`.
+pub fn frame_stats() -> Option<FrameStats> {
+    with_memory(|memory| memory.frame_allocator.stats())
+}
+
+/// Backs `size` bytes starting at the page containing `start` with fresh frames.
+pub fn map_pages(
+    mapper: &mut impl Mapper<Size4KiB>,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+    start: VirtAddr,
+    size: u64,
+    flags: PageTableFlags,
+) -> Result<(), MapToError<Size4KiB>> {
+    let page_range = {
+        let end = start + size - 1u64;
+        let start_page = Page::containing_address(start);
+        let end_page = Page::containing_address(end);
+        Page::range_inclusive(start_page, end_page)
+    };
+
+    for page in page_range {
+        let frame = frame_allocator
+            .allocate_frame()
+            .ok_or(MapToError::FrameAllocationFailed)?;
+        unsafe { mapper.map_to(page, frame, flags, frame_allocator)?.flush() };
+    }
+    Ok(())
+}
+
 unsafe fn active_level_4_table(physical_memory_offset: VirtAddr) -> &'static mut PageTable {
     let (level_4_table_frame, _) = Cr3::read();
 
@@ -16,21 +185,59 @@ unsafe fn active_level_4_table(physical_memory_offset: VirtAddr) -> &'static mut
     &mut *page_table_ptr // unsafe - all phys mem must be loaded after physical_memory_offest arg
 }
 
+//Where the bootloader mapped all of physical memory, set by init
+static PHYS_OFFSET: AtomicU64 = AtomicU64::new(0);
+
 pub unsafe fn init(physical_memory_offset: VirtAddr) -> OffsetPageTable<'static> {
+    PHYS_OFFSET.store(physical_memory_offset.as_u64(), Ordering::Relaxed);
     let level_4_table = active_level_4_table(physical_memory_offset);
     OffsetPageTable::new(level_4_table, physical_memory_offset)
 }
 
+/// The virtual address `addr` can be accessed at through the bootloader's physical memory mapping.
+pub fn phys_to_virt(addr: PhysAddr) -> VirtAddr {
+    VirtAddr::new(PHYS_OFFSET.load(Ordering::Relaxed) + addr.as_u64())
+}
+
+/// Physical memory usage, counted in 4 KiB frames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameStats {
+    pub total: usize, //usable frames in the bootloader's memory map
+    pub allocated: usize,
+    pub free: usize,
+}
+
+//Frames handed back through `FrameDeallocator` - a fixed array since this can run under the heap's lock
+const RECYCLED_FRAMES: usize = 256;
+
 pub struct BootInfoFrameAllocator {
     memory_map: &'static MemoryMap,
     next: usize,
+    total: usize,
+    allocated: usize,
+    recycled: [Option<PhysFrame>; RECYCLED_FRAMES],
+    recycled_len: usize,
 }
 
 impl BootInfoFrameAllocator {
     pub unsafe fn init(memory_map: &'static MemoryMap) -> Self {
-        BootInfoFrameAllocator {
+        let mut allocator = BootInfoFrameAllocator {
             memory_map,
             next: 0,
+            total: 0,
+            allocated: 0,
+            recycled: [None; RECYCLED_FRAMES],
+            recycled_len: 0,
+        };
+        allocator.total = allocator.usable_frames().count();
+        allocator
+    }
+
+    pub fn stats(&self) -> FrameStats {
+        FrameStats {
+            total: self.total,
+            allocated: self.allocated,
+            free: self.total - self.allocated,
         }
     }
 
@@ -45,8 +252,276 @@ impl BootInfoFrameAllocator {
 
 unsafe impl FrameAllocator<Size4KiB> for BootInfoFrameAllocator {
     fn allocate_frame(&mut self) -> Option<PhysFrame> {
-        let frame = self.usable_frames().nth(self.next);
-        self.next += 1;
+        let frame = if self.recycled_len > 0 {
+            self.recycled_len -= 1;
+            self.recycled[self.recycled_len].take()
+        } else {
+            let frame = self.usable_frames().nth(self.next);
+            self.next += 1;
+            frame
+        };
+        if frame.is_some() {
+            self.allocated += 1;
+        }
         frame
     }
 }
+
+impl FrameDeallocator<Size4KiB> for BootInfoFrameAllocator {
+    unsafe fn deallocate_frame(&mut self, frame: PhysFrame) {
+        self.allocated -= 1;
+        //Once the array is full the frame is leaked, the bump allocator can't take it back
+        if self.recycled_len < RECYCLED_FRAMES {
+            self.recycled[self.recycled_len] = Some(frame);
+            self.recycled_len += 1;
+        }
+    }
+}
+
+//Virtual region handed out by `map_anonymous`, well clear of the heap
+pub const ANON_START: u64 = 0x_5555_0000_0000;
+pub const ANON_SIZE: u64 = 0x0100_0000_0000; // 1 TiB
+const PAGE_SIZE: u64 = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MapError {
+    NotInstalled,
+    OutOfAddressSpace,
+    OutOfFrames,
+    AlreadyMapped,
+    NotMapped,
+    ZeroPages,
+    NotAnonymous, //the range isn't exactly one mapping map_anonymous handed out
+}
+
+//Bump allocator over the anonymous region, with freed ranges reused first fit
+struct AnonRanges {
+    next: u64,
+    free: Vec<(u64, usize)>,
+    live: Vec<(u64, usize)>, //mappings currently handed out, so unmap only takes back whole ones
+}
+
+static ANON_RANGES: Mutex<AnonRanges> = Mutex::new(AnonRanges {
+    next: ANON_START,
+    free: Vec::new(),
+    live: Vec::new(),
+});
+
+impl AnonRanges {
+    fn take(&mut self, num_pages: usize) -> Option<u64> {
+        //An empty range would start where the next mapping does
+        if num_pages == 0 {
+            return None;
+        }
+        if let Some(index) = self.free.iter().position(|&(_, pages)| pages >= num_pages) {
+            let (start, pages) = self.free[index];
+            if pages == num_pages {
+                self.free.swap_remove(index);
+            } else {
+                self.free[index] = (start + num_pages as u64 * PAGE_SIZE, pages - num_pages);
+            }
+            return Some(start);
+        }
+        let size = num_pages as u64 * PAGE_SIZE;
+        if self.next + size > ANON_START + ANON_SIZE {
+            return None;
+        }
+        let start = self.next;
+        self.next += size;
+        Some(start)
+    }
+
+    fn give_back(&mut self, start: u64, num_pages: usize) {
+        self.free.push((start, num_pages));
+    }
+
+    fn remove_live(&mut self, start: u64, num_pages: usize) -> bool {
+        match self
+            .live
+            .iter()
+            .position(|&range| range == (start, num_pages))
+        {
+            Some(index) => {
+                self.live.swap_remove(index);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+//unmaps the pages and returns their frames, carrying on past any page that isn't mapped
+//so the whole range is clear afterwards
+fn unmap_pages(memory: &mut MemoryContext, start: u64, num_pages: usize) -> Result<(), MapError> {
+    let mut result = Ok(());
+    for i in 0..num_pages as u64 {
+        let page = Page::<Size4KiB>::containing_address(VirtAddr::new(start + i * PAGE_SIZE));
+        match memory.mapper.unmap(page) {
+            Ok((frame, flush)) => {
+                flush.flush();
+                unsafe { memory.frame_allocator.deallocate_frame(frame) };
+            }
+            Err(_) => result = Err(MapError::NotMapped),
+        }
+    }
+    result
+}
+
+/// Maps `num_pages` fresh writable pages outside the heap and returns where they start.
+pub fn map_anonymous(num_pages: usize) -> Result<VirtAddr, MapError> {
+    if num_pages == 0 {
+        return Err(MapError::ZeroPages);
+    }
+    let start = interrupts::without_interrupts(|| ANON_RANGES.lock().take(num_pages))
+        .ok_or(MapError::OutOfAddressSpace)?;
+
+    let mapped = with_memory(|memory| {
+        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+        for i in 0..num_pages as u64 {
+            let page = Page::containing_address(VirtAddr::new(start + i * PAGE_SIZE));
+            let result = match memory.frame_allocator.allocate_frame() {
+                Some(frame) => unsafe {
+                    memory
+                        .mapper
+                        .map_to(page, frame, flags, &mut memory.frame_allocator)
+                        .map(|flush| flush.flush())
+                        .map_err(|err| {
+                            memory.frame_allocator.deallocate_frame(frame);
+                            match err {
+                                MapToError::FrameAllocationFailed => MapError::OutOfFrames,
+                                _ => MapError::AlreadyMapped,
+                            }
+                        })
+                },
+                None => Err(MapError::OutOfFrames),
+            };
+            if let Err(err) = result {
+                //roll back the pages mapped so far so a failed call leaves nothing behind
+                unmap_pages(memory, start, i as usize).expect("rollback of a partial mapping");
+                return Err(err);
+            }
+        }
+        Ok(())
+    })
+    .unwrap_or(Err(MapError::NotInstalled));
+
+    match mapped {
+        Ok(()) => {
+            interrupts::without_interrupts(|| ANON_RANGES.lock().live.push((start, num_pages)));
+            Ok(VirtAddr::new(start))
+        }
+        Err(err) => {
+            interrupts::without_interrupts(|| ANON_RANGES.lock().give_back(start, num_pages));
+            Err(err)
+        }
+    }
+}
+
+/// Unmaps a mapping returned by `map_anonymous` and frees its frames.
+/// `addr` and `num_pages` have to match one whole mapping, anything else is refused
+/// so this can't free the heap, a stack or part of another mapping.
+pub fn unmap(addr: VirtAddr, num_pages: usize) -> Result<(), MapError> {
+    if num_pages == 0 {
+        return Err(MapError::ZeroPages);
+    }
+    let start = addr.as_u64();
+    if !interrupts::without_interrupts(|| ANON_RANGES.lock().remove_live(start, num_pages)) {
+        return Err(MapError::NotAnonymous);
+    }
+    let unmapped = with_memory(|memory| unmap_pages(memory, start, num_pages))
+        .unwrap_or(Err(MapError::NotInstalled));
+    //the range is given back even if a page was missing, unmap_pages cleared the rest of it
+    interrupts::without_interrupts(|| ANON_RANGES.lock().give_back(start, num_pages));
+    unmapped
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test_case]
+    fn frame_counter() {
+        let before = frame_stats().unwrap();
+        assert_eq!(before.allocated + before.free, before.total);
+        for _ in 0..3 {
+            assert!(
+                with_memory(|memory| memory.frame_allocator.allocate_frame())
+                    .unwrap()
+                    .is_some()
+            );
+        }
+        let after = frame_stats().unwrap();
+        assert_eq!(after.allocated, before.allocated + 3);
+        assert_eq!(after.free, before.free - 3);
+        assert_eq!(after.total, before.total);
+    }
+
+    #[test_case]
+    fn anonymous_mapping() {
+        let frames_before = frame_stats().unwrap().allocated;
+        let base = map_anonymous(4).unwrap();
+        for page in 0..4u64 {
+            let ptr = (base + page * PAGE_SIZE).as_mut_ptr::<u64>();
+            unsafe {
+                ptr.write_volatile(page);
+                assert_eq!(ptr.read_volatile(), page);
+            }
+        }
+        unmap(base, 4).unwrap();
+        //page table frames may have been allocated along the way, but the 4 data frames are back
+        assert!(frame_stats().unwrap().allocated <= frames_before + 3);
+        assert_eq!(unmap(base, 4), Err(MapError::NotAnonymous));
+
+        //the freed range is reused
+        let again = map_anonymous(4).unwrap();
+        assert_eq!(again, base);
+        unmap(again, 4).unwrap();
+    }
+
+    #[test_case]
+    fn unmap_rejects_other_memory() {
+        let heap = VirtAddr::new(crate::allocator::HEAP_START as u64);
+        assert_eq!(unmap(heap, 1), Err(MapError::NotAnonymous));
+        //Running off the end of the region, or starting mid page
+        let last = VirtAddr::new(ANON_START + ANON_SIZE - PAGE_SIZE);
+        assert_eq!(unmap(last, 2), Err(MapError::NotAnonymous));
+        assert_eq!(
+            unmap(VirtAddr::new(ANON_START + 8), 1),
+            Err(MapError::NotAnonymous)
+        );
+
+        assert_eq!(map_anonymous(0), Err(MapError::ZeroPages));
+        assert_eq!(
+            unmap(VirtAddr::new(ANON_START), 0),
+            Err(MapError::ZeroPages)
+        );
+        //Two mappings in a row never share an address
+        let (first, second) = (map_anonymous(1).unwrap(), map_anonymous(1).unwrap());
+        assert_ne!(first, second);
+        unmap(first, 1).unwrap();
+        unmap(second, 1).unwrap();
+
+        //Only a whole mapping is taken back, never more or less of it
+        let mapping = map_anonymous(4).unwrap();
+        let neighbour = map_anonymous(4).unwrap();
+        assert_eq!(unmap(mapping, 8), Err(MapError::NotAnonymous));
+        assert_eq!(unmap(mapping, 2), Err(MapError::NotAnonymous));
+        assert_eq!(unmap(mapping + PAGE_SIZE, 3), Err(MapError::NotAnonymous));
+        unsafe { neighbour.as_mut_ptr::<u64>().write_volatile(7) };
+        unmap(mapping, 4).unwrap();
+        assert_eq!(unsafe { neighbour.as_ptr::<u64>().read_volatile() }, 7);
+        unmap(neighbour, 4).unwrap();
+    }
+
+    #[test_case]
+    fn demand_zero_region() {
+        let start = VirtAddr::new(0x_6666_0000_0000);
+        assert!(register_demand_region(start, 2 * PAGE_SIZE));
+        let ptr = (start + PAGE_SIZE + 8u64).as_mut_ptr::<u64>();
+        unsafe {
+            assert_eq!(ptr.read_volatile(), 0);
+            ptr.write_volatile(42);
+            assert_eq!(ptr.read_volatile(), 42);
+        }
+    }
+}
@@ -0,0 +1,185 @@
+use alloc::vec::Vec;
+use x86_64::instructions::interrupts;
+use x86_64::instructions::port::Port;
+
+const CONFIG_ADDRESS: u16 = 0xCF8;
+const CONFIG_DATA: u16 = 0xCFC;
+const ENABLE: u32 = 1 << 31;
+
+const MAX_BUS: u16 = 256;
+const MAX_SLOT: u8 = 32;
+const MAX_FUNCTION: u8 = 8;
+
+const OFFSET_VENDOR: u8 = 0x00; //device id in the upper half
+const OFFSET_COMMAND: u8 = 0x04; //status in the upper half
+const OFFSET_CLASS: u8 = 0x08; //class, subclass, prog if, revision from the top byte down
+const OFFSET_HEADER: u8 = 0x0C; //header type is byte 2
+const OFFSET_BAR0: u8 = 0x10;
+
+const COMMAND_BUS_MASTER: u32 = 1 << 2;
+
+const NO_DEVICE: u16 = 0xFFFF;
+const MULTI_FUNCTION: u8 = 0x80;
+const HEADER_TYPE_MASK: u8 = 0x7F;
+
+/// A function found on the PCI bus.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PciDevice {
+    pub bus: u8,
+    pub slot: u8,
+    pub function: u8,
+    pub vendor_id: u16,
+    pub device_id: u16,
+    pub class: u8,
+    pub subclass: u8,
+    pub prog_if: u8,
+    pub header_type: u8,
+    pub bars: [u32; 6], //only the first bar_count() are meaningful
+}
+
+impl PciDevice {
+    /// Lets the device initiate DMA, which most drivers need.
+    pub fn enable_bus_mastering(&self) {
+        let command = config_read_u32(self.bus, self.slot, self.function, OFFSET_COMMAND);
+        //Status bits are write-one-to-clear, so only the command half is written back
+        let command = command & 0xFFFF | COMMAND_BUS_MASTER;
+        unsafe { config_write_u32(self.bus, self.slot, self.function, OFFSET_COMMAND, command) }
+    }
+
+    /// General devices have 6 BARs, PCI-to-PCI bridges 2 and CardBus bridges none.
+    pub fn bar_count(&self) -> usize {
+        match self.header_type {
+            0 => 6,
+            1 => 2,
+            _ => 0,
+        }
+    }
+}
+
+fn config_address(bus: u8, slot: u8, function: u8, offset: u8) -> u32 {
+    debug_assert!(slot < MAX_SLOT && function < MAX_FUNCTION);
+    ENABLE
+        | (bus as u32) << 16
+        | (slot as u32) << 11
+        | (function as u32) << 8
+        | (offset & 0xFC) as u32
+}
+
+/// Reads a dword of configuration space. The offset has to be dword aligned.
+pub fn config_read_u32(bus: u8, slot: u8, function: u8, offset: u8) -> u32 {
+    assert!(offset.is_multiple_of(4), "unaligned PCI config read");
+    let mut address_port = Port::<u32>::new(CONFIG_ADDRESS);
+    let mut data_port = Port::<u32>::new(CONFIG_DATA);
+    //The address and data accesses have to stay paired
+    interrupts::without_interrupts(|| unsafe {
+        address_port.write(config_address(bus, slot, function, offset));
+        data_port.read()
+    })
+}
+
+/// Reads a word of configuration space. The offset has to be word aligned.
+pub fn config_read_u16(bus: u8, slot: u8, function: u8, offset: u8) -> u16 {
+    assert!(offset.is_multiple_of(2), "unaligned PCI config read");
+    let dword = config_read_u32(bus, slot, function, offset & !3);
+    (dword >> ((offset & 2) * 8)) as u16
+}
+
+pub fn config_read_u8(bus: u8, slot: u8, function: u8, offset: u8) -> u8 {
+    let dword = config_read_u32(bus, slot, function, offset & !3);
+    (dword >> ((offset & 3) * 8)) as u8
+}
+
+/// Writes a dword of configuration space. The offset has to be dword aligned.
+///
+/// # Safety
+/// Configuration writes reprogram the device, e.g. moving its BARs over memory in use.
+pub unsafe fn config_write_u32(bus: u8, slot: u8, function: u8, offset: u8, value: u32) {
+    assert!(offset.is_multiple_of(4), "unaligned PCI config write");
+    let mut address_port = Port::<u32>::new(CONFIG_ADDRESS);
+    let mut data_port = Port::<u32>::new(CONFIG_DATA);
+    interrupts::without_interrupts(|| {
+        address_port.write(config_address(bus, slot, function, offset));
+        data_port.write(value);
+    })
+}
+
+fn vendor_id(bus: u8, slot: u8, function: u8) -> u16 {
+    config_read_u16(bus, slot, function, OFFSET_VENDOR)
+}
+
+fn header_type(bus: u8, slot: u8, function: u8) -> u8 {
+    config_read_u8(bus, slot, function, OFFSET_HEADER + 2)
+}
+
+fn read_device(bus: u8, slot: u8, function: u8) -> PciDevice {
+    let id = config_read_u32(bus, slot, function, OFFSET_VENDOR);
+    let class = config_read_u32(bus, slot, function, OFFSET_CLASS);
+    let mut device = PciDevice {
+        bus,
+        slot,
+        function,
+        vendor_id: id as u16,
+        device_id: (id >> 16) as u16,
+        class: (class >> 24) as u8,
+        subclass: (class >> 16) as u8,
+        prog_if: (class >> 8) as u8,
+        header_type: header_type(bus, slot, function) & HEADER_TYPE_MASK,
+        bars: [0; 6],
+    };
+    for i in 0..device.bar_count() {
+        device.bars[i] = config_read_u32(bus, slot, function, OFFSET_BAR0 + 4 * i as u8);
+    }
+    device
+}
+
+/// Scans every bus, slot and function for devices.
+pub fn enumerate() -> Vec<PciDevice> {
+    let mut devices = Vec::new();
+    for bus in 0..MAX_BUS {
+        let bus = bus as u8;
+        for slot in 0..MAX_SLOT {
+            if vendor_id(bus, slot, 0) == NO_DEVICE {
+                continue;
+            }
+            devices.push(read_device(bus, slot, 0));
+            //Other functions are only probed on multi-function devices
+            if header_type(bus, slot, 0) & MULTI_FUNCTION != 0 {
+                for function in 1..MAX_FUNCTION {
+                    if vendor_id(bus, slot, function) != NO_DEVICE {
+                        devices.push(read_device(bus, slot, function));
+                    }
+                }
+            }
+        }
+    }
+    devices
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test_case]
+    fn finds_host_bridge() {
+        let devices = enumerate();
+        //QEMU's default machine has an i440FX host bridge at 00:00.0
+        let bridge = devices
+            .iter()
+            .find(|d| d.bus == 0 && d.slot == 0 && d.function == 0)
+            .expect("no device at 00:00.0");
+        assert_eq!((bridge.class, bridge.subclass), (0x06, 0x00));
+        assert_eq!(bridge.vendor_id, 0x8086);
+        assert!(devices.iter().all(|d| d.vendor_id != NO_DEVICE));
+    }
+
+    #[test_case]
+    fn config_reads() {
+        let vendor = config_read_u16(0, 0, 0, OFFSET_VENDOR);
+        assert_ne!(vendor, NO_DEVICE);
+        assert_ne!(vendor, 0);
+        let dword = config_read_u32(0, 0, 0, OFFSET_VENDOR);
+        assert_eq!(dword as u16, vendor);
+        assert_eq!(config_read_u16(0, 0, 0, 2), (dword >> 16) as u16);
+        assert_eq!(config_read_u8(0, 0, 0, 1), (dword >> 8) as u8);
+    }
+}
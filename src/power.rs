@@ -0,0 +1,63 @@
+use crate::{acpi, hlt_loop};
+use x86_64::instructions::{interrupts, port::Port, tables::lidt};
+use x86_64::{structures::DescriptorTablePointer, VirtAddr};
+
+//Emulator power off ports, written with the S5 sleep enable bit
+const QEMU_SHUTDOWN_PORT: u16 = 0x604;
+const BOCHS_SHUTDOWN_PORT: u16 = 0xB004; //also older QEMU
+const EMULATOR_SHUTDOWN_VALUE: u16 = 0x2000;
+
+/// Powers the machine off through ACPI, falling back to the emulator ports and finally halting.
+pub fn shutdown() -> ! {
+    interrupts::disable();
+    acpi::enter_s5();
+    unsafe {
+        Port::<u16>::new(QEMU_SHUTDOWN_PORT).write(EMULATOR_SHUTDOWN_VALUE);
+        Port::<u16>::new(BOCHS_SHUTDOWN_PORT).write(EMULATOR_SHUTDOWN_VALUE);
+    }
+    hlt_loop()
+}
+
+const KEYBOARD_CONTROLLER_STATUS: u16 = 0x64; //also the command port
+const INPUT_BUFFER_FULL: u8 = 1 << 1;
+const PULSE_RESET_LINE: u8 = 0xFE;
+//How long to give each reset method before moving on to the next
+const RESET_SPINS: usize = 1_000_000;
+
+fn wait_for_reset() {
+    for _ in 0..RESET_SPINS {
+        core::hint::spin_loop();
+    }
+}
+
+/// Restarts the machine, trying the cleanest method first:
+/// 1. The ACPI reset register, if the FADT has one.
+/// 2. Pulsing the CPU reset line through the 8042 keyboard controller.
+/// 3. A triple fault - with an empty IDT the next exception can't be delivered and the CPU resets.
+pub fn reboot() -> ! {
+    interrupts::disable();
+    if acpi::reset() {
+        wait_for_reset();
+    }
+
+    let mut controller = Port::<u8>::new(KEYBOARD_CONTROLLER_STATUS);
+    unsafe {
+        //The controller ignores commands until it has taken the last input byte
+        for _ in 0..RESET_SPINS {
+            if controller.read() & INPUT_BUFFER_FULL == 0 {
+                break;
+            }
+        }
+        controller.write(PULSE_RESET_LINE);
+    }
+    wait_for_reset();
+
+    unsafe {
+        lidt(&DescriptorTablePointer {
+            limit: 0,
+            base: VirtAddr::zero(),
+        });
+    }
+    interrupts::int3();
+    hlt_loop()
+}
@@ -0,0 +1,144 @@
+use super::PixelFormat;
+use crate::graphics::colors::DEFAULT_PALETTE;
+
+/// A 24 bit RGB color, packed into whatever format the framebuffer uses when drawn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Color {
+    pub const BLACK: Color = Color::new(0, 0, 0);
+    pub const WHITE: Color = Color::new(255, 255, 255);
+    pub const GRAY: Color = Color::new(128, 128, 128);
+    pub const RED: Color = Color::new(255, 0, 0);
+    pub const GREEN: Color = Color::new(0, 255, 0);
+    pub const BLUE: Color = Color::new(0, 0, 255);
+    pub const YELLOW: Color = Color::new(255, 255, 0);
+    pub const CYAN: Color = Color::new(0, 255, 255);
+    pub const MAGENTA: Color = Color::new(255, 0, 255);
+
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b }
+    }
+
+    pub const fn gray(level: u8) -> Self {
+        Self::new(level, level, level)
+    }
+
+    /// Packs the color into a single pixel of the given format. Only the first `bytes_per_pixel`
+    /// bytes are meaningful, the rest are zero.
+    pub fn to_framebuffer_bytes(&self, format: PixelFormat) -> [u8; 4] {
+        match format {
+            PixelFormat::Rgb => [self.r, self.g, self.b, 0],
+            PixelFormat::Bgr => [self.b, self.g, self.r, 0],
+            PixelFormat::Indexed => [self.palette_index(), 0, 0, 0],
+            PixelFormat::Grayscale => [self.luminance(), 0, 0, 0],
+        }
+    }
+
+    /// Reads a single pixel in the given format back into a color.
+    pub fn from_framebuffer_bytes(bytes: &[u8], format: PixelFormat) -> Self {
+        match format {
+            PixelFormat::Rgb => Self::new(bytes[0], bytes[1], bytes[2]),
+            PixelFormat::Bgr => Self::new(bytes[2], bytes[1], bytes[0]),
+            PixelFormat::Grayscale => Self::gray(bytes[0]),
+            PixelFormat::Indexed => {
+                let entry = &DEFAULT_PALETTE[bytes[0] as usize * 3..][..3];
+                //Scale the 6 bit palette channels up to 8 bits
+                Self::new(
+                    (entry[0] as u32 * 255 / 63) as u8,
+                    (entry[1] as u32 * 255 / 63) as u8,
+                    (entry[2] as u32 * 255 / 63) as u8,
+                )
+            }
+        }
+    }
+
+    /// Perceived brightness, weighting the channels like BT.601.
+    pub fn luminance(&self) -> u8 {
+        ((self.r as u32 * 299 + self.g as u32 * 587 + self.b as u32 * 114 + 500) / 1000) as u8
+    }
+
+    /// Composites this color over `under` with the given opacity, 255 being fully opaque.
+    pub fn blend_over(&self, under: Color, alpha: u8) -> Color {
+        let mix = |over: u8, under: u8| {
+            //Rounded rather than truncated, so fully opaque and fully clear are exact
+            ((over as u32 * alpha as u32 + under as u32 * (255 - alpha as u32) + 127) / 255) as u8
+        };
+        Color::new(
+            mix(self.r, under.r),
+            mix(self.g, under.g),
+            mix(self.b, under.b),
+        )
+    }
+
+    //Index of the closest color in the default VGA palette
+    fn palette_index(&self) -> u8 {
+        let mut best = (0, u32::MAX);
+        for (index, entry) in DEFAULT_PALETTE.chunks_exact(3).enumerate() {
+            //Palette entries only have 6 bits per channel
+            let distance: u32 = [self.r, self.g, self.b]
+                .iter()
+                .zip(entry)
+                .map(|(&channel, &palette)| {
+                    let difference = channel as i32 - (palette as i32 * 255 / 63);
+                    (difference * difference) as u32
+                })
+                .sum();
+            if distance < best.1 {
+                best = (index, distance);
+            }
+        }
+        best.0 as u8
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Color;
+    use crate::render::PixelFormat;
+
+    #[test_case]
+    fn pack_red() {
+        assert_eq!(
+            Color::RED.to_framebuffer_bytes(PixelFormat::Rgb)[..3],
+            [255, 0, 0]
+        );
+        assert_eq!(
+            Color::RED.to_framebuffer_bytes(PixelFormat::Bgr)[..3],
+            [0, 0, 255]
+        );
+    }
+
+    #[test_case]
+    fn pack_grayscale() {
+        assert_eq!(
+            Color::WHITE.to_framebuffer_bytes(PixelFormat::Grayscale)[0],
+            255
+        );
+        assert_eq!(
+            Color::BLACK.to_framebuffer_bytes(PixelFormat::Grayscale)[0],
+            0
+        );
+        assert_eq!(
+            Color::from_framebuffer_bytes(&[77], PixelFormat::Grayscale),
+            Color::gray(77)
+        );
+    }
+
+    #[test_case]
+    fn pack_indexed() {
+        //Matches the entries of the default palette
+        assert_eq!(
+            Color::BLACK.to_framebuffer_bytes(PixelFormat::Indexed)[0],
+            0x00
+        );
+        assert_eq!(
+            Color::WHITE.to_framebuffer_bytes(PixelFormat::Indexed)[0],
+            0x0F
+        );
+    }
+}
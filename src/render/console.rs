@@ -0,0 +1,317 @@
+use super::draw::GLYPH_SIZE;
+use super::{Color, Framebuffer, FRAMEBUFFER};
+use core::fmt;
+use spinning_top::Spinlock;
+
+/// The console shared by everything that echoes text to the screen.
+pub static CONSOLE: Spinlock<Console> = Spinlock::new(Console::new(Color::WHITE, Color::BLACK));
+
+//The 16 colors of ANSI escape codes 30-37 and 90-97 (40-47 and 100-107 for the background)
+const ANSI_COLORS: [Color; 16] = [
+    Color::new(0, 0, 0),
+    Color::new(170, 0, 0),
+    Color::new(0, 170, 0),
+    Color::new(170, 85, 0),
+    Color::new(0, 0, 170),
+    Color::new(170, 0, 170),
+    Color::new(0, 170, 170),
+    Color::new(170, 170, 170),
+    Color::new(85, 85, 85),
+    Color::new(255, 85, 85),
+    Color::new(85, 255, 85),
+    Color::new(255, 255, 85),
+    Color::new(85, 85, 255),
+    Color::new(255, 85, 255),
+    Color::new(85, 255, 255),
+    Color::new(255, 255, 255),
+];
+
+const MAX_PARAMETERS: usize = 4;
+
+//Where the console is in an escape sequence, kept across writes so one can be split between them
+#[derive(Clone, Copy)]
+enum Escape {
+    None,
+    Started, //seen ESC
+    //seen ESC [, collecting the ;-separated parameters
+    Csi {
+        parameters: [u16; MAX_PARAMETERS],
+        count: usize, //parameters started so far
+    },
+}
+
+/// A text terminal on the framebuffer that scrolls once output reaches the bottom.
+///
+/// Writing through `fmt::Write` draws into the screen's back buffer, `write_to` targets any framebuffer.
+/// ANSI color escapes (`ESC [ ... m`) change the colors of the text that follows, other escape
+/// sequences are dropped.
+pub struct Console {
+    column: usize,
+    row: usize,
+    foreground: Color,
+    background: Color,
+    //what `ESC [ 0 m` goes back to
+    default_colors: (Color, Color),
+    escape: Escape,
+}
+
+impl Console {
+    pub const fn new(foreground: Color, background: Color) -> Self {
+        Self {
+            column: 0,
+            row: 0,
+            foreground,
+            background,
+            default_colors: (foreground, background),
+            escape: Escape::None,
+        }
+    }
+
+    /// Colors for the characters written from now on.
+    pub fn set_colors(&mut self, foreground: Color, background: Color) {
+        self.foreground = foreground;
+        self.background = background;
+    }
+
+    pub fn colors(&self) -> (Color, Color) {
+        (self.foreground, self.background)
+    }
+
+    //Feeds a character to the escape parser, false if it's ordinary text to draw
+    fn escape(&mut self, character: char) -> bool {
+        match (self.escape, character) {
+            (Escape::None, '\x1b') => self.escape = Escape::Started,
+            (Escape::None, _) => return false,
+            (Escape::Started, '[') => {
+                self.escape = Escape::Csi {
+                    parameters: [0; MAX_PARAMETERS],
+                    count: 0,
+                }
+            }
+            (
+                Escape::Csi {
+                    mut parameters,
+                    mut count,
+                },
+                '0'..='9' | ';',
+            ) => {
+                if count == 0 {
+                    count = 1;
+                }
+                match character.to_digit(10) {
+                    //Parameters past the ones kept are parsed but ignored
+                    Some(digit) if count <= MAX_PARAMETERS => {
+                        let parameter = &mut parameters[count - 1];
+                        *parameter = parameter.saturating_mul(10).saturating_add(digit as u16);
+                    }
+                    Some(_) => {}
+                    None => count += 1,
+                }
+                self.escape = Escape::Csi { parameters, count };
+            }
+            //The final byte ends the sequence, only m (colors) is understood
+            (Escape::Csi { parameters, count }, '@'..='~') => {
+                self.escape = Escape::None;
+                if character == 'm' {
+                    self.select_graphic_rendition(&parameters[..count.clamp(1, MAX_PARAMETERS)]);
+                }
+            }
+            //Anything else means the sequence is broken - drop it and treat the character as text
+            _ => {
+                self.escape = Escape::None;
+                return self.escape(character);
+            }
+        }
+        true
+    }
+
+    fn select_graphic_rendition(&mut self, parameters: &[u16]) {
+        for &parameter in parameters {
+            match parameter {
+                0 => (self.foreground, self.background) = self.default_colors,
+                30..=37 => self.foreground = ANSI_COLORS[parameter as usize - 30],
+                39 => self.foreground = self.default_colors.0,
+                40..=47 => self.background = ANSI_COLORS[parameter as usize - 40],
+                49 => self.background = self.default_colors.1,
+                90..=97 => self.foreground = ANSI_COLORS[parameter as usize - 90 + 8],
+                100..=107 => self.background = ANSI_COLORS[parameter as usize - 100 + 8],
+                _ => {}
+            }
+        }
+    }
+
+    /// The cursor position as (column, row) in characters.
+    pub fn cursor(&self) -> (usize, usize) {
+        (self.column, self.row)
+    }
+
+    pub fn write_to(&mut self, framebuffer: &mut Framebuffer, s: &str) {
+        let info = framebuffer.info();
+        let columns = info.width / GLYPH_SIZE;
+        let rows = info.height / GLYPH_SIZE;
+        if columns == 0 || rows == 0 {
+            return;
+        }
+
+        for character in s.chars() {
+            if self.escape(character) {
+                continue;
+            }
+            match character {
+                '\n' => self.new_line(framebuffer, rows),
+                '\r' => self.column = 0,
+                //Backspace erases the previous character, but never past the start of the line
+                '\x08' => {
+                    if self.column > 0 {
+                        self.column -= 1;
+                        let (x, y) = (self.column * GLYPH_SIZE, self.row * GLYPH_SIZE);
+                        framebuffer.fill_rect(
+                            x as isize,
+                            y as isize,
+                            GLYPH_SIZE,
+                            GLYPH_SIZE,
+                            self.background,
+                        );
+                    }
+                }
+                character => {
+                    if self.column >= columns {
+                        self.new_line(framebuffer, rows);
+                    }
+                    let (x, y) = (self.column * GLYPH_SIZE, self.row * GLYPH_SIZE);
+                    //Clear the cell first so overwritten characters don't leave pixels behind
+                    framebuffer.fill_rect(
+                        x as isize,
+                        y as isize,
+                        GLYPH_SIZE,
+                        GLYPH_SIZE,
+                        self.background,
+                    );
+                    framebuffer.draw_character(x, y, character, self.foreground);
+                    self.column += 1;
+                }
+            }
+        }
+    }
+
+    /// Clears the framebuffer to the background color and moves the cursor back to the top left.
+    pub fn clear_screen(&mut self, framebuffer: &mut Framebuffer) {
+        framebuffer.clear(self.background);
+        self.column = 0;
+        self.row = 0;
+    }
+
+    fn new_line(&mut self, framebuffer: &mut Framebuffer, rows: usize) {
+        self.column = 0;
+        if self.row + 1 < rows {
+            self.row += 1;
+        } else {
+            framebuffer.scroll_up(GLYPH_SIZE, self.background);
+        }
+    }
+}
+
+impl fmt::Write for Console {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write_to(&mut FRAMEBUFFER.lock(), s);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Console;
+    use crate::render::{Color, Framebuffer, FramebufferInfo, PixelFormat};
+    use alloc::{vec, vec::Vec};
+    use core::fmt::Write;
+
+    #[test_case]
+    fn console_scrolls() {
+        //Room for 2x2 characters
+        let info = FramebufferInfo {
+            width: 16,
+            height: 16,
+            stride: 16,
+            bytes_per_pixel: 1,
+            format: PixelFormat::Indexed,
+        };
+        let mut front: Vec<u8> = vec![0; info.stride * info.height];
+        let mut framebuffer = unsafe { Framebuffer::new(info, front.as_mut_ptr()) };
+        let mut console = Console::new(Color::WHITE, Color::BLACK);
+
+        console.write_to(&mut framebuffer, "\u{7}\n\n\nab");
+        assert_eq!(console.cursor(), (2, 1));
+        //The placeholder box was scrolled off, leaving the top line blank
+        assert!(framebuffer.back_buffer()[..16 * 8]
+            .iter()
+            .all(|&byte| byte == 0));
+
+        //Writing past the last column wraps and scrolls "ab" up to the top line
+        console.write_to(&mut framebuffer, "c\r");
+        assert_eq!(console.cursor(), (0, 1));
+        assert!(framebuffer.back_buffer()[..16 * 8]
+            .iter()
+            .any(|&byte| byte != 0));
+    }
+
+    #[test_case]
+    fn console_fmt_write() {
+        let mut console = Console::new(Color::WHITE, Color::BLACK);
+        write!(console, "{}\n{}", 1, 2).unwrap();
+        assert_eq!(console.cursor(), (1, 1));
+    }
+
+    #[test_case]
+    fn console_colors() {
+        let info = FramebufferInfo {
+            width: 16,
+            height: 16,
+            stride: 64,
+            bytes_per_pixel: 4,
+            format: PixelFormat::Rgb,
+        };
+        let mut front: Vec<u8> = vec![0; info.stride * info.height];
+        let mut framebuffer = unsafe { Framebuffer::new(info, front.as_mut_ptr()) };
+        let color_at = |framebuffer: &Framebuffer, x, y| {
+            Color::from_framebuffer_bytes(framebuffer.pixel(x, y).unwrap(), PixelFormat::Rgb)
+        };
+        let mut console = Console::new(Color::WHITE, Color::BLACK);
+
+        //Split across writes, with a parameter past the ones that are kept
+        console.write_to(&mut framebuffer, "\x1b[31;4");
+        console.write_to(&mut framebuffer, "4;1;2;3m|");
+        let (red, blue) = (Color::new(170, 0, 0), Color::new(0, 0, 170));
+        //The bar of '|' is in columns 3 and 4, the rest of the cell is background
+        assert_eq!(color_at(&framebuffer, 3, 0), red);
+        assert_eq!(color_at(&framebuffer, 0, 0), blue);
+        assert_eq!(console.colors(), (red, blue));
+
+        //Unknown and broken sequences are dropped, the character that broke one is drawn
+        console.write_to(&mut framebuffer, "\x1b[5h\x1b[3\n\x1bq");
+        assert_eq!(console.colors(), (red, blue));
+        assert_eq!(console.cursor(), (1, 1));
+        console.write_to(&mut framebuffer, "\x1b[m");
+        assert_eq!(console.colors(), (Color::WHITE, Color::BLACK));
+        console.set_colors(Color::GREEN, Color::BLACK);
+        assert_eq!(console.colors(), (Color::GREEN, Color::BLACK));
+    }
+
+    #[test_case]
+    fn console_backspace() {
+        let info = FramebufferInfo {
+            width: 16,
+            height: 8,
+            stride: 16,
+            bytes_per_pixel: 1,
+            format: PixelFormat::Indexed,
+        };
+        let mut front: Vec<u8> = vec![0; info.stride * info.height];
+        let mut framebuffer = unsafe { Framebuffer::new(info, front.as_mut_ptr()) };
+        let mut console = Console::new(Color::WHITE, Color::BLACK);
+
+        console.write_to(&mut framebuffer, "\u{7}\x08\x08");
+        //The glyph is erased and the extra backspace stops at the line start
+        assert_eq!(console.cursor(), (0, 0));
+        assert!(framebuffer.back_buffer().iter().all(|&byte| byte == 0));
+    }
+}
@@ -0,0 +1,186 @@
+use super::{Color, Framebuffer, Rect, FRAMEBUFFER};
+use spinning_top::Spinlock;
+
+const WIDTH: usize = 8;
+const HEIGHT: usize = 11;
+
+//X is the outline, o the fill and . see-through
+const ARROW: [&[u8; WIDTH]; HEIGHT] = [
+    b"X.......",
+    b"XX......",
+    b"XoX.....",
+    b"XooX....",
+    b"XoooX...",
+    b"XooooX..",
+    b"XoooooX.",
+    b"XooXXXXX",
+    b"XoX.....",
+    b"XX......",
+    b"X.......",
+];
+
+/// The mouse cursor shared by everything drawing to the screen.
+pub static CURSOR: Spinlock<Cursor> = Spinlock::new(Cursor::new());
+
+/// An arrow drawn over the scene, with the pixels it covers saved so they can be put back when it
+/// moves. Anything drawn under a visible cursor is overwritten by the restore, so hide it first.
+pub struct Cursor {
+    x: usize,
+    y: usize,
+    visible: bool,
+    //Back buffer pixels under the arrow, only the part inside `saved_rect` is valid
+    saved: [[u8; 4]; WIDTH * HEIGHT],
+    saved_rect: Option<Rect>,
+}
+
+impl Cursor {
+    pub const fn new() -> Self {
+        Self {
+            x: 0,
+            y: 0,
+            visible: false,
+            saved: [[0; 4]; WIDTH * HEIGHT],
+            saved_rect: None,
+        }
+    }
+
+    /// The position of the arrow's tip.
+    pub fn position(&self) -> (usize, usize) {
+        (self.x, self.y)
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    fn draw(&mut self, framebuffer: &mut Framebuffer) {
+        let rect = match framebuffer
+            .screen_rect()
+            .intersection(&Rect::new(self.x, self.y, WIDTH, HEIGHT))
+        {
+            Some(rect) => rect,
+            None => return,
+        };
+        let outline = framebuffer.pixel_bytes(Color::BLACK);
+        let fill = framebuffer.pixel_bytes(Color::WHITE);
+        //Clipped near the right and bottom edges, so only what's on screen is saved and drawn
+        for y in rect.y..rect.bottom() {
+            for x in rect.x..rect.right() {
+                let (column, row) = (x - self.x, y - self.y);
+                let saved = &mut self.saved[row * WIDTH + column];
+                let pixel = framebuffer.pixel(x, y).unwrap();
+                saved[..pixel.len()].copy_from_slice(pixel);
+                match ARROW[row][column] {
+                    b'X' => framebuffer.put_pixel(x, y, &outline),
+                    b'o' => framebuffer.put_pixel(x, y, &fill),
+                    _ => {}
+                }
+            }
+        }
+        framebuffer.mark_dirty(rect);
+        self.saved_rect = Some(rect);
+    }
+
+    fn restore(&mut self, framebuffer: &mut Framebuffer) {
+        if let Some(rect) = self.saved_rect.take() {
+            for y in rect.y..rect.bottom() {
+                for x in rect.x..rect.right() {
+                    let saved = self.saved[(y - self.y) * WIDTH + (x - self.x)];
+                    framebuffer.put_pixel(x, y, &saved);
+                }
+            }
+            framebuffer.mark_dirty(rect);
+        }
+    }
+
+    /// Moves the tip to (x, y), clamped to the screen.
+    pub fn move_to(&mut self, framebuffer: &mut Framebuffer, x: isize, y: isize) {
+        let info = framebuffer.info();
+        self.restore(framebuffer);
+        self.x = x.clamp(0, info.width as isize - 1) as usize;
+        self.y = y.clamp(0, info.height as isize - 1) as usize;
+        if self.visible {
+            self.draw(framebuffer);
+        }
+    }
+
+    pub fn set_visible(&mut self, framebuffer: &mut Framebuffer, visible: bool) {
+        if visible == self.visible {
+            return;
+        }
+        self.visible = visible;
+        if visible {
+            self.draw(framebuffer);
+        } else {
+            self.restore(framebuffer);
+        }
+    }
+}
+
+impl Default for Cursor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Moves the screen's cursor, see `Cursor::move_to`.
+pub fn set_cursor_position(x: isize, y: isize) {
+    CURSOR.lock().move_to(&mut FRAMEBUFFER.lock(), x, y);
+}
+
+/// Moves the screen's cursor by a mouse movement, where positive `dy` is up.
+pub fn move_cursor(dx: i16, dy: i16) {
+    let mut cursor = CURSOR.lock();
+    let (x, y) = cursor.position();
+    cursor.move_to(
+        &mut FRAMEBUFFER.lock(),
+        x as isize + dx as isize,
+        y as isize - dy as isize,
+    );
+}
+
+pub fn show_cursor(visible: bool) {
+    CURSOR.lock().set_visible(&mut FRAMEBUFFER.lock(), visible);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::render::{FramebufferInfo, PixelFormat};
+    use alloc::{vec, vec::Vec};
+
+    #[test_case]
+    fn cursor_restores_pixels() {
+        let info = FramebufferInfo {
+            width: 16,
+            height: 16,
+            stride: 16,
+            bytes_per_pixel: 1,
+            format: PixelFormat::Indexed,
+        };
+        let mut front: Vec<u8> = vec![0; info.stride * info.height];
+        let mut framebuffer = unsafe { Framebuffer::new(info, front.as_mut_ptr()) };
+        for (i, byte) in framebuffer.back_buffer_mut().iter_mut().enumerate() {
+            *byte = i as u8;
+        }
+        let scene: Vec<u8> = framebuffer.back_buffer().to_vec();
+
+        let mut cursor = Cursor::new();
+        cursor.move_to(&mut framebuffer, 2, 2);
+        cursor.set_visible(&mut framebuffer, true);
+        assert_ne!(framebuffer.back_buffer(), &scene[..]);
+
+        //Past the bottom right corner, so the arrow is clamped and mostly clipped
+        cursor.move_to(&mut framebuffer, 100, 100);
+        assert_eq!(cursor.position(), (15, 15));
+        let back = framebuffer.back_buffer();
+        //Everything but the one visible pixel of the arrow is the scene again
+        assert!((0..back.len() - 1).all(|i| back[i] == scene[i]));
+        assert_ne!(back[back.len() - 1], scene[scene.len() - 1]);
+
+        cursor.move_to(&mut framebuffer, -5, 3);
+        assert_eq!(cursor.position(), (0, 3));
+        cursor.set_visible(&mut framebuffer, false);
+        assert_eq!(framebuffer.back_buffer(), &scene[..]);
+    }
+}
@@ -0,0 +1,569 @@
+use super::framebuffer::{fill_pixels, Framebuffer, FRAMEBUFFER};
+use super::{Color, Rect};
+use crate::graphics::lines::Bresenham;
+use font8x8::UnicodeFonts;
+
+pub(super) const GLYPH_SIZE: usize = 8;
+
+//Hollow box drawn in place of characters the font has no printable glyph for
+const PLACEHOLDER_GLYPH: [u8; GLYPH_SIZE] = [0xFF, 0x81, 0x81, 0x81, 0x81, 0x81, 0x81, 0xFF];
+
+pub(super) fn glyph(character: char) -> [u8; GLYPH_SIZE] {
+    match character {
+        ' '..='~' => font8x8::BASIC_FONTS
+            .get(character)
+            .unwrap_or(PLACEHOLDER_GLYPH),
+        _ => PLACEHOLDER_GLYPH,
+    }
+}
+
+impl Framebuffer {
+    //Clips a rectangle to the screen, None if none of it is on screen
+    pub(super) fn clip_rect(
+        &self,
+        x: isize,
+        y: isize,
+        width: usize,
+        height: usize,
+    ) -> Option<Rect> {
+        let info = self.info();
+        let x0 = x.clamp(0, info.width as isize) as usize;
+        let y0 = y.clamp(0, info.height as isize) as usize;
+        let x1 = x
+            .saturating_add_unsigned(width)
+            .clamp(0, info.width as isize) as usize;
+        let y1 = y
+            .saturating_add_unsigned(height)
+            .clamp(0, info.height as isize) as usize;
+        let rect = Rect::new(x0, y0, x1 - x0, y1 - y0);
+        (!rect.is_empty()).then_some(rect)
+    }
+
+    /// Fills a rectangle with its top left corner at (x, y), the parts off screen are clipped.
+    pub fn fill_rect(&mut self, x: isize, y: isize, width: usize, height: usize, color: Color) {
+        let rect = match self.clip_rect(x, y, width, height) {
+            Some(rect) => rect,
+            None => return,
+        };
+        self.mark_dirty(rect);
+        let info = self.info();
+        let pixel = self.pixel_bytes(color);
+        let row_bytes = info.width * info.bytes_per_pixel;
+        let back = self.back_mut();
+        //Rows of the back buffer are contiguous, so each row of the rect is a single span
+        for row in rect.y..rect.bottom() {
+            let start = row * row_bytes + rect.x * info.bytes_per_pixel;
+            let end = row * row_bytes + rect.right() * info.bytes_per_pixel;
+            fill_pixels(&mut back[start..end], &pixel[..info.bytes_per_pixel]);
+        }
+    }
+
+    /// Draws the 1 pixel wide outline of a rectangle, the parts off screen are clipped.
+    pub fn draw_rect(&mut self, x: isize, y: isize, width: usize, height: usize, color: Color) {
+        if width == 0 || height == 0 {
+            return;
+        }
+        let right = x.saturating_add_unsigned(width - 1);
+        let bottom = y.saturating_add_unsigned(height - 1);
+        self.fill_rect(x, y, width, 1, color);
+        self.fill_rect(x, bottom, width, 1, color);
+        self.fill_rect(x, y, 1, height, color);
+        self.fill_rect(right, y, 1, height, color);
+    }
+
+    /// Draws a line from (x0, y0) to (x1, y1) inclusive, pixels off screen are clipped.
+    pub fn draw_line(&mut self, x0: isize, y0: isize, x1: isize, y1: isize, color: Color) {
+        let info = self.info();
+        let (width, height) = (info.width as isize, info.height as isize);
+        //Both ends past the same edge means the whole line is off screen
+        if (x0 < 0 && x1 < 0)
+            || (y0 < 0 && y1 < 0)
+            || (x0 >= width && x1 >= width)
+            || (y0 >= height && y1 >= height)
+        {
+            return;
+        }
+        //The line's bounding box, clip_rect drops the part that's off screen
+        if let Some(rect) = self.clip_rect(
+            x0.min(x1),
+            y0.min(y1),
+            x0.abs_diff(x1) + 1,
+            y0.abs_diff(y1) + 1,
+        ) {
+            self.mark_dirty(rect);
+        }
+        let pixel = self.pixel_bytes(color);
+        for (x, y) in Bresenham::new((x0, y0), (x1, y1)) {
+            if x >= 0 && y >= 0 {
+                self.put_pixel(x as usize, y as usize, &pixel);
+            }
+        }
+    }
+
+    /// Draws the 1 pixel wide outline of a circle around (cx, cy), the parts off screen are clipped.
+    /// A radius of 0 draws just the center pixel.
+    pub fn draw_circle(&mut self, cx: isize, cy: isize, r: usize, color: Color) {
+        let circle = CircleRows::new(r);
+        let cx = cx as i128;
+        for row in 0..self.info().height {
+            let RowColumns { single, span } = match circle.columns(row as i128 - cy as i128) {
+                Some(columns) => columns,
+                None => continue,
+            };
+            if let Some(x) = single {
+                self.fill_span(cx + x, cx + x, row, color);
+                self.fill_span(cx - x, cx - x, row, color);
+            }
+            if let Some((low, high)) = span {
+                self.fill_span(cx + low, cx + high, row, color);
+                self.fill_span(cx - high, cx - low, row, color);
+            }
+        }
+    }
+
+    /// Fills a circle around (cx, cy) out to the outline `draw_circle` draws, the parts off screen
+    /// are clipped.
+    pub fn fill_circle(&mut self, cx: isize, cy: isize, r: usize, color: Color) {
+        let circle = CircleRows::new(r);
+        let cx = cx as i128;
+        for row in 0..self.info().height {
+            //The outermost column lit on the row, one span per row so nothing is filled twice
+            let half_width = match circle.columns(row as i128 - cy as i128) {
+                Some(RowColumns {
+                    single: Some(x), ..
+                }) => x,
+                Some(RowColumns {
+                    span: Some((_, high)),
+                    ..
+                }) => high,
+                _ => continue,
+            };
+            self.fill_span(cx - half_width, cx + half_width, row, color);
+        }
+    }
+
+    //Fills columns left to right inclusive of a row that's on screen, clipping columns anywhere
+    fn fill_span(&mut self, left: i128, right: i128, row: usize, color: Color) {
+        let (left, right) = (left.max(0), right.min(self.info().width as i128 - 1));
+        if left <= right {
+            let width = (right - left + 1) as usize;
+            self.fill_rect(left as isize, row as isize, width, 1, color);
+        }
+    }
+
+    /// Moves the whole back buffer up by `lines` pixel rows and fills the exposed rows at the bottom.
+    pub fn scroll_up(&mut self, lines: usize, fill: Color) {
+        let info = self.info();
+        let lines = lines.min(info.height);
+        let row_bytes = info.width * info.bytes_per_pixel;
+        //Overlapping move, rows are contiguous so it's a single memmove
+        self.back_mut().copy_within(lines * row_bytes.., 0);
+        self.mark_dirty(self.screen_rect());
+        self.fill_rect(0, (info.height - lines) as isize, info.width, lines, fill);
+    }
+
+    /// Draws a single character with its top left corner at (x, y).
+    pub fn draw_character(&mut self, x: usize, y: usize, character: char, color: Color) {
+        self.mark_dirty(Rect::new(x, y, GLYPH_SIZE, GLYPH_SIZE));
+        let pixel = self.pixel_bytes(color);
+        for (row, byte) in glyph(character).iter().enumerate() {
+            for bit in 0..GLYPH_SIZE {
+                //Bit 0 is the leftmost pixel of the row
+                if *byte & 1 << bit != 0 {
+                    self.put_pixel(x + bit, y + row, &pixel);
+                }
+            }
+        }
+    }
+
+    /// Draws a string starting at (x, y), one 8x8 glyph per character.
+    /// Text running past the right or bottom edge of the screen is clipped.
+    pub fn draw_text(&mut self, x: usize, y: usize, s: &str, color: Color) {
+        let info = self.info();
+        if y >= info.height {
+            return;
+        }
+        for (i, character) in s.chars().enumerate() {
+            let glyph_x = x + i * GLYPH_SIZE;
+            if glyph_x >= info.width {
+                break;
+            }
+            //put_pixel skips pixels past the edges, which clips glyphs that are partially on screen
+            self.draw_character(glyph_x, y, character, color);
+        }
+    }
+
+    /// Like `draw_text`, but every pixel of the font is drawn as a `scale` x `scale` block, so
+    /// characters take `8 * scale` pixels each way. A scale of 0 draws at scale 1.
+    pub fn draw_text_scaled(&mut self, x: usize, y: usize, s: &str, color: Color, scale: usize) {
+        let scale = scale.max(1);
+        let size = GLYPH_SIZE.saturating_mul(scale);
+        let info = self.info();
+        if y >= info.height {
+            return;
+        }
+        for (i, character) in s.chars().enumerate() {
+            let glyph_x = x.saturating_add(i.saturating_mul(size));
+            if glyph_x >= info.width {
+                break;
+            }
+            for (row, byte) in glyph(character).iter().enumerate() {
+                let block_y = y.saturating_add(row.saturating_mul(scale));
+                if block_y >= info.height {
+                    break;
+                }
+                for bit in 0..GLYPH_SIZE {
+                    let block_x = glyph_x.saturating_add(bit.saturating_mul(scale));
+                    //Both corners are on screen from here, so the casts can't wrap - fill_rect
+                    //clips the blocks that run past the right and bottom edges
+                    if block_x >= info.width {
+                        break;
+                    }
+                    if *byte & 1 << bit != 0 {
+                        self.fill_rect(block_x as isize, block_y as isize, scale, scale, color);
+                    }
+                }
+            }
+        }
+    }
+}
+
+//A circle's outline one row at a time, so only the rows on screen cost anything however large r is.
+//The pixels are exactly the midpoint algorithm's: walking the first octant from (r, 0), x steps in
+//at each y until x(x - 1) + y² < r² holds - so row y of the octant is at the largest such x.
+//Rows are relative to the center, and u128 keeps every square in range
+struct CircleRows {
+    r: u128,
+}
+
+impl CircleRows {
+    fn new(r: usize) -> CircleRows {
+        //Coordinates are isize, so a larger radius can't change which pixels are on screen
+        CircleRows {
+            r: r.min(isize::MAX as usize) as u128,
+        }
+    }
+
+    //The octant's x on row y, None if the octant never reaches it
+    fn octant_x(&self, y: u128) -> Option<u128> {
+        let k = (self.r * self.r).checked_sub(y * y).filter(|&k| k > 0)?;
+        Some((4 * k - 3).isqrt().div_ceil(2))
+    }
+
+    //The last row of the octant whose x is at least x
+    fn last_row_reaching(&self, x: u128) -> Option<u128> {
+        (self.r * self.r + x)
+            .checked_sub(x * x + 1)
+            .map(u128::isqrt)
+    }
+
+    //The columns right of the center lit on row dy, None if the circle doesn't reach it
+    fn columns(&self, dy: i128) -> Option<RowColumns> {
+        let y = dy.unsigned_abs();
+        if y > self.r {
+            return None;
+        }
+        if self.r == 0 {
+            return Some(RowColumns {
+                single: Some(0),
+                span: None,
+            });
+        }
+        let single = self.octant_x(y).filter(|&x| x >= y);
+        //Mirrored, row y holds the octant's rows whose x is y - only those up to the diagonal
+        let span = self.last_row_reaching(y).and_then(|high| {
+            let low = self.last_row_reaching(y + 1).map_or(0, |row| row + 1);
+            (low <= high.min(y)).then_some((low as i128, high.min(y) as i128))
+        });
+        Some(RowColumns {
+            single: single.map(|x| x as i128),
+            span,
+        })
+    }
+}
+
+//A single pixel from the octant running down, and a span from the mirrored octant running across
+struct RowColumns {
+    single: Option<i128>,
+    span: Option<(i128, i128)>,
+}
+
+/// Fills the screen's back buffer with one color.
+pub fn clear(color: Color) {
+    FRAMEBUFFER.lock().clear(color);
+}
+
+/// Fills a rectangle in the screen's back buffer, see `Framebuffer::fill_rect`.
+pub fn fill_rect(x: isize, y: isize, width: usize, height: usize, color: Color) {
+    FRAMEBUFFER.lock().fill_rect(x, y, width, height, color);
+}
+
+/// Draws a rectangle outline in the screen's back buffer, see `Framebuffer::draw_rect`.
+pub fn draw_rect(x: isize, y: isize, width: usize, height: usize, color: Color) {
+    FRAMEBUFFER.lock().draw_rect(x, y, width, height, color);
+}
+
+/// Draws a line into the screen's back buffer, see `Framebuffer::draw_line`.
+pub fn draw_line(x0: isize, y0: isize, x1: isize, y1: isize, color: Color) {
+    FRAMEBUFFER.lock().draw_line(x0, y0, x1, y1, color);
+}
+
+/// Draws a circle outline in the screen's back buffer, see `Framebuffer::draw_circle`.
+pub fn draw_circle(cx: isize, cy: isize, r: usize, color: Color) {
+    FRAMEBUFFER.lock().draw_circle(cx, cy, r, color);
+}
+
+/// Fills a circle in the screen's back buffer, see `Framebuffer::fill_circle`.
+pub fn fill_circle(cx: isize, cy: isize, r: usize, color: Color) {
+    FRAMEBUFFER.lock().fill_circle(cx, cy, r, color);
+}
+
+/// Draws a string into the screen's back buffer, see `Framebuffer::draw_text`.
+pub fn draw_text(x: usize, y: usize, s: &str, color: Color) {
+    FRAMEBUFFER.lock().draw_text(x, y, s, color);
+}
+
+/// Draws an enlarged string into the screen's back buffer, see `Framebuffer::draw_text_scaled`.
+pub fn draw_text_scaled(x: usize, y: usize, s: &str, color: Color, scale: usize) {
+    FRAMEBUFFER.lock().draw_text_scaled(x, y, s, color, scale);
+}
+
+#[cfg(test)]
+mod test {
+    use crate::render::{Color, Framebuffer, FramebufferInfo, PixelFormat};
+    use alloc::{vec, vec::Vec};
+    use font8x8::UnicodeFonts;
+
+    //Framebuffer over a heap allocated front buffer, so tests don't draw to the screen
+    fn test_framebuffer(width: usize, height: usize) -> (Framebuffer, Vec<u8>) {
+        let info = FramebufferInfo {
+            width,
+            height,
+            stride: width,
+            bytes_per_pixel: 1,
+            format: PixelFormat::Indexed,
+        };
+        let mut front = vec![0; width * height];
+        let framebuffer = unsafe { Framebuffer::new(info, front.as_mut_ptr()) };
+        (framebuffer, front)
+    }
+
+    fn is_lit(framebuffer: &Framebuffer, x: usize, y: usize) -> bool {
+        framebuffer.pixel(x, y).unwrap()[0] != 0
+    }
+
+    #[test_case]
+    fn draw_text_matches_font() {
+        let (mut framebuffer, _front) = test_framebuffer(32, 16);
+        framebuffer.draw_text(4, 2, "AB", Color::WHITE);
+
+        for (i, character) in ['A', 'B'].into_iter().enumerate() {
+            let glyph = font8x8::BASIC_FONTS.get(character).unwrap();
+            for (row, byte) in glyph.iter().enumerate() {
+                for bit in 0..8 {
+                    let lit = is_lit(&framebuffer, 4 + i * 8 + bit, 2 + row);
+                    assert_eq!(lit, *byte & 1 << bit != 0);
+                }
+            }
+        }
+        //Glyph row 0 of 'A' is 0x0C, so only its 3rd and 4th pixel are lit
+        assert!(is_lit(&framebuffer, 6, 2) && is_lit(&framebuffer, 7, 2));
+        assert!(!is_lit(&framebuffer, 5, 2));
+    }
+
+    #[test_case]
+    fn draw_text_clips_and_placeholders() {
+        let (mut framebuffer, _front) = test_framebuffer(12, 4);
+        //Only the top half of the glyphs fits and "yz" is past the right edge entirely
+        framebuffer.draw_text(0, 0, "\u{7}xyz", Color::WHITE);
+        //The placeholder box has a solid top row
+        assert!((0..8).all(|x| is_lit(&framebuffer, x, 0)));
+        assert!(is_lit(&framebuffer, 0, 3) && !is_lit(&framebuffer, 1, 3));
+    }
+
+    #[test_case]
+    fn draw_text_scaled_blocks() {
+        let (mut framebuffer, _front) = test_framebuffer(64, 32);
+        framebuffer.draw_text_scaled(1, 2, "A", Color::WHITE, 3);
+        let glyph = font8x8::BASIC_FONTS.get('A').unwrap();
+        let bits: u32 = glyph.iter().map(|byte| byte.count_ones()).sum();
+        assert_eq!(
+            lit_pixels(&framebuffer, 1, 2, 25, 26),
+            (bits as usize * 9, true)
+        );
+        //Row 0 of 'A' is 0x0C: pixels 2 and 3 become the block from x = 7 to 12
+        assert!((7..13).all(|x| (2..5).all(|y| is_lit(&framebuffer, x, y))));
+        assert!(!is_lit(&framebuffer, 6, 2) && !is_lit(&framebuffer, 13, 2));
+
+        //Scale 0 is scale 1, and the advance scales with the glyphs
+        let (mut unscaled, _front) = test_framebuffer(64, 32);
+        unscaled.draw_text(0, 0, "AB", Color::WHITE);
+        let (mut framebuffer, _front) = test_framebuffer(64, 32);
+        framebuffer.draw_text_scaled(0, 0, "AB", Color::WHITE, 0);
+        for (x, y) in (0..32).flat_map(|y| (0..64).map(move |x| (x, y))) {
+            assert_eq!(is_lit(&framebuffer, x, y), is_lit(&unscaled, x, y));
+        }
+
+        //Clipped at the right and bottom edges
+        let (mut framebuffer, _front) = test_framebuffer(64, 32);
+        framebuffer.draw_text_scaled(40, 20, "AB", Color::WHITE, 4);
+        let (count, inside) = lit_pixels(&framebuffer, 40, 20, 64, 32);
+        assert!(count > 0 && inside);
+
+        //A scale far past the screen only leaves the top left block, which covers it all
+        let (mut framebuffer, _front) = test_framebuffer(16, 16);
+        framebuffer.draw_text_scaled(0, 0, "\u{7}", Color::WHITE, usize::MAX);
+        assert_eq!(lit_pixels(&framebuffer, 0, 0, 16, 16).0, 256);
+        let (mut framebuffer, _front) = test_framebuffer(16, 16);
+        framebuffer.draw_text_scaled(4, 4, "AB", Color::WHITE, usize::MAX / 2);
+        assert_eq!(lit_pixels(&framebuffer, 0, 0, 16, 16).0, 0);
+    }
+
+    //Number of lit pixels, and whether all of them are inside the given rect
+    fn lit_pixels(
+        framebuffer: &Framebuffer,
+        x0: usize,
+        y0: usize,
+        x1: usize,
+        y1: usize,
+    ) -> (usize, bool) {
+        let info = framebuffer.info();
+        let mut count = 0;
+        let mut inside = true;
+        for y in 0..info.height {
+            for x in 0..info.width {
+                if is_lit(framebuffer, x, y) {
+                    count += 1;
+                    inside &= (x0..x1).contains(&x) && (y0..y1).contains(&y);
+                }
+            }
+        }
+        (count, inside)
+    }
+
+    #[test_case]
+    fn fill_rect_on_screen() {
+        let (mut framebuffer, _front) = test_framebuffer(16, 16);
+        framebuffer.fill_rect(2, 3, 4, 5, Color::WHITE);
+        assert_eq!(lit_pixels(&framebuffer, 2, 3, 6, 8), (20, true));
+
+        let (mut framebuffer, _front) = test_framebuffer(16, 16);
+        framebuffer.draw_rect(2, 3, 4, 5, Color::WHITE);
+        //Outline only - the 2x3 inside stays empty
+        assert_eq!(lit_pixels(&framebuffer, 2, 3, 6, 8), (14, true));
+        assert!(!is_lit(&framebuffer, 3, 4));
+    }
+
+    #[test_case]
+    fn fill_rect_clipped() {
+        let (mut framebuffer, _front) = test_framebuffer(16, 16);
+        framebuffer.fill_rect(-4, 12, 8, 100, Color::WHITE);
+        assert_eq!(lit_pixels(&framebuffer, 0, 12, 4, 16), (16, true));
+
+        //Entirely off screen
+        framebuffer.fill_rect(-10, -10, 5, 5, Color::WHITE);
+        framebuffer.draw_rect(20, 0, 5, 5, Color::WHITE);
+        assert_eq!(lit_pixels(&framebuffer, 0, 12, 4, 16), (16, true));
+    }
+
+    #[test_case]
+    fn zero_size_rect() {
+        let (mut framebuffer, _front) = test_framebuffer(16, 16);
+        framebuffer.fill_rect(4, 4, 0, 5, Color::WHITE);
+        framebuffer.fill_rect(4, 4, 5, 0, Color::WHITE);
+        framebuffer.draw_rect(4, 4, 0, 0, Color::WHITE);
+        assert_eq!(lit_pixels(&framebuffer, 0, 0, 0, 0).0, 0);
+    }
+
+    #[test_case]
+    fn draw_diagonal_line() {
+        let (mut framebuffer, _front) = test_framebuffer(16, 16);
+        framebuffer.draw_line(2, 2, 9, 9, Color::WHITE);
+        assert_eq!(lit_pixels(&framebuffer, 0, 0, 16, 16).0, 8);
+        assert!((2..=9).all(|i| is_lit(&framebuffer, i, i)));
+    }
+
+    #[test_case]
+    fn draw_line_clipped_and_degenerate() {
+        let (mut framebuffer, _front) = test_framebuffer(16, 16);
+        //Vertical line running off both the top and bottom edge
+        framebuffer.draw_line(5, -10, 5, 30, Color::WHITE);
+        assert_eq!(lit_pixels(&framebuffer, 5, 0, 6, 16), (16, true));
+
+        let (mut framebuffer, _front) = test_framebuffer(16, 16);
+        framebuffer.draw_line(7, 3, 7, 3, Color::WHITE);
+        assert_eq!(lit_pixels(&framebuffer, 7, 3, 8, 4), (1, true));
+    }
+
+    #[test_case]
+    fn draw_small_circle() {
+        let (mut framebuffer, _front) = test_framebuffer(16, 16);
+        framebuffer.draw_circle(5, 5, 2, Color::WHITE);
+        let outline = [
+            (3, 5),
+            (7, 5),
+            (5, 3),
+            (5, 7),
+            (3, 4),
+            (3, 6),
+            (7, 4),
+            (7, 6),
+            (4, 3),
+            (6, 3),
+            (4, 7),
+            (6, 7),
+        ];
+        assert_eq!(lit_pixels(&framebuffer, 0, 0, 16, 16).0, outline.len());
+        assert!(outline.iter().all(|&(x, y)| is_lit(&framebuffer, x, y)));
+
+        let (mut framebuffer, _front) = test_framebuffer(16, 16);
+        framebuffer.draw_circle(5, 5, 0, Color::WHITE);
+        framebuffer.fill_circle(9, 9, 0, Color::WHITE);
+        assert_eq!(lit_pixels(&framebuffer, 0, 0, 16, 16).0, 2);
+        assert!(is_lit(&framebuffer, 5, 5) && is_lit(&framebuffer, 9, 9));
+    }
+
+    #[test_case]
+    fn fill_circle_covers_outline() {
+        let (mut framebuffer, _front) = test_framebuffer(16, 16);
+        framebuffer.fill_circle(5, 5, 2, Color::WHITE);
+        //Rows of 3, 5, 5, 5 and 3 pixels
+        assert_eq!(lit_pixels(&framebuffer, 3, 3, 8, 8), (21, true));
+
+        let (mut outline, _front) = test_framebuffer(16, 16);
+        outline.draw_circle(5, 5, 2, Color::WHITE);
+        for (y, x) in (0..16).flat_map(|y| (0..16).map(move |x| (y, x))) {
+            assert!(!is_lit(&outline, x, y) || is_lit(&framebuffer, x, y));
+        }
+    }
+
+    #[test_case]
+    fn huge_circles() {
+        let (mut framebuffer, _front) = test_framebuffer(16, 16);
+        //Only the rows on screen are worked out, so none of these take long or overflow
+        framebuffer.draw_circle(8, 8, usize::MAX, Color::WHITE);
+        framebuffer.fill_circle(isize::MAX, isize::MIN, usize::MAX, Color::WHITE);
+        framebuffer.draw_circle(isize::MAX, 5, 3, Color::WHITE);
+        assert_eq!(lit_pixels(&framebuffer, 0, 0, 16, 16).0, 0);
+
+        //Centered far off to the left, the outline crosses the screen at x = 5
+        let r = 1 << 40;
+        framebuffer.draw_circle(5 - r as isize, 8, r, Color::WHITE);
+        assert!(is_lit(&framebuffer, 5, 8) && !is_lit(&framebuffer, 4, 8));
+        let (mut framebuffer, _front) = test_framebuffer(16, 16);
+        framebuffer.fill_circle(5 - r as isize, 8, r, Color::WHITE);
+        assert!(is_lit(&framebuffer, 5, 8) && is_lit(&framebuffer, 0, 8));
+        assert!(!is_lit(&framebuffer, 6, 8));
+    }
+
+    #[test_case]
+    fn circle_clipped() {
+        let (mut framebuffer, _front) = test_framebuffer(16, 16);
+        framebuffer.fill_circle(0, 15, 3, Color::WHITE);
+        //The quarter that's on screen: rows of 4, 4, 3 and 2 pixels
+        assert_eq!(lit_pixels(&framebuffer, 0, 12, 4, 16), (13, true));
+        framebuffer.draw_circle(-20, 5, 4, Color::WHITE);
+        framebuffer.draw_circle(8, 8, 30, Color::WHITE);
+        assert_eq!(lit_pixels(&framebuffer, 0, 12, 4, 16), (13, true));
+    }
+}
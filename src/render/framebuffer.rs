@@ -0,0 +1,369 @@
+use alloc::{vec, vec::Vec};
+use conquer_once::spin::Lazy;
+use core::ptr::copy_nonoverlapping;
+use spinning_top::Spinlock;
+
+use super::{Color, Rect};
+
+/// How the bytes of a single pixel are laid out in the framebuffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PixelFormat {
+    Rgb,
+    Bgr,
+    /// One byte per pixel, an index into the VGA color palette.
+    Indexed,
+    /// One byte per pixel, the pixel's brightness.
+    Grayscale,
+}
+
+/// Describes the layout of a hardware framebuffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FramebufferInfo {
+    pub width: usize,
+    pub height: usize,
+    /// Bytes between the start of two rows - may be larger than `width * bytes_per_pixel`.
+    pub stride: usize,
+    pub bytes_per_pixel: usize,
+    pub format: PixelFormat,
+}
+
+impl FramebufferInfo {
+    //Bytes of actual pixel data in a row, without any padding
+    fn row_bytes(&self) -> usize {
+        self.width * self.bytes_per_pixel
+    }
+}
+
+//The bootloader leaves the framebuffer setup to us, and Vga::setup switches to mode 13h (320x200x256)
+pub(super) const VGA_FRONT: *mut u8 = 0xa0000 as *mut u8;
+pub(super) const VGA_INFO: FramebufferInfo = FramebufferInfo {
+    width: 320,
+    height: 200,
+    stride: 320,
+    bytes_per_pixel: 1,
+    format: PixelFormat::Indexed,
+};
+
+/// The screen's layout - the one place drawing code should get the screen size from.
+pub fn framebuffer_info() -> FramebufferInfo {
+    FRAMEBUFFER.lock().info()
+}
+
+/// Provides mutable access to the screen's framebuffer.
+pub static FRAMEBUFFER: Lazy<Spinlock<Framebuffer>> =
+    Lazy::new(|| Spinlock::new(unsafe { Framebuffer::new(VGA_INFO, VGA_FRONT) }));
+
+/// A hardware framebuffer with an off-screen back buffer.
+///
+/// Drawing only touches the back buffer, `present` then copies it to the hardware in one pass so a half
+/// drawn frame is never visible.
+pub struct Framebuffer {
+    info: FramebufferInfo,
+    front: *mut u8, //null for off-screen framebuffers, see Surface
+    back: Vec<u8>,  //rows are tightly packed, i.e. its stride is info.row_bytes()
+    //regions of the back buffer changed since the last present - never overlapping
+    dirty: Vec<Rect>,
+}
+
+//The front buffer pointer is only ever accessed through the Spinlock
+unsafe impl Send for Framebuffer {}
+
+impl Framebuffer {
+    /// # Safety
+    /// `front` must point to `info.stride * info.height` bytes of writable memory that stays
+    /// valid for the lifetime of the framebuffer.
+    pub unsafe fn new(info: FramebufferInfo, front: *mut u8) -> Self {
+        assert!(
+            info.stride >= info.row_bytes(),
+            "framebuffer stride smaller than a row"
+        );
+        Self {
+            info,
+            front,
+            back: vec![0; info.row_bytes() * info.height],
+            dirty: Vec::new(),
+        }
+    }
+
+    //Only ever drawn to, with nothing to present to - back is the whole picture
+    pub(super) fn offscreen(info: FramebufferInfo) -> Self {
+        unsafe { Self::new(info, core::ptr::null_mut()) }
+    }
+
+    fn is_offscreen(&self) -> bool {
+        self.front.is_null()
+    }
+
+    pub fn info(&self) -> FramebufferInfo {
+        self.info
+    }
+
+    pub fn screen_rect(&self) -> Rect {
+        Rect::new(0, 0, self.info.width, self.info.height)
+    }
+
+    /// The back buffer, `width * bytes_per_pixel` bytes per row without any padding.
+    /// Since any byte could be changed the whole screen is marked dirty.
+    pub fn back_buffer_mut(&mut self) -> &mut [u8] {
+        self.mark_dirty(self.screen_rect());
+        &mut self.back
+    }
+
+    //For primitives that mark the region they draw to themselves
+    pub(super) fn back_mut(&mut self) -> &mut [u8] {
+        &mut self.back
+    }
+
+    pub fn back_buffer(&self) -> &[u8] {
+        &self.back
+    }
+
+    //Byte range of the pixel at (x, y) in the back buffer - None if it's off screen
+    fn pixel_range(&self, x: usize, y: usize) -> Option<core::ops::Range<usize>> {
+        if x >= self.info.width || y >= self.info.height {
+            return None;
+        }
+        let start = y * self.info.row_bytes() + x * self.info.bytes_per_pixel;
+        Some(start..start + self.info.bytes_per_pixel)
+    }
+
+    /// The bytes of the pixel at (x, y) in the back buffer, or `None` if it's off screen.
+    pub fn pixel(&self, x: usize, y: usize) -> Option<&[u8]> {
+        self.pixel_range(x, y).map(|range| &self.back[range])
+    }
+
+    //The color packed into the framebuffer's pixel format, only the first bytes_per_pixel bytes are used
+    pub(super) fn pixel_bytes(&self, color: Color) -> [u8; 4] {
+        color.to_framebuffer_bytes(self.info.format)
+    }
+
+    /// Sets a pixel in the back buffer, pixels off screen are ignored.
+    pub fn set_pixel(&mut self, x: usize, y: usize, color: Color) {
+        if x < self.info.width && y < self.info.height {
+            self.mark_dirty(Rect::new(x, y, 1, 1));
+        }
+        let pixel = self.pixel_bytes(color);
+        self.put_pixel(x, y, &pixel);
+    }
+
+    //Like set_pixel with an already packed color, so primitives only pack once
+    //Doesn't mark anything dirty, callers mark their whole bounding box instead
+    #[inline]
+    pub(super) fn put_pixel(&mut self, x: usize, y: usize, pixel: &[u8; 4]) {
+        if let Some(range) = self.pixel_range(x, y) {
+            self.back[range].copy_from_slice(&pixel[..self.info.bytes_per_pixel]);
+        }
+    }
+
+    /// Fills the whole back buffer with one color.
+    pub fn clear(&mut self, color: Color) {
+        let pixel = self.pixel_bytes(color);
+        let bytes_per_pixel = self.info.bytes_per_pixel;
+        fill_pixels(&mut self.back, &pixel[..bytes_per_pixel]);
+        self.mark_dirty(self.screen_rect());
+    }
+
+    /// Records that a region of the back buffer changed, merging it with any dirty region it overlaps.
+    pub fn mark_dirty(&mut self, rect: Rect) {
+        if self.is_offscreen() {
+            return;
+        }
+        let mut rect = match self.screen_rect().intersection(&rect) {
+            Some(rect) => rect,
+            None => return,
+        };
+        //Merging can make the rect grow into others, so repeat until nothing overlaps anymore
+        while let Some(index) = self.dirty.iter().position(|dirty| dirty.intersects(&rect)) {
+            rect = rect.union(&self.dirty.swap_remove(index));
+        }
+        self.dirty.push(rect);
+    }
+
+    pub fn dirty_rects(&self) -> &[Rect] {
+        &self.dirty
+    }
+
+    /// Copies only the dirty regions of the back buffer to the hardware framebuffer.
+    pub fn present_dirty(&mut self) {
+        if self.is_offscreen() {
+            return;
+        }
+        let row_bytes = self.info.row_bytes();
+        let bytes_per_pixel = self.info.bytes_per_pixel;
+        for rect in core::mem::take(&mut self.dirty) {
+            let span = rect.width * bytes_per_pixel;
+            for y in rect.y..rect.bottom() {
+                let offset = rect.x * bytes_per_pixel;
+                unsafe {
+                    copy_nonoverlapping(
+                        self.back.as_ptr().add(y * row_bytes + offset),
+                        self.front.add(y * self.info.stride + offset),
+                        span,
+                    );
+                }
+            }
+        }
+    }
+
+    //Pixel data of row y of the hardware framebuffer, without the padding
+    pub(super) fn front_row(&self, y: usize) -> &[u8] {
+        assert!(y < self.info.height);
+        if self.is_offscreen() {
+            return &self.back[y * self.info.row_bytes()..][..self.info.row_bytes()];
+        }
+        unsafe {
+            core::slice::from_raw_parts(self.front.add(y * self.info.stride), self.info.row_bytes())
+        }
+    }
+
+    /// Copies the back buffer to the hardware framebuffer.
+    pub fn present(&mut self) {
+        self.dirty.clear();
+        if self.is_offscreen() {
+            return;
+        }
+        let row_bytes = self.info.row_bytes();
+        unsafe {
+            if self.info.stride == row_bytes {
+                copy_nonoverlapping(self.back.as_ptr(), self.front, self.back.len());
+            } else {
+                //Padded rows have to be copied one at a time to skip the padding
+                for (y, row) in self.back.chunks_exact(row_bytes).enumerate() {
+                    copy_nonoverlapping(
+                        row.as_ptr(),
+                        self.front.add(y * self.info.stride),
+                        row_bytes,
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Fills `buffer`, which must start at a pixel boundary, with copies of `pixel`.
+/// Writes whole u64 words where alignment allows and the pixel size divides a word.
+pub(super) fn fill_pixels(buffer: &mut [u8], pixel: &[u8]) {
+    let bytes_per_pixel = pixel.len();
+    if 8 % bytes_per_pixel != 0 {
+        //A 3 byte pixel doesn't tile a word, so fall back to one pixel at a time
+        for chunk in buffer.chunks_exact_mut(bytes_per_pixel) {
+            chunk.copy_from_slice(pixel);
+        }
+        return;
+    }
+
+    let (prefix, words, suffix) = unsafe { buffer.align_to_mut::<u64>() };
+    let prefix_len = prefix.len();
+    for (i, byte) in prefix.iter_mut().enumerate() {
+        *byte = pixel[i % bytes_per_pixel];
+    }
+    //The words start prefix_len bytes into the buffer, so the pattern is shifted by that much
+    let mut pattern = [0; 8];
+    for (i, byte) in pattern.iter_mut().enumerate() {
+        *byte = pixel[(prefix_len + i) % bytes_per_pixel];
+    }
+    words.fill(u64::from_ne_bytes(pattern));
+    let suffix_start = prefix_len + words.len() * 8;
+    for (i, byte) in suffix.iter_mut().enumerate() {
+        *byte = pixel[(suffix_start + i) % bytes_per_pixel];
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{fill_pixels, framebuffer_info, Framebuffer, FramebufferInfo, PixelFormat};
+    use crate::render::{Color, Rect};
+    use alloc::{vec, vec::Vec};
+
+    #[test_case]
+    fn screen_geometry() {
+        //Mode 13h, set up by Vga::setup
+        let info = framebuffer_info();
+        assert_eq!((info.width, info.height), (320, 200));
+        assert_eq!(info.stride, info.width * info.bytes_per_pixel);
+        assert_eq!(info.format, PixelFormat::Indexed);
+    }
+
+    #[test_case]
+    fn present_padded_rows() {
+        //Each row has 4 bytes of padding that present must leave alone
+        let info = FramebufferInfo {
+            width: 8,
+            height: 4,
+            stride: 28,
+            bytes_per_pixel: 3,
+            format: PixelFormat::Rgb,
+        };
+        let mut front: Vec<u8> = vec![0xAA; info.stride * info.height];
+        let mut framebuffer = unsafe { Framebuffer::new(info, front.as_mut_ptr()) };
+        for (i, byte) in framebuffer.back_buffer_mut().iter_mut().enumerate() {
+            *byte = i as u8;
+        }
+        framebuffer.present();
+
+        for y in 0..info.height {
+            let row = &front[y * info.stride..(y + 1) * info.stride];
+            let back_row = &framebuffer.back_buffer()[y * 24..(y + 1) * 24];
+            assert_eq!(&row[..24], back_row);
+            assert!(row[24..].iter().all(|&byte| byte == 0xAA));
+        }
+    }
+
+    #[test_case]
+    fn clear_fills_every_pixel() {
+        let info = FramebufferInfo {
+            width: 13,
+            height: 7,
+            stride: 13,
+            bytes_per_pixel: 1,
+            format: PixelFormat::Indexed,
+        };
+        let mut front: Vec<u8> = vec![0; info.stride * info.height];
+        let mut framebuffer = unsafe { Framebuffer::new(info, front.as_mut_ptr()) };
+        framebuffer.clear(Color::RED);
+
+        let red = Color::RED.to_framebuffer_bytes(PixelFormat::Indexed);
+        for (x, y) in [(0, 0), (12, 0), (0, 6), (12, 6)] {
+            assert_eq!(framebuffer.pixel(x, y), Some(&red[..1]));
+        }
+    }
+
+    #[test_case]
+    fn fill_pixels_keeps_pattern_across_words() {
+        //Offset by one byte so the unaligned prefix shifts the pattern inside the words
+        let mut buffer = [0u8; 41];
+        fill_pixels(&mut buffer[1..], &[1, 2, 3, 4]);
+        for (i, byte) in buffer[1..].iter().enumerate() {
+            assert_eq!(*byte, [1, 2, 3, 4][i % 4]);
+        }
+    }
+
+    #[test_case]
+    fn dirty_rects_merge_and_present() {
+        let info = FramebufferInfo {
+            width: 32,
+            height: 32,
+            stride: 32,
+            bytes_per_pixel: 1,
+            format: PixelFormat::Indexed,
+        };
+        let mut front: Vec<u8> = vec![0; info.stride * info.height];
+        let mut framebuffer = unsafe { Framebuffer::new(info, front.as_mut_ptr()) };
+        assert!(framebuffer.dirty_rects().is_empty());
+
+        //Only the corner that was drawn to is reported
+        framebuffer.fill_rect(-2, -2, 6, 6, Color::WHITE);
+        assert_eq!(framebuffer.dirty_rects(), [Rect::new(0, 0, 4, 4)]);
+        //Overlapping regions are merged, separate ones are kept apart
+        framebuffer.fill_rect(2, 2, 4, 4, Color::WHITE);
+        framebuffer.fill_rect(20, 20, 2, 2, Color::WHITE);
+        assert_eq!(
+            framebuffer.dirty_rects(),
+            [Rect::new(0, 0, 6, 6), Rect::new(20, 20, 2, 2)]
+        );
+
+        framebuffer.present_dirty();
+        assert!(framebuffer.dirty_rects().is_empty());
+        assert_eq!(&front[..], framebuffer.back_buffer());
+    }
+}
@@ -0,0 +1,387 @@
+use super::{Color, Framebuffer, FRAMEBUFFER};
+use alloc::vec::Vec;
+use core::fmt;
+
+/// A decoded image, row by row from the top.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Color>,
+    /// Per pixel opacity in the same order as `pixels`, None if the image is opaque.
+    pub alpha: Option<Vec<u8>>,
+}
+
+impl Image {
+    pub fn pixel(&self, x: usize, y: usize) -> Option<Color> {
+        (x < self.width && y < self.height).then(|| self.pixels[y * self.width + x])
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeError {
+    NotBmp,
+    Truncated,
+    MalformedHeader,
+    UnsupportedBitDepth(u16),
+    UnsupportedCompression(u32),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecodeError::NotBmp => f.write_str("not a BMP file"),
+            DecodeError::Truncated => f.write_str("truncated BMP file"),
+            DecodeError::MalformedHeader => f.write_str("malformed BMP header"),
+            DecodeError::UnsupportedBitDepth(bits) => write!(f, "unsupported {} bit BMP", bits),
+            DecodeError::UnsupportedCompression(kind) => {
+                write!(f, "unsupported BMP compression {}", kind)
+            }
+        }
+    }
+}
+
+const MAGIC: &[u8; 2] = b"BM";
+const FILE_HEADER_SIZE: usize = 14;
+const INFO_HEADER_SIZE: u32 = 40; //BITMAPINFOHEADER, later versions only add fields after it
+const COMPRESSION_NONE: u32 = 0;
+
+fn read_u16(data: &[u8], offset: usize) -> Result<u16, DecodeError> {
+    let bytes = data.get(offset..offset + 2).ok_or(DecodeError::Truncated)?;
+    Ok(u16::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32, DecodeError> {
+    let bytes = data.get(offset..offset + 4).ok_or(DecodeError::Truncated)?;
+    Ok(u32::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+/// Decodes an uncompressed 24 or 32 bit BMP file. The fourth byte of 32 bit pixels is taken as
+/// alpha, unless it's zero throughout - plenty of encoders leave it unused.
+pub fn decode_bmp(data: &[u8]) -> Result<Image, DecodeError> {
+    if data.get(..2) != Some(MAGIC) {
+        return Err(DecodeError::NotBmp);
+    }
+    let pixel_offset = read_u32(data, 10)? as usize;
+    if read_u32(data, FILE_HEADER_SIZE)? < INFO_HEADER_SIZE {
+        return Err(DecodeError::MalformedHeader);
+    }
+    let width = read_u32(data, 18)? as i32;
+    let height = read_u32(data, 22)? as i32;
+    let planes = read_u16(data, 26)?;
+    let bits = read_u16(data, 28)?;
+    let compression = read_u32(data, 30)?;
+    if width <= 0 || height == 0 || planes != 1 {
+        return Err(DecodeError::MalformedHeader);
+    }
+    let bytes_per_pixel = match bits {
+        24 => 3,
+        32 => 4,
+        _ => return Err(DecodeError::UnsupportedBitDepth(bits)),
+    };
+    if compression != COMPRESSION_NONE {
+        return Err(DecodeError::UnsupportedCompression(compression));
+    }
+
+    //Positive heights store the bottom row first, negative ones are already top down
+    let bottom_up = height > 0;
+    let (width, height) = (width as usize, height.unsigned_abs() as usize);
+    //Rows are padded to a multiple of 4 bytes
+    let row_size = (width * bytes_per_pixel).div_ceil(4) * 4;
+    //Checked before allocating, so a bogus size in the header can't exhaust the heap
+    let pixel_data = row_size
+        .checked_mul(height)
+        .and_then(|size| data.get(pixel_offset..)?.get(..size))
+        .ok_or(DecodeError::Truncated)?;
+
+    let mut pixels = Vec::with_capacity(width * height);
+    let mut alpha = Vec::new();
+    for y in 0..height {
+        let stored = if bottom_up { height - 1 - y } else { y };
+        let row = &pixel_data[stored * row_size..][..width * bytes_per_pixel];
+        for pixel in row.chunks_exact(bytes_per_pixel) {
+            //Stored blue first
+            pixels.push(Color::new(pixel[2], pixel[1], pixel[0]));
+            if bytes_per_pixel == 4 {
+                alpha.push(pixel[3]);
+            }
+        }
+    }
+    Ok(Image {
+        width,
+        height,
+        pixels,
+        alpha: alpha.iter().any(|&a| a != 0).then_some(alpha),
+    })
+}
+
+impl Framebuffer {
+    //Calls `write` with the back buffer bytes and image index of every pixel of the image that lands
+    //on screen. Each row of the clipped image is one contiguous span of the back buffer
+    fn blit_pixels(
+        &mut self,
+        x: isize,
+        y: isize,
+        image: &Image,
+        mut write: impl FnMut(&mut [u8], usize),
+    ) {
+        let rect = match self.clip_rect(x, y, image.width, image.height) {
+            Some(rect) => rect,
+            None => return,
+        };
+        self.mark_dirty(rect);
+        let info = self.info();
+        let row_bytes = info.width * info.bytes_per_pixel;
+        //Columns of the image that land on screen
+        let left = (rect.x as isize - x) as usize;
+        let back = self.back_mut();
+        for screen_y in rect.y..rect.bottom() {
+            let image_row = (screen_y as isize - y) as usize * image.width + left;
+            let start = screen_y * row_bytes + rect.x * info.bytes_per_pixel;
+            let span = &mut back[start..start + rect.width * info.bytes_per_pixel];
+            for (i, pixel) in span.chunks_exact_mut(info.bytes_per_pixel).enumerate() {
+                write(pixel, image_row + i);
+            }
+        }
+    }
+
+    /// Draws an image with its top left corner at (x, y), the parts off screen are clipped.
+    /// Any alpha the image has is ignored, see `blit_alpha`.
+    pub fn blit(&mut self, x: isize, y: isize, image: &Image) {
+        let format = self.info().format;
+        self.blit_pixels(x, y, image, |pixel, i| {
+            let bytes = image.pixels[i].to_framebuffer_bytes(format);
+            pixel.copy_from_slice(&bytes[..pixel.len()]);
+        });
+    }
+
+    /// Composites an image over what's already in the back buffer at (x, y). `alpha` is the
+    /// opacity of the whole image, combined with the image's own per pixel alpha if it has any.
+    pub fn blit_alpha(&mut self, x: isize, y: isize, image: &Image, alpha: u8) {
+        match (alpha, &image.alpha) {
+            (0, _) => return,
+            (255, None) => return self.blit(x, y, image),
+            _ => {}
+        }
+        let format = self.info().format;
+        self.blit_pixels(x, y, image, |pixel, i| {
+            let alpha = match &image.alpha {
+                Some(mask) => ((mask[i] as u32 * alpha as u32 + 127) / 255) as u8,
+                None => alpha,
+            };
+            let color = match alpha {
+                0 => return,
+                255 => image.pixels[i],
+                _ => {
+                    let under = Color::from_framebuffer_bytes(pixel, format);
+                    image.pixels[i].blend_over(under, alpha)
+                }
+            };
+            pixel.copy_from_slice(&color.to_framebuffer_bytes(format)[..pixel.len()]);
+        });
+    }
+}
+
+/// Draws an image into the screen's back buffer, see `Framebuffer::blit`.
+pub fn blit(x: isize, y: isize, image: &Image) {
+    FRAMEBUFFER.lock().blit(x, y, image);
+}
+
+/// Composites an image over the screen's back buffer, see `Framebuffer::blit_alpha`.
+pub fn blit_alpha(x: isize, y: isize, image: &Image, alpha: u8) {
+    FRAMEBUFFER.lock().blit_alpha(x, y, image, alpha);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::render::{FramebufferInfo, PixelFormat};
+    use alloc::{vec, vec::Vec};
+
+    //A BMP with the given pixel rows, exactly as they're stored in the file
+    fn bmp(width: i32, height: i32, bits: u16, rows: &[&[u8]]) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(b"BM");
+        data.extend_from_slice(&0u32.to_le_bytes()); //file size, never checked
+        data.extend_from_slice(&0u32.to_le_bytes());
+        data.extend_from_slice(&54u32.to_le_bytes());
+        data.extend_from_slice(&40u32.to_le_bytes());
+        data.extend_from_slice(&width.to_le_bytes());
+        data.extend_from_slice(&height.to_le_bytes());
+        data.extend_from_slice(&1u16.to_le_bytes());
+        data.extend_from_slice(&bits.to_le_bytes());
+        data.extend_from_slice(&[0; 24]); //compression, sizes, resolution and palette counts
+        for row in rows {
+            data.extend_from_slice(row);
+        }
+        data
+    }
+
+    const RED: [u8; 3] = [0, 0, 255];
+    const GREEN: [u8; 3] = [0, 255, 0];
+    const BLUE: [u8; 3] = [255, 0, 0];
+    const WHITE: [u8; 3] = [255, 255, 255];
+
+    fn row(left: [u8; 3], right: [u8; 3]) -> [u8; 8] {
+        let mut row = [0; 8]; //6 bytes of pixels, padded to 8
+        row[..3].copy_from_slice(&left);
+        row[3..6].copy_from_slice(&right);
+        row
+    }
+
+    #[test_case]
+    fn decode_24_bit_bottom_up() {
+        let data = bmp(2, 2, 24, &[&row(RED, GREEN), &row(BLUE, WHITE)]);
+        let image = decode_bmp(&data).unwrap();
+        assert_eq!((image.width, image.height), (2, 2));
+        //The first row in the file is the bottom one
+        assert_eq!(image.pixel(0, 0), Some(Color::BLUE));
+        assert_eq!(image.pixel(1, 0), Some(Color::WHITE));
+        assert_eq!(image.pixel(0, 1), Some(Color::RED));
+        assert_eq!(image.pixel(1, 1), Some(Color::GREEN));
+
+        let top_down = decode_bmp(&bmp(2, -2, 24, &[&row(RED, GREEN), &row(BLUE, WHITE)])).unwrap();
+        assert_eq!(top_down.pixel(0, 0), Some(Color::RED));
+    }
+
+    #[test_case]
+    fn decode_32_bit() {
+        let data = bmp(1, 1, 32, &[&[0x10, 0x20, 0x30, 0xFF]]);
+        assert_eq!(
+            decode_bmp(&data).unwrap().pixels,
+            [Color::new(0x30, 0x20, 0x10)]
+        );
+    }
+
+    #[test_case]
+    fn reject_invalid() {
+        let data = bmp(2, 2, 24, &[&row(RED, GREEN), &row(BLUE, WHITE)]);
+        assert_eq!(
+            decode_bmp(&data[..data.len() - 1]),
+            Err(DecodeError::Truncated)
+        );
+        assert_eq!(decode_bmp(&data[..20]), Err(DecodeError::Truncated));
+        assert_eq!(decode_bmp(b"PNG"), Err(DecodeError::NotBmp));
+        assert_eq!(
+            decode_bmp(&bmp(2, 2, 8, &[])),
+            Err(DecodeError::UnsupportedBitDepth(8))
+        );
+        assert_eq!(
+            decode_bmp(&bmp(0, 2, 24, &[])),
+            Err(DecodeError::MalformedHeader)
+        );
+        //Far more pixels than the file holds
+        assert_eq!(
+            decode_bmp(&bmp(i32::MAX, i32::MAX, 32, &[])),
+            Err(DecodeError::Truncated)
+        );
+    }
+
+    fn test_framebuffer(width: usize, height: usize) -> (Framebuffer, Vec<u8>) {
+        let info = FramebufferInfo {
+            width,
+            height,
+            stride: width,
+            bytes_per_pixel: 4,
+            format: PixelFormat::Rgb,
+        };
+        let mut front = vec![0; width * height * 4];
+        let framebuffer = unsafe { Framebuffer::new(info, front.as_mut_ptr()) };
+        (framebuffer, front)
+    }
+
+    fn color_at(framebuffer: &Framebuffer, x: usize, y: usize) -> Color {
+        Color::from_framebuffer_bytes(framebuffer.pixel(x, y).unwrap(), PixelFormat::Rgb)
+    }
+
+    #[test_case]
+    fn blit_past_right_edge() {
+        let (mut framebuffer, _front) = test_framebuffer(4, 4);
+        let image = Image {
+            width: 3,
+            height: 2,
+            pixels: vec![
+                Color::RED,
+                Color::GREEN,
+                Color::BLUE,
+                Color::WHITE,
+                Color::YELLOW,
+                Color::CYAN,
+            ],
+            alpha: None,
+        };
+        framebuffer.blit(2, 1, &image);
+        assert_eq!(color_at(&framebuffer, 2, 1), Color::RED);
+        assert_eq!(color_at(&framebuffer, 3, 1), Color::GREEN);
+        assert_eq!(color_at(&framebuffer, 2, 2), Color::WHITE);
+        assert_eq!(color_at(&framebuffer, 3, 2), Color::YELLOW);
+        //The third column is clipped rather than wrapping onto the next row
+        assert_eq!(color_at(&framebuffer, 0, 2), Color::BLACK);
+        assert_eq!(color_at(&framebuffer, 0, 3), Color::BLACK);
+
+        //Entirely off screen in every direction, and larger than the screen
+        let before = framebuffer.back_buffer().to_vec();
+        framebuffer.blit(4, 0, &image);
+        framebuffer.blit(-3, 0, &image);
+        framebuffer.blit(0, -2, &image);
+        framebuffer.blit(0, 4, &image);
+        assert_eq!(framebuffer.back_buffer(), &before[..]);
+        let large = Image {
+            width: 6,
+            height: 6,
+            pixels: vec![Color::MAGENTA; 36],
+            alpha: None,
+        };
+        framebuffer.blit(-1, -1, &large);
+        assert!((0..4).all(|y| (0..4).all(|x| color_at(&framebuffer, x, y) == Color::MAGENTA)));
+    }
+
+    #[test_case]
+    fn blit_clips() {
+        let (mut framebuffer, _front) = test_framebuffer(4, 4);
+        let image = decode_bmp(&bmp(2, 2, 24, &[&row(RED, GREEN), &row(BLUE, WHITE)])).unwrap();
+        framebuffer.blit(-1, 3, &image);
+        //Only the top right pixel of the image lands on screen
+        assert_eq!(framebuffer.pixel(0, 3), Some(&[255, 255, 255, 0][..]));
+        let lit = framebuffer
+            .back_buffer()
+            .iter()
+            .filter(|&&byte| byte != 0)
+            .count();
+        assert_eq!(lit, 3);
+    }
+
+    #[test_case]
+    fn blend_red_over_blue() {
+        let (mut framebuffer, _front) = test_framebuffer(2, 1);
+        framebuffer.fill_rect(0, 0, 2, 1, Color::BLUE);
+        let red = Image {
+            width: 1,
+            height: 1,
+            pixels: vec![Color::RED],
+            alpha: None,
+        };
+        framebuffer.blit_alpha(0, 0, &red, 128);
+        assert_eq!(color_at(&framebuffer, 0, 0), Color::new(128, 0, 127));
+        framebuffer.blit_alpha(1, 0, &red, 0);
+        assert_eq!(color_at(&framebuffer, 1, 0), Color::BLUE);
+        framebuffer.blit_alpha(1, 0, &red, 255);
+        assert_eq!(color_at(&framebuffer, 1, 0), Color::RED);
+    }
+
+    #[test_case]
+    fn blend_per_pixel_alpha() {
+        let (mut framebuffer, _front) = test_framebuffer(3, 1);
+        framebuffer.fill_rect(0, 0, 3, 1, Color::BLUE);
+        let red = Image {
+            width: 3,
+            height: 1,
+            pixels: vec![Color::RED; 3],
+            alpha: Some(vec![0, 128, 255]),
+        };
+        framebuffer.blit_alpha(0, 0, &red, 255);
+        assert_eq!(color_at(&framebuffer, 0, 0), Color::BLUE);
+        assert_eq!(color_at(&framebuffer, 1, 0), Color::new(128, 0, 127));
+        assert_eq!(color_at(&framebuffer, 2, 0), Color::RED);
+    }
+}
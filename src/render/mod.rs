@@ -1,5 +1,27 @@
+mod color;
+mod console;
+mod cursor;
+mod draw;
+mod framebuffer;
 mod geometry;
+mod image;
 mod objects;
+mod panic;
+mod rect;
 mod renderer;
+mod screenshot;
+mod surface;
 
-pub use renderer::render;
+pub use color::Color;
+pub use console::{Console, CONSOLE};
+pub use cursor::{move_cursor, set_cursor_position, show_cursor, Cursor, CURSOR};
+pub use draw::{
+    clear, draw_circle, draw_line, draw_rect, draw_text, draw_text_scaled, fill_circle, fill_rect,
+};
+pub use framebuffer::{framebuffer_info, Framebuffer, FramebufferInfo, PixelFormat, FRAMEBUFFER};
+pub use image::{blit, blit_alpha, decode_bmp, DecodeError, Image};
+pub use panic::{show_panic, PanicConsole};
+pub use rect::Rect;
+pub use renderer::{render, set_target_fps};
+pub use screenshot::dump_ppm;
+pub use surface::Surface;
@@ -0,0 +1,144 @@
+use super::draw::{glyph, GLYPH_SIZE};
+use super::framebuffer::{VGA_FRONT, VGA_INFO};
+use super::{Color, FramebufferInfo};
+use core::fmt::{self, Write};
+use core::panic::PanicInfo;
+
+const MARGIN: usize = 4;
+
+/// Writes text straight to a framebuffer's front buffer, without the back buffer, any lock or any
+/// allocation - whatever panicked may hold the screen's lock or be the allocator itself.
+pub struct PanicConsole {
+    info: FramebufferInfo,
+    front: *mut u8,
+    x: usize,
+    y: usize,
+    foreground: [u8; 4],
+}
+
+impl PanicConsole {
+    /// Clears the screen to `background`.
+    ///
+    /// # Safety
+    /// `front` must point to `info.stride * info.height` bytes of writable memory.
+    pub unsafe fn new(
+        info: FramebufferInfo,
+        front: *mut u8,
+        foreground: Color,
+        background: Color,
+    ) -> Self {
+        let background = background.to_framebuffer_bytes(info.format);
+        for y in 0..info.height {
+            for x in 0..info.width {
+                for (i, &byte) in background[..info.bytes_per_pixel].iter().enumerate() {
+                    //Volatile, since the writes are all there is to see of them
+                    front
+                        .add(y * info.stride + x * info.bytes_per_pixel + i)
+                        .write_volatile(byte);
+                }
+            }
+        }
+        Self {
+            info,
+            front,
+            x: MARGIN,
+            y: MARGIN,
+            foreground: foreground.to_framebuffer_bytes(info.format),
+        }
+    }
+
+    fn new_line(&mut self) {
+        self.x = MARGIN;
+        self.y += GLYPH_SIZE + 2;
+    }
+
+    fn put_char(&mut self, character: char) {
+        if character == '\n' {
+            return self.new_line();
+        }
+        if self.x + GLYPH_SIZE > self.info.width - MARGIN {
+            self.new_line();
+        }
+        //Anything past the bottom is lost, it's on serial as well
+        if self.y + GLYPH_SIZE > self.info.height {
+            return;
+        }
+        let bytes_per_pixel = self.info.bytes_per_pixel;
+        for (row, bits) in glyph(character).iter().enumerate() {
+            for column in 0..GLYPH_SIZE {
+                if bits & 1 << column == 0 {
+                    continue;
+                }
+                let offset =
+                    (self.y + row) * self.info.stride + (self.x + column) * bytes_per_pixel;
+                for (i, &byte) in self.foreground[..bytes_per_pixel].iter().enumerate() {
+                    unsafe { self.front.add(offset + i).write_volatile(byte) };
+                }
+            }
+        }
+        self.x += GLYPH_SIZE;
+    }
+}
+
+impl Write for PanicConsole {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        s.chars().for_each(|character| self.put_char(character));
+        Ok(())
+    }
+}
+
+/// Shows the panic red on black on the screen. Returns false without drawing anything if the
+/// screen isn't in graphics mode yet, leaving serial as the only place the panic shows up.
+pub fn show_panic(info: &PanicInfo) -> bool {
+    if !crate::graphics::is_graphics_mode() {
+        return false;
+    }
+    let mut console = unsafe { PanicConsole::new(VGA_INFO, VGA_FRONT, Color::RED, Color::BLACK) };
+    let _ = writeln!(console, "KERNEL PANIC\n\n{}", info.message());
+    if let Some(location) = info.location() {
+        let _ = write!(
+            console,
+            "\nat {}:{}:{}",
+            location.file(),
+            location.line(),
+            location.column()
+        );
+    }
+    true
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::render::PixelFormat;
+    use alloc::vec;
+
+    #[test_case]
+    fn panic_text_is_drawn() {
+        let info = FramebufferInfo {
+            width: 24,
+            height: 16,
+            stride: 24,
+            bytes_per_pixel: 1,
+            format: PixelFormat::Indexed,
+        };
+        let mut front = vec![0xAA; info.stride * info.height];
+        let mut console =
+            unsafe { PanicConsole::new(info, front.as_mut_ptr(), Color::RED, Color::BLACK) };
+        //The X doesn't fit next to the bar, and the line it wraps to is past the bottom
+        write!(console, "|X").unwrap();
+
+        let red = Color::RED.to_framebuffer_bytes(PixelFormat::Indexed)[0];
+        let black = Color::BLACK.to_framebuffer_bytes(PixelFormat::Indexed)[0];
+        let lit = |x: usize, y: usize| front[y * info.stride + x] == red;
+        for (row, bits) in glyph('|').iter().enumerate() {
+            for column in 0..GLYPH_SIZE {
+                assert_eq!(lit(MARGIN + column, MARGIN + row), bits & 1 << column != 0);
+            }
+        }
+        let drawn = front.iter().filter(|&&pixel| pixel == red).count();
+        let bar: u32 = glyph('|').iter().map(|bits| bits.count_ones()).sum();
+        assert_eq!(drawn, bar as usize);
+        assert!(front.iter().all(|&pixel| pixel == red || pixel == black));
+    }
+}
@@ -1,22 +1,47 @@
 use super::geometry::*;
 use super::objects::SHIP;
+use super::FRAMEBUFFER;
 use crate::graphics::VGA;
 use crate::io::{get_key_ev, KeyCode, KeyEvent, KeyState, MOUSE, SCANCODE_QUEUE};
-use crate::timer::sleep;
+use crate::time::{ms_to_ticks, tsc, Delay};
 use alloc::vec::Vec;
 use core::f32::consts::PI;
+use core::sync::atomic::{AtomicU64, Ordering};
 use libm::tanf;
 
-const WIDTH: f32 = 320.0;
-const HEIGHT: f32 = 200.0;
+static TARGET_FPS: AtomicU64 = AtomicU64::new(60);
+
+/// Caps how many frames per second `render` draws.
+/// Frames are paced in whole timer ticks, so rates above ~18 FPS run at one frame per tick.
+pub fn set_target_fps(fps: u64) {
+    assert!(fps > 0, "target FPS must be non-zero");
+    TARGET_FPS.store(fps, Ordering::Relaxed);
+}
+
+//How long to sleep after a frame that took `frame_ms` to draw. A frame that ran over gets no sleep,
+//but the time it lost isn't taken from the frames after it either
+fn frame_sleep_ms(fps: u64, frame_ms: u64) -> u64 {
+    (1000 / fps).saturating_sub(frame_ms)
+}
+
+//Waits out the rest of a frame that started at `frame_start_ns`. Timers only have tick resolution
+//(~55ms), so it's always at least a tick - rounding down to no wait at all would busy-spin the
+//render task and keep the executor from ever halting
+async fn pace_frame(fps: u64, frame_start_ns: u64) {
+    let frame_ms = tsc::uptime_ns().saturating_sub(frame_start_ns) / 1_000_000;
+    let ticks = ms_to_ticks(frame_sleep_ms(fps, frame_ms)).max(1);
+    Delay::new(ticks).await;
+}
 
 pub async fn render() {
+    let screen = super::framebuffer_info();
+    let (width, height) = (screen.width as f32, screen.height as f32);
     let mesh = Mesh::from_obj_file(SHIP);
     let proj_matrix = {
         let near: f32 = 0.5;
         let far: f32 = 1000.0;
         let fov: f32 = 90.0;
-        let aspect_ratio: f32 = HEIGHT / WIDTH;
+        let aspect_ratio: f32 = height / width;
         let fov_rad = 1.0 / tanf(fov * 0.5 / 180.0 * PI);
         Matrix4x4 {
             m: [
@@ -49,6 +74,7 @@ pub async fn render() {
 
     let mut iterations: f32 = 0.0;
     loop {
+        let frame_start = tsc::uptime_ns();
         // Get user input
         while let Ok(code) = scancode_queue.pop() {
             if let Ok(key_event) = get_key_ev(code) {
@@ -245,12 +271,12 @@ pub async fn render() {
                         clipped_tri.p[2].y *= -1.0;
 
                         // Scale into view
-                        clipped_tri.p[0].x += (clipped_tri.p[0].x + 1.0) * 0.5 * WIDTH;
-                        clipped_tri.p[0].y += (clipped_tri.p[0].y + 1.0) * 0.5 * HEIGHT;
-                        clipped_tri.p[1].x += (clipped_tri.p[1].x + 1.0) * 0.5 * WIDTH;
-                        clipped_tri.p[1].y += (clipped_tri.p[1].y + 1.0) * 0.5 * HEIGHT;
-                        clipped_tri.p[2].x += (clipped_tri.p[2].x + 1.0) * 0.5 * WIDTH;
-                        clipped_tri.p[2].y += (clipped_tri.p[2].y + 1.0) * 0.5 * HEIGHT;
+                        clipped_tri.p[0].x += (clipped_tri.p[0].x + 1.0) * 0.5 * width;
+                        clipped_tri.p[0].y += (clipped_tri.p[0].y + 1.0) * 0.5 * height;
+                        clipped_tri.p[1].x += (clipped_tri.p[1].x + 1.0) * 0.5 * width;
+                        clipped_tri.p[1].y += (clipped_tri.p[1].y + 1.0) * 0.5 * height;
+                        clipped_tri.p[2].x += (clipped_tri.p[2].x + 1.0) * 0.5 * width;
+                        clipped_tri.p[2].y += (clipped_tri.p[2].y + 1.0) * 0.5 * height;
 
                         triangles_to_raster.push(clipped_tri);
                     }
@@ -297,7 +323,7 @@ pub async fn render() {
                         1 => Vector::clip_plane(
                             Vector {
                                 x: 0.0,
-                                y: HEIGHT - 1.0,
+                                y: height - 1.0,
                                 z: 0.0,
                                 w: 1.0,
                             },
@@ -326,7 +352,7 @@ pub async fn render() {
                         ),
                         3 => Vector::clip_plane(
                             Vector {
-                                x: WIDTH - 1.0,
+                                x: width - 1.0,
                                 y: 0.0,
                                 z: 0.0,
                                 w: 1.0,
@@ -390,16 +416,47 @@ pub async fn render() {
 
         //Draw crosshair
         VGA.lock().draw_bitmap(
-            (WIDTH as usize / 2) - 6,
-            HEIGHT as usize / 2,
+            (width as usize / 2) - 6,
+            height as usize / 2,
             &crosshair,
             0xF,
         );
 
-        // Swap buffers
-        VGA.lock().swap_buffers();
+        // Present through FRAMEBUFFER, so it stays the only thing writing to the screen
+        {
+            let mut framebuffer = FRAMEBUFFER.lock();
+            framebuffer
+                .back_buffer_mut()
+                .copy_from_slice(VGA.lock().back_buffer());
+            framebuffer.present();
+        }
 
-        sleep(1).await;
+        pace_frame(TARGET_FPS.load(Ordering::Relaxed), frame_start).await;
         iterations += 0.05;
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{frame_sleep_ms, pace_frame};
+    use crate::executor::block_on;
+    use crate::time::{ticks, tsc};
+
+    #[test_case]
+    fn frame_sleep() {
+        assert_eq!(frame_sleep_ms(60, 0), 16);
+        assert_eq!(frame_sleep_ms(60, 10), 6);
+        assert_eq!(frame_sleep_ms(10, 30), 70);
+        //Overran the budget, so the next frame starts straight away
+        assert_eq!(frame_sleep_ms(60, 16), 0);
+        assert_eq!(frame_sleep_ms(60, 200), 0);
+    }
+
+    #[test_case]
+    fn pace_frame_sleeps() {
+        //A 60 FPS frame is far shorter than a tick, but still waits on a Delay rather than yielding
+        let start = ticks();
+        block_on(pace_frame(60, tsc::uptime_ns()));
+        assert!(ticks() > start);
+    }
+}
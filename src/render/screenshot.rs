@@ -0,0 +1,76 @@
+use super::{Color, Framebuffer, FRAMEBUFFER};
+use crate::io::COM1;
+use alloc::{format, vec::Vec};
+use x86_64::instructions::interrupts;
+
+impl Framebuffer {
+    /// Encodes what's currently on screen as a binary PPM (P6) image, keeping every `scale`th pixel in
+    /// both directions. The header and then each image row are passed to `out` as they're produced.
+    pub fn write_ppm(&self, scale: usize, mut out: impl FnMut(&[u8])) {
+        assert!(scale > 0, "PPM scale must be non-zero");
+        let info = self.info();
+        let width = info.width.div_ceil(scale);
+        let height = info.height.div_ceil(scale);
+        out(format!("P6\n{} {}\n255\n", width, height).as_bytes());
+
+        let mut row_out = Vec::with_capacity(width * 3);
+        for y in (0..info.height).step_by(scale) {
+            let row = self.front_row(y);
+            row_out.clear();
+            for pixel in row.chunks_exact(info.bytes_per_pixel).step_by(scale) {
+                let color = Color::from_framebuffer_bytes(pixel, info.format);
+                row_out.extend_from_slice(&[color.r, color.g, color.b]);
+            }
+            out(&row_out);
+        }
+    }
+}
+
+/// Streams a PPM screenshot of the screen over the serial port, see `Framebuffer::write_ppm`.
+pub fn dump_ppm(scale: usize) {
+    FRAMEBUFFER.lock().write_ppm(scale, |bytes| {
+        //Serial is slow, so only hold the lock with interrupts disabled for one row at a time
+        interrupts::without_interrupts(|| {
+            let mut serial = COM1.lock();
+            for &byte in bytes {
+                serial.send_raw(byte);
+            }
+        });
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use crate::render::{Color, Framebuffer, FramebufferInfo, PixelFormat};
+    use alloc::{vec, vec::Vec};
+
+    #[test_case]
+    fn ppm_header_and_pixels() {
+        let info = FramebufferInfo {
+            width: 4,
+            height: 2,
+            stride: 16,
+            bytes_per_pixel: 3,
+            format: PixelFormat::Bgr,
+        };
+        let mut front: Vec<u8> = vec![0; info.stride * info.height];
+        let mut framebuffer = unsafe { Framebuffer::new(info, front.as_mut_ptr()) };
+        framebuffer.clear(Color::RED);
+        framebuffer.present();
+
+        let mut ppm = Vec::new();
+        framebuffer.write_ppm(1, |bytes| ppm.extend_from_slice(bytes));
+        let header = b"P6\n4 2\n255\n";
+        assert_eq!(&ppm[..header.len()], header);
+        //Converted from BGR back to RGB
+        assert_eq!(ppm.len(), header.len() + 4 * 2 * 3);
+        assert!(ppm[header.len()..]
+            .chunks(3)
+            .all(|pixel| pixel == [255, 0, 0]));
+
+        let mut ppm = Vec::new();
+        framebuffer.write_ppm(3, |bytes| ppm.extend_from_slice(bytes));
+        assert_eq!(&ppm[..11], b"P6\n2 1\n255\n");
+        assert_eq!(ppm.len(), 11 + 2 * 3);
+    }
+}
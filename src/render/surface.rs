@@ -0,0 +1,135 @@
+use super::{framebuffer_info, Color, Framebuffer, FramebufferInfo, PixelFormat};
+use core::ops::{Deref, DerefMut};
+
+/// An off-screen image of any size to compose widgets on before copying them to the screen.
+///
+/// It dereferences to a `Framebuffer` without any hardware behind it, so every drawing primitive
+/// works on it the same way - presenting it just does nothing.
+pub struct Surface {
+    canvas: Framebuffer,
+}
+
+impl Surface {
+    /// A surface in the screen's pixel format, so copying it to the screen needs no conversion.
+    pub fn new(width: usize, height: usize) -> Self {
+        let screen = framebuffer_info();
+        Self::with_format(width, height, screen.format, screen.bytes_per_pixel)
+    }
+
+    pub fn with_format(
+        width: usize,
+        height: usize,
+        format: PixelFormat,
+        bytes_per_pixel: usize,
+    ) -> Self {
+        let info = FramebufferInfo {
+            width,
+            height,
+            stride: width * bytes_per_pixel,
+            bytes_per_pixel,
+            format,
+        };
+        Self {
+            canvas: Framebuffer::offscreen(info),
+        }
+    }
+
+    /// Reads back the pixel at (x, y), None if it's outside the surface.
+    pub fn color_at(&self, x: usize, y: usize) -> Option<Color> {
+        let format = self.info().format;
+        self.pixel(x, y)
+            .map(|pixel| Color::from_framebuffer_bytes(pixel, format))
+    }
+
+    /// Copies the surface onto a framebuffer - the screen's or another surface's - with its top
+    /// left corner at (x, y). The parts that don't fit are clipped.
+    pub fn blit_to(&self, target: &mut Framebuffer, x: isize, y: isize) {
+        let rect = match target.clip_rect(x, y, self.info().width, self.info().height) {
+            Some(rect) => rect,
+            None => return,
+        };
+        target.mark_dirty(rect);
+        let (source, destination) = (self.info(), target.info());
+        let same_format = source.format == destination.format
+            && source.bytes_per_pixel == destination.bytes_per_pixel;
+        let left = (rect.x as isize - x) as usize;
+        let top = (rect.y as isize - y) as usize;
+        let back = target.back_mut();
+        for row in 0..rect.height {
+            let from = &self.back_buffer()[(top + row) * source.width * source.bytes_per_pixel..]
+                [left * source.bytes_per_pixel..][..rect.width * source.bytes_per_pixel];
+            let start = (rect.y + row) * destination.width * destination.bytes_per_pixel
+                + rect.x * destination.bytes_per_pixel;
+            let to = &mut back[start..start + rect.width * destination.bytes_per_pixel];
+            if same_format {
+                to.copy_from_slice(from);
+                continue;
+            }
+            let pixels = from.chunks_exact(source.bytes_per_pixel);
+            for (to, from) in to.chunks_exact_mut(destination.bytes_per_pixel).zip(pixels) {
+                let color = Color::from_framebuffer_bytes(from, source.format);
+                to.copy_from_slice(
+                    &color.to_framebuffer_bytes(destination.format)[..destination.bytes_per_pixel],
+                );
+            }
+        }
+    }
+}
+
+impl Deref for Surface {
+    type Target = Framebuffer;
+
+    fn deref(&self) -> &Framebuffer {
+        &self.canvas
+    }
+}
+
+impl DerefMut for Surface {
+    fn deref_mut(&mut self) -> &mut Framebuffer {
+        &mut self.canvas
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::render::Image;
+    use alloc::vec;
+
+    fn rgb_surface(width: usize, height: usize) -> Surface {
+        Surface::with_format(width, height, PixelFormat::Rgb, 4)
+    }
+
+    #[test_case]
+    fn compose_surfaces() {
+        let mut widget = rgb_surface(16, 16);
+        widget.clear(Color::BLUE);
+        widget.fill_rect(0, 0, 4, 4, Color::RED);
+        widget.draw_text(8, 8, "|", Color::WHITE);
+        let dot = Image {
+            width: 1,
+            height: 1,
+            pixels: vec![Color::GREEN],
+            alpha: None,
+        };
+        widget.blit(15, 15, &dot);
+        assert!(widget.dirty_rects().is_empty());
+
+        let mut window = rgb_surface(32, 32);
+        window.clear(Color::BLACK);
+        widget.blit_to(&mut window, 10, 20);
+        assert_eq!(window.color_at(9, 20), Some(Color::BLACK));
+        assert_eq!(window.color_at(10, 20), Some(Color::RED));
+        assert_eq!(window.color_at(14, 24), Some(Color::BLUE));
+        //The bottom four rows are clipped by the window
+        assert_eq!(window.color_at(21, 31), widget.color_at(11, 11));
+        //The bar of the '|' glyph is in its fourth and fifth columns
+        assert_eq!(window.color_at(21, 28), Some(Color::WHITE));
+
+        //Converted when the formats differ
+        let mut bgr = Surface::with_format(4, 4, PixelFormat::Bgr, 3);
+        widget.blit_to(&mut bgr, -12, -12);
+        assert_eq!(bgr.color_at(3, 3), Some(Color::GREEN));
+        assert_eq!(bgr.color_at(0, 0), Some(Color::BLUE));
+    }
+}
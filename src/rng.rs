@@ -0,0 +1,136 @@
+use crate::cpu;
+use conquer_once::spin::Lazy;
+use core::arch::{asm, x86_64::_rdtsc};
+use spin::Mutex;
+
+//Intel recommends 10 retries - RDRAND only fails transiently when the DRNG is drained
+const RDRAND_RETRIES: usize = 10;
+
+static HAS_RDRAND: Lazy<bool> = Lazy::new(|| cpu::features().rdrand);
+
+fn rdrand() -> Option<u64> {
+    let value: u64;
+    let ok: u8;
+    //The carry flag is clear when no random value was ready
+    unsafe {
+        asm!("rdrand {}", "setc {}", out(reg) value, out(reg_byte) ok, options(nomem, nostack));
+    }
+    (ok != 0).then_some(value)
+}
+
+/// A random value from the CPU's hardware generator,
+/// or None if it doesn't have one or it keeps failing.
+pub fn hardware_u64() -> Option<u64> {
+    if !*HAS_RDRAND {
+        return None;
+    }
+    (0..RDRAND_RETRIES).find_map(|_| rdrand())
+}
+
+/// A fast deterministic generator (xorshift128+). Not suitable for cryptography.
+#[derive(Debug, Clone)]
+pub struct Prng {
+    state: [u64; 2],
+}
+
+//Spreads a seed over the state so similar seeds still give unrelated sequences
+fn splitmix64(x: &mut u64) -> u64 {
+    *x = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = *x;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+impl Prng {
+    pub fn new(seed: u64) -> Self {
+        let mut x = seed;
+        let mut state = [splitmix64(&mut x), splitmix64(&mut x)];
+        //An all zero state would only ever produce zeroes
+        if state == [0, 0] {
+            state[0] = 1;
+        }
+        Prng { state }
+    }
+
+    /// Seeded from RDRAND if available, the TSC otherwise.
+    pub fn from_entropy() -> Self {
+        Prng::new(hardware_u64().unwrap_or_else(|| unsafe { _rdtsc() }))
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        let [mut s1, s0] = self.state;
+        let result = s0.wrapping_add(s1);
+        s1 ^= s1 << 23;
+        self.state = [s0, s1 ^ s0 ^ (s1 >> 17) ^ (s0 >> 26)];
+        result
+    }
+
+    /// A uniformly distributed value in 0..n.
+    pub fn next_range(&mut self, n: u64) -> u64 {
+        assert!(n > 0, "empty range");
+        //Values in the partial last copy of 0..n would bias the result, so they are rejected
+        let zone = u64::MAX - u64::MAX % n;
+        loop {
+            let value = self.next_u64();
+            if value < zone {
+                return value % n;
+            }
+        }
+    }
+
+    pub fn fill_bytes(&mut self, buf: &mut [u8]) {
+        for chunk in buf.chunks_mut(8) {
+            let bytes = self.next_u64().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+}
+
+static PRNG: Lazy<Mutex<Prng>> = Lazy::new(|| Mutex::new(Prng::from_entropy()));
+
+/// A value from the shared kernel PRNG.
+pub fn random_u64() -> u64 {
+    PRNG.lock().next_u64()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test_case]
+    fn hardware_random() {
+        if !cpu::features().rdrand {
+            assert_eq!(hardware_u64(), None);
+            return;
+        }
+        let first = hardware_u64().expect("rdrand failed");
+        //A repeat is possible but a run of them isn't
+        assert!((0..4).any(|_| hardware_u64() != Some(first)));
+    }
+
+    #[test_case]
+    fn prng_reproducible() {
+        let mut a = Prng::new(42);
+        let mut b = Prng::new(42);
+        for _ in 0..100 {
+            assert_eq!(a.next_u64(), b.next_u64());
+        }
+        assert_ne!(Prng::new(1).next_u64(), Prng::new(2).next_u64());
+
+        let mut bytes = [0; 13];
+        Prng::new(42).fill_bytes(&mut bytes);
+        assert_eq!(bytes[..8], Prng::new(42).next_u64().to_le_bytes());
+    }
+
+    #[test_case]
+    fn prng_unbiased() {
+        let mut prng = Prng::new(7);
+        let mut counts = [0u32; 10];
+        for _ in 0..10_000 {
+            counts[prng.next_range(10) as usize] += 1;
+        }
+        //Each bucket expects 1000 - this is many standard deviations of slack
+        assert!(counts.iter().all(|&count| (850..1150).contains(&count)));
+    }
+}
@@ -0,0 +1,202 @@
+use crate::io::{History, KeyStream, LineReader};
+use crate::render::{Console, CONSOLE, FRAMEBUFFER};
+use alloc::{collections::BTreeMap, sync::Arc};
+use conquer_once::spin::Lazy;
+use core::fmt::{self, Write};
+use spin::Mutex;
+
+const PROMPT: &str = "> ";
+const MAX_LINE: usize = 78; //what fits on one row next to the prompt
+const HISTORY_LEN: usize = 32;
+
+/// A command handler, given everything after the command name and the console to print to.
+pub type Handler = Arc<dyn Fn(&str, &mut Console) + Send + Sync>;
+
+struct Command {
+    help: &'static str,
+    handler: Handler,
+}
+
+/// Returned by `register` when a command of that name already exists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AlreadyRegistered(pub &'static str);
+
+impl fmt::Display for AlreadyRegistered {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "shell command '{}' is already registered", self.0)
+    }
+}
+
+type Registry = BTreeMap<&'static str, Command>;
+type BuiltinHandler = fn(&str, &mut Console);
+
+static COMMANDS: Lazy<Mutex<Registry>> = Lazy::new(|| {
+    let builtins: [(&str, &str, BuiltinHandler); 5] = [
+        ("help", "list the available commands", help),
+        ("echo", "print the rest of the line", echo),
+        ("clear", "clear the screen", clear),
+        ("uptime", "time since boot", uptime),
+        ("irqs", "interrupt counts by vector", irqs),
+    ];
+    let mut commands = BTreeMap::new();
+    for (name, help, handler) in builtins {
+        let handler: Handler = Arc::new(handler);
+        commands.insert(name, Command { help, handler });
+    }
+    Mutex::new(commands)
+});
+
+/// Adds a command to the shell, so subsystems can provide their own without touching this module.
+pub fn register(
+    name: &'static str,
+    handler: impl Fn(&str, &mut Console) + Send + Sync + 'static,
+) -> Result<(), AlreadyRegistered> {
+    let mut commands = COMMANDS.lock();
+    if commands.contains_key(name) {
+        return Err(AlreadyRegistered(name));
+    }
+    commands.insert(
+        name,
+        Command {
+            help: "",
+            handler: Arc::new(handler),
+        },
+    );
+    Ok(())
+}
+
+fn help(_args: &str, console: &mut Console) {
+    for (name, command) in COMMANDS.lock().iter() {
+        let _ = writeln!(console, "{:<8}{}", name, command.help);
+    }
+}
+
+fn echo(args: &str, console: &mut Console) {
+    let _ = writeln!(console, "{}", args);
+}
+
+fn clear(_args: &str, console: &mut Console) {
+    console.clear_screen(&mut FRAMEBUFFER.lock());
+}
+
+fn uptime(_args: &str, console: &mut Console) {
+    let ms = crate::time::uptime_ms();
+    let _ = writeln!(console, "up {}.{:03}s", ms / 1000, ms % 1000);
+}
+
+fn irqs(_args: &str, console: &mut Console) {
+    for (vector, count) in crate::interrupts::stats().iter().enumerate() {
+        if *count > 0 {
+            let _ = writeln!(console, "{:>3} {}", vector, count);
+        }
+    }
+}
+
+/// Splits a line into the command name and its arguments, `None` for a blank line.
+pub fn parse(line: &str) -> Option<(&str, &str)> {
+    let line = line.trim();
+    if line.is_empty() {
+        return None;
+    }
+    match line.split_once(char::is_whitespace) {
+        Some((name, args)) => Some((name, args.trim_start())),
+        None => Some((line, "")),
+    }
+}
+
+/// Runs a single line of input, printing an error for commands that don't exist.
+pub fn execute(line: &str, console: &mut Console) {
+    let (name, args) = match parse(line) {
+        Some(parsed) => parsed,
+        None => return,
+    };
+    //Cloned out so the handler runs without the registry locked - `help` needs it too
+    let handler = COMMANDS
+        .lock()
+        .get(name)
+        .map(|command| command.handler.clone());
+    match handler {
+        Some(handler) => handler(args, console),
+        None => {
+            let _ = writeln!(
+                console,
+                "unknown command '{}' - try 'help' for a list",
+                name
+            );
+        }
+    }
+}
+
+fn prompt() {
+    let _ = CONSOLE.lock().write_str(PROMPT);
+    FRAMEBUFFER.lock().present_dirty();
+}
+
+/// Reads commands from the keyboard and runs them, forever.
+pub async fn run() {
+    let mut keys = KeyStream::new();
+    let reader = LineReader::new(MAX_LINE);
+    let mut history = History::new(HISTORY_LEN);
+    loop {
+        prompt();
+        let line = reader.read_line_with_history(&mut keys, &mut history).await;
+        execute(&line, &mut CONSOLE.lock());
+        FRAMEBUFFER.lock().present_dirty();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::render::{Color, Framebuffer};
+    use alloc::vec;
+
+    #[test_case]
+    fn parse_whitespace() {
+        assert_eq!(parse("echo hello"), Some(("echo", "hello")));
+        assert_eq!(
+            parse("   echo    hello  world  "),
+            Some(("echo", "hello  world"))
+        );
+        assert_eq!(parse("\thelp\t"), Some(("help", "")));
+    }
+
+    #[test_case]
+    fn parse_empty_lines() {
+        assert_eq!(parse(""), None);
+        assert_eq!(parse("    "), None);
+    }
+
+    #[test_case]
+    fn registered_command_is_dispatched() {
+        register("greet", |args: &str, console: &mut Console| {
+            let _ = writeln!(console, "hello {}", args);
+        })
+        .unwrap();
+        assert_eq!(
+            register("greet", |_: &str, _: &mut Console| {}),
+            Err(AlreadyRegistered("greet"))
+        );
+        assert_eq!(
+            register("help", |_: &str, _: &mut Console| {}),
+            Err(AlreadyRegistered("help"))
+        );
+
+        //The console draws to the screen, so what the handler wrote is captured by drawing the
+        //expected line into a copy of the screen and comparing the two
+        let (info, mut expected) = {
+            let framebuffer = FRAMEBUFFER.lock();
+            (framebuffer.info(), framebuffer.back_buffer().to_vec())
+        };
+        let mut front = vec![0u8; info.stride * info.height];
+        let mut reference = unsafe { Framebuffer::new(info, front.as_mut_ptr()) };
+        reference.back_buffer_mut().copy_from_slice(&expected);
+        Console::new(Color::WHITE, Color::BLACK).write_to(&mut reference, "hello world\n");
+        expected.copy_from_slice(reference.back_buffer());
+
+        let mut console = Console::new(Color::WHITE, Color::BLACK);
+        execute("  greet   world ", &mut console);
+        assert!(FRAMEBUFFER.lock().back_buffer() == expected.as_slice());
+        assert_eq!(console.cursor(), (0, 1));
+    }
+}
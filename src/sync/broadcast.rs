@@ -0,0 +1,262 @@
+use alloc::{collections::VecDeque, sync::Arc, vec::Vec};
+use core::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll, Waker},
+};
+
+struct Inner<T> {
+    //the last `capacity` values sent, the oldest first
+    values: VecDeque<T>,
+    capacity: usize,
+    head: u64, //sequence number of values[0]
+    senders: usize,
+    receivers: usize,
+    waiting: Vec<(u64, Waker)>, //by receiver id
+    next_receiver: u64,
+}
+
+impl<T> Inner<T> {
+    //sequence number the next value sent will get
+    fn tail(&self) -> u64 {
+        self.head + self.values.len() as u64
+    }
+
+    fn wake_all(&mut self) {
+        for (_, waker) in self.waiting.drain(..) {
+            waker.wake();
+        }
+    }
+}
+
+/// Creates a channel where every `Receiver` gets its own copy of every value sent after it
+/// subscribed. Only the last `capacity` values are kept, a receiver that falls further behind
+/// skips ahead and is told how many it missed.
+pub fn broadcast<T: Clone>(capacity: usize) -> Sender<T> {
+    assert!(capacity > 0, "broadcast capacity must be non-zero");
+    Sender {
+        inner: Arc::new(spin::Mutex::new(Inner {
+            values: VecDeque::with_capacity(capacity),
+            capacity,
+            head: 0,
+            senders: 1,
+            receivers: 0,
+            waiting: Vec::new(),
+            next_receiver: 0,
+        })),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecvError {
+    /// The receiver fell this many values behind, which were dropped. The next receive continues
+    /// with the oldest value still kept.
+    Lagged(u64),
+    /// Every sender was dropped and all values sent have been received.
+    Closed,
+}
+
+impl fmt::Display for RecvError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RecvError::Lagged(missed) => write!(f, "broadcast receiver missed {} values", missed),
+            RecvError::Closed => write!(f, "broadcast channel closed"),
+        }
+    }
+}
+
+pub struct Sender<T> {
+    inner: Arc<spin::Mutex<Inner<T>>>,
+}
+
+impl<T: Clone> Sender<T> {
+    /// Sends the value to every current receiver, returning how many there are, or hands it back
+    /// if there are none.
+    pub fn send(&self, value: T) -> Result<usize, T> {
+        let mut inner = self.inner.lock();
+        if inner.receivers == 0 {
+            return Err(value);
+        }
+        if inner.values.len() == inner.capacity {
+            inner.values.pop_front();
+            inner.head += 1;
+        }
+        inner.values.push_back(value);
+        inner.wake_all();
+        Ok(inner.receivers)
+    }
+
+    /// A new receiver, which gets the values sent from now on.
+    pub fn subscribe(&self) -> Receiver<T> {
+        let mut inner = self.inner.lock();
+        let id = inner.next_receiver;
+        inner.next_receiver += 1;
+        inner.receivers += 1;
+        Receiver {
+            inner: self.inner.clone(),
+            id,
+            next: inner.tail(),
+        }
+    }
+
+    pub fn receiver_count(&self) -> usize {
+        self.inner.lock().receivers
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.inner.lock().senders += 1;
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut inner = self.inner.lock();
+        inner.senders -= 1;
+        //waiting receivers have to find out the channel is closed
+        if inner.senders == 0 {
+            inner.wake_all();
+        }
+    }
+}
+
+pub struct Receiver<T> {
+    inner: Arc<spin::Mutex<Inner<T>>>,
+    id: u64,
+    next: u64, //sequence number of the next value to receive
+}
+
+impl<T: Clone> Receiver<T> {
+    /// Resolves to the next value, waiting for one to be sent if this receiver has seen them all.
+    pub fn recv(&mut self) -> Recv<'_, T> {
+        Recv { receiver: self }
+    }
+
+    /// Receives the next value if one was already sent, `Ok(None)` if there's none yet.
+    pub fn try_recv(&mut self) -> Result<Option<T>, RecvError> {
+        let inner = self.inner.lock();
+        if self.next < inner.head {
+            let missed = inner.head - self.next;
+            self.next = inner.head;
+            return Err(RecvError::Lagged(missed));
+        }
+        if self.next < inner.tail() {
+            let value = inner.values[(self.next - inner.head) as usize].clone();
+            self.next += 1;
+            return Ok(Some(value));
+        }
+        if inner.senders == 0 {
+            return Err(RecvError::Closed);
+        }
+        Ok(None)
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        let mut inner = self.inner.lock();
+        inner.receivers -= 1;
+        let id = self.id;
+        inner.waiting.retain(|(waiting, _)| *waiting != id);
+    }
+}
+
+/// Future returned by `Receiver::recv`.
+pub struct Recv<'a, T> {
+    receiver: &'a mut Receiver<T>,
+}
+
+impl<T: Clone> Future for Recv<'_, T> {
+    type Output = Result<T, RecvError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<T, RecvError>> {
+        let receiver = &mut *self.receiver;
+        match receiver.try_recv() {
+            Ok(Some(value)) => Poll::Ready(Ok(value)),
+            Err(error) => Poll::Ready(Err(error)),
+            Ok(None) => {
+                //Checked again under the same lock the waker is stored under, so a send in
+                //between can't be missed
+                let mut inner = receiver.inner.lock();
+                if receiver.next < inner.tail() || inner.senders == 0 {
+                    cx.waker().wake_by_ref();
+                    return Poll::Pending;
+                }
+                let id = receiver.id;
+                match inner.waiting.iter_mut().find(|(waiting, _)| *waiting == id) {
+                    Some((_, waker)) => *waker = cx.waker().clone(),
+                    None => inner.waiting.push((id, cx.waker().clone())),
+                }
+                Poll::Pending
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::executor::{yield_now, Executor, Task};
+
+    #[test_case]
+    fn every_receiver_gets_every_value() {
+        let sender = broadcast(4);
+        let received = Arc::new(spin::Mutex::new([Vec::new(), Vec::new()]));
+        let mut executor = Executor::new();
+        for index in 0..2 {
+            let mut receiver = sender.subscribe();
+            let received = received.clone();
+            executor.spawn(Task::new(async move {
+                while let Ok(value) = receiver.recv().await {
+                    received.lock()[index].push(value);
+                }
+            }));
+        }
+        executor.spawn(Task::new(async move {
+            for value in 0..10 {
+                assert_eq!(sender.send(value), Ok(2));
+                //Lets the receivers keep up, so nothing is dropped
+                yield_now().await;
+            }
+        }));
+        executor.test_run();
+        let expected: Vec<u32> = (0..10).collect();
+        assert_eq!(*received.lock(), [expected.clone(), expected]);
+    }
+
+    #[test_case]
+    fn slow_receiver_lags() {
+        let sender = broadcast(2);
+        let mut early = sender.subscribe();
+        assert_eq!(sender.send(1), Ok(1));
+        let mut late = sender.subscribe();
+        for value in 2..6 {
+            sender.send(value).unwrap();
+        }
+        //Only the last two are left, early missed 1, 2 and 3; late subscribed after 1, so missed 2 and 3
+        assert_eq!(early.try_recv(), Err(RecvError::Lagged(3)));
+        assert_eq!(late.try_recv(), Err(RecvError::Lagged(2)));
+        for receiver in [&mut early, &mut late] {
+            assert_eq!(receiver.try_recv(), Ok(Some(4)));
+            assert_eq!(receiver.try_recv(), Ok(Some(5)));
+            assert_eq!(receiver.try_recv(), Ok(None));
+        }
+        drop(sender);
+        assert_eq!(early.try_recv(), Err(RecvError::Closed));
+    }
+
+    #[test_case]
+    fn send_without_receivers() {
+        let sender = broadcast(1);
+        assert_eq!(sender.send(1), Err(1));
+        let receiver = sender.subscribe();
+        assert_eq!(sender.receiver_count(), 1);
+        drop(receiver);
+        assert_eq!(sender.send(2), Err(2));
+    }
+}
@@ -0,0 +1,283 @@
+use alloc::{collections::VecDeque, sync::Arc};
+use core::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll, Waker},
+};
+
+struct Waiter {
+    id: u64,
+    waker: Waker,
+}
+
+struct Shared<T> {
+    buffer: VecDeque<T>,
+    capacity: usize,
+    senders: usize,
+    receiver_alive: bool,
+    receiver_waker: Option<Waker>,
+    sender_wakers: VecDeque<Waiter>, //senders waiting for room, woken one per received item
+    next_id: u64,
+}
+
+impl<T> Shared<T> {
+    fn wake_sender(&mut self) {
+        if let Some(waiter) = self.sender_wakers.pop_front() {
+            waiter.waker.wake();
+        }
+    }
+
+    //Takes the sender off the queue, false if it was already woken
+    fn remove_sender(&mut self, id: u64) -> bool {
+        let queued = self.sender_wakers.len();
+        self.sender_wakers.retain(|waiter| waiter.id != id);
+        self.sender_wakers.len() != queued
+    }
+}
+
+/// Creates a bounded channel that holds up to `capacity` values.
+///A rendezvous channel isn't supported, so `capacity` must be at least 1
+pub fn channel<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    assert!(capacity > 0, "channel capacity must be at least 1");
+    let shared = Arc::new(spin::Mutex::new(Shared {
+        buffer: VecDeque::with_capacity(capacity),
+        capacity,
+        senders: 1,
+        receiver_alive: true,
+        receiver_waker: None,
+        sender_wakers: VecDeque::new(),
+        next_id: 0,
+    }));
+    (
+        Sender {
+            shared: shared.clone(),
+        },
+        Receiver { shared },
+    )
+}
+
+pub struct Sender<T> {
+    shared: Arc<spin::Mutex<Shared<T>>>,
+}
+
+impl<T> Sender<T> {
+    /// Waits for room in the buffer, then sends `value`. Gives it back if the receiver is gone.
+    pub fn send(&self, value: T) -> SendFuture<'_, T> {
+        SendFuture {
+            sender: self,
+            value: Some(value),
+            id: None,
+        }
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.shared.lock().senders += 1;
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut shared = self.shared.lock();
+        shared.senders -= 1;
+        if shared.senders == 0 {
+            //let the receiver see that the channel is closed
+            if let Some(waker) = shared.receiver_waker.take() {
+                waker.wake();
+            }
+        }
+    }
+}
+
+pub struct SendFuture<'a, T> {
+    sender: &'a Sender<T>,
+    value: Option<T>,
+    id: Option<u64>, //set while queued for room
+}
+
+//The value is moved out but never pinned
+impl<T> Unpin for SendFuture<'_, T> {}
+
+impl<T> Future for SendFuture<'_, T> {
+    type Output = Result<(), T>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), T>> {
+        let value = self
+            .value
+            .take()
+            .expect("SendFuture polled after completion");
+        let sender = self.sender;
+        let mut shared = sender.shared.lock();
+        if !shared.receiver_alive || shared.buffer.len() < shared.capacity {
+            if let Some(id) = self.id.take() {
+                shared.remove_sender(id);
+            }
+            if !shared.receiver_alive {
+                return Poll::Ready(Err(value));
+            }
+            shared.buffer.push_back(value);
+            if let Some(waker) = shared.receiver_waker.take() {
+                waker.wake();
+            }
+            return Poll::Ready(Ok(()));
+        }
+        let queued = self.id.and_then(|id| {
+            shared
+                .sender_wakers
+                .iter_mut()
+                .find(|waiter| waiter.id == id)
+        });
+        match queued {
+            //polled again while still waiting - keep the newest waker
+            Some(waiter) => waiter.waker = cx.waker().clone(),
+            //first poll, or woken but another sender took the room first
+            None => {
+                let id = self.id.unwrap_or_else(|| {
+                    shared.next_id += 1;
+                    shared.next_id - 1
+                });
+                shared.sender_wakers.push_back(Waiter {
+                    id,
+                    waker: cx.waker().clone(),
+                });
+                self.id = Some(id);
+            }
+        }
+        drop(shared);
+        self.value = Some(value);
+        Poll::Pending
+    }
+}
+
+impl<T> Drop for SendFuture<'_, T> {
+    fn drop(&mut self) {
+        let id = match self.id {
+            Some(id) => id,
+            None => return,
+        };
+        let mut shared = self.sender.shared.lock();
+        //Woken for room it will never use, so pass the wakeup on to the next sender
+        if !shared.remove_sender(id) {
+            shared.wake_sender();
+        }
+    }
+}
+
+pub struct Receiver<T> {
+    shared: Arc<spin::Mutex<Shared<T>>>,
+}
+
+impl<T> Receiver<T> {
+    /// Waits for the next value, `None` once every sender is gone and the buffer is empty.
+    pub fn recv(&mut self) -> RecvFuture<'_, T> {
+        RecvFuture { receiver: self }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        let mut shared = self.shared.lock();
+        shared.receiver_alive = false;
+        //blocked senders get their values back instead of waiting forever
+        for waiter in shared.sender_wakers.drain(..) {
+            waiter.waker.wake();
+        }
+    }
+}
+
+pub struct RecvFuture<'a, T> {
+    receiver: &'a mut Receiver<T>,
+}
+
+impl<T> Future for RecvFuture<'_, T> {
+    type Output = Option<T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<T>> {
+        let mut shared = self.receiver.shared.lock();
+        if let Some(value) = shared.buffer.pop_front() {
+            shared.wake_sender();
+            return Poll::Ready(Some(value));
+        }
+        if shared.senders == 0 {
+            return Poll::Ready(None);
+        }
+        shared.receiver_waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::executor::{Executor, Task};
+    use alloc::vec::Vec;
+    use core::future::poll_fn;
+
+    #[test_case]
+    fn backpressure_keeps_order() {
+        let (sender, mut receiver) = channel(1);
+        let received = Arc::new(spin::Mutex::new(Vec::new()));
+        let log = received.clone();
+        let mut executor = Executor::new();
+        executor.spawn(Task::new(async move {
+            for i in 1..=3 {
+                sender.send(i).await.unwrap();
+            }
+        }));
+        executor.spawn(Task::new(async move {
+            while let Some(value) = receiver.recv().await {
+                log.lock().push(value);
+            }
+        }));
+        executor.test_run();
+        //the receiver only stops once the sender was dropped
+        assert_eq!(*received.lock(), [1, 2, 3]);
+    }
+
+    #[test_case]
+    fn cancelled_send_leaves_queue() {
+        let (sender, mut receiver) = channel(1);
+        let second_sender = sender.clone();
+        let received = Arc::new(spin::Mutex::new(Vec::new()));
+        let log = received.clone();
+        let mut executor = Executor::new();
+        executor.spawn(Task::new(async move {
+            sender.send(1).await.unwrap();
+            //Queued for room once and then given up, like the losing side of a select
+            let mut cancelled = sender.send(2);
+            poll_fn(|cx| {
+                assert!(Pin::new(&mut cancelled).poll(cx).is_pending());
+                Poll::Ready(())
+            })
+            .await;
+        }));
+        executor.spawn(Task::new(async move {
+            second_sender.send(3).await.unwrap();
+        }));
+        executor.spawn(Task::new(async move {
+            while let Some(value) = receiver.recv().await {
+                log.lock().push(value);
+            }
+        }));
+        executor.test_run();
+        assert_eq!(*received.lock(), [1, 3]);
+    }
+
+    #[test_case]
+    fn send_without_receiver() {
+        let (sender, receiver) = channel(1);
+        drop(receiver);
+        let result = Arc::new(spin::Mutex::new(None));
+        let out = result.clone();
+        let mut executor = Executor::new();
+        executor.spawn(Task::new(async move {
+            *out.lock() = Some(sender.send(7).await);
+        }));
+        executor.test_run();
+        assert_eq!(*result.lock(), Some(Err(7)));
+    }
+}
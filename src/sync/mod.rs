@@ -0,0 +1,13 @@
+pub mod broadcast;
+mod channel;
+mod mutex;
+pub mod oneshot;
+mod rwlock;
+mod spinlock;
+
+pub use broadcast::broadcast;
+pub use channel::{channel, Receiver, RecvFuture, SendFuture, Sender};
+pub use mutex::{AsyncMutex, AsyncMutexGuard, Lock};
+pub use oneshot::oneshot;
+pub use rwlock::{AsyncRwLock, Read, ReadGuard, Write, WriteGuard};
+pub use spinlock::{IrqSpinLock, IrqSpinLockGuard, SpinLock, SpinLockGuard};
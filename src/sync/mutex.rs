@@ -0,0 +1,227 @@
+use alloc::collections::VecDeque;
+use core::{
+    cell::UnsafeCell,
+    future::Future,
+    ops::{Deref, DerefMut},
+    pin::Pin,
+    task::{Context, Poll, Waker},
+};
+
+struct Waiter {
+    id: u64,
+    waker: Waker,
+}
+
+struct State {
+    locked: bool,
+    //Set when the lock was passed straight to a waiter that hasn't been polled yet, so nobody can barge in ahead of it
+    handed_to: Option<u64>,
+    waiters: VecDeque<Waiter>,
+    next_id: u64,
+}
+
+impl State {
+    //gives the lock to the longest waiting task, or releases it if there is none
+    fn release(&mut self) {
+        match self.waiters.pop_front() {
+            Some(waiter) => {
+                self.handed_to = Some(waiter.id);
+                waiter.waker.wake();
+            }
+            None => self.locked = false,
+        }
+    }
+}
+
+/// A mutex that parks the task while the lock is held elsewhere instead of spinning.
+///Waiters get the lock in the order they started waiting
+pub struct AsyncMutex<T> {
+    state: spin::Mutex<State>,
+    value: UnsafeCell<T>,
+}
+
+//The value is only ever reached through a guard, of which there is at most one
+unsafe impl<T: Send> Send for AsyncMutex<T> {}
+unsafe impl<T: Send> Sync for AsyncMutex<T> {}
+
+impl<T> AsyncMutex<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            state: spin::Mutex::new(State {
+                locked: false,
+                handed_to: None,
+                waiters: VecDeque::new(),
+                next_id: 0,
+            }),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    pub fn lock(&self) -> Lock<'_, T> {
+        Lock {
+            mutex: self,
+            id: None,
+        }
+    }
+
+    /// Takes the lock if it's free right now, without queueing.
+    pub fn try_lock(&self) -> Option<AsyncMutexGuard<'_, T>> {
+        let mut state = self.state.lock();
+        if state.locked {
+            return None;
+        }
+        state.locked = true;
+        Some(AsyncMutexGuard { mutex: self })
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+}
+
+/// Future returned by `AsyncMutex::lock`.
+pub struct Lock<'a, T> {
+    mutex: &'a AsyncMutex<T>,
+    id: Option<u64>, //set once queued
+}
+
+impl<'a, T> Future for Lock<'a, T> {
+    type Output = AsyncMutexGuard<'a, T>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let mutex = self.mutex;
+        let mut state = mutex.state.lock();
+        match self.id {
+            None if !state.locked => {
+                state.locked = true;
+                return Poll::Ready(AsyncMutexGuard { mutex });
+            }
+            None => {
+                let id = state.next_id;
+                state.next_id += 1;
+                state.waiters.push_back(Waiter {
+                    id,
+                    waker: cx.waker().clone(),
+                });
+                drop(state);
+                self.id = Some(id);
+            }
+            Some(id) if state.handed_to == Some(id) => {
+                state.handed_to = None;
+                drop(state);
+                self.id = None;
+                return Poll::Ready(AsyncMutexGuard { mutex });
+            }
+            Some(id) => {
+                //polled again before its turn - keep the newest waker
+                if let Some(waiter) = state.waiters.iter_mut().find(|waiter| waiter.id == id) {
+                    waiter.waker = cx.waker().clone();
+                }
+            }
+        }
+        Poll::Pending
+    }
+}
+
+impl<T> Drop for Lock<'_, T> {
+    fn drop(&mut self) {
+        let id = match self.id {
+            Some(id) => id,
+            None => return,
+        };
+        let mut state = self.mutex.state.lock();
+        if state.handed_to == Some(id) {
+            //the lock was already ours - pass it on rather than leaving it held forever
+            state.handed_to = None;
+            state.release();
+        } else {
+            state.waiters.retain(|waiter| waiter.id != id);
+        }
+    }
+}
+
+pub struct AsyncMutexGuard<'a, T> {
+    mutex: &'a AsyncMutex<T>,
+}
+
+impl<T> Deref for AsyncMutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.value.get() }
+    }
+}
+
+impl<T> DerefMut for AsyncMutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.value.get() }
+    }
+}
+
+impl<T> Drop for AsyncMutexGuard<'_, T> {
+    fn drop(&mut self) {
+        self.mutex.state.lock().release();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::executor::{yield_now, Executor, Task};
+    use alloc::{sync::Arc, vec::Vec};
+    use futures_util::task::noop_waker;
+
+    #[test_case]
+    fn contending_tasks_are_serialized() {
+        let mutex = Arc::new(AsyncMutex::new(Vec::new()));
+        let mut executor = Executor::new();
+        for task in 0..2 {
+            let mutex = mutex.clone();
+            executor.spawn(Task::new(async move {
+                for _ in 0..3 {
+                    let mut log = mutex.lock().await;
+                    log.push((task, "enter"));
+                    //the other task gets to run here, but must not get the lock
+                    yield_now().await;
+                    log.push((task, "exit"));
+                }
+            }));
+        }
+        executor.test_run();
+
+        let log = Arc::try_unwrap(mutex).ok().unwrap().into_inner();
+        assert_eq!(log.len(), 12);
+        for pair in log.chunks(2) {
+            assert_eq!(pair[0].0, pair[1].0);
+            assert_eq!((pair[0].1, pair[1].1), ("enter", "exit"));
+        }
+        //FIFO handoff makes the two tasks alternate
+        assert!(log
+            .chunks(2)
+            .zip(log.chunks(2).skip(1))
+            .all(|(a, b)| a[0].0 != b[0].0));
+    }
+
+    #[test_case]
+    fn dropped_waiter_is_removed() {
+        let mutex = AsyncMutex::new(0);
+        let waker = noop_waker();
+        let mut cx = Context::from_waker(&waker);
+
+        let guard = mutex.try_lock().unwrap();
+        let mut first = mutex.lock();
+        let mut second = mutex.lock();
+        assert!(Pin::new(&mut first).poll(&mut cx).is_pending());
+        assert!(Pin::new(&mut second).poll(&mut cx).is_pending());
+
+        //first is handed the lock, but gives up before ever taking it
+        drop(guard);
+        drop(first);
+        match Pin::new(&mut second).poll(&mut cx) {
+            Poll::Ready(guard) => drop(guard),
+            Poll::Pending => panic!("lock was not passed on past the dropped waiter"),
+        }
+        assert!(mutex.state.lock().waiters.is_empty());
+        assert!(mutex.try_lock().is_some());
+    }
+}
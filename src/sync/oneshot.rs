@@ -0,0 +1,150 @@
+use alloc::sync::Arc;
+use core::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll, Waker},
+};
+
+struct Inner<T> {
+    value: Option<T>,
+    sender_alive: bool,
+    receiver_alive: bool,
+    waker: Option<Waker>,
+}
+
+/// Creates a channel that carries a single value from the `Sender` to the `Receiver`.
+pub fn oneshot<T>() -> (Sender<T>, Receiver<T>) {
+    let inner = Arc::new(spin::Mutex::new(Inner {
+        value: None,
+        sender_alive: true,
+        receiver_alive: true,
+        waker: None,
+    }));
+    (
+        Sender {
+            inner: inner.clone(),
+        },
+        Receiver { inner },
+    )
+}
+
+/// The sender was dropped without sending a value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Canceled;
+
+impl fmt::Display for Canceled {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "oneshot sender dropped without sending")
+    }
+}
+
+pub struct Sender<T> {
+    inner: Arc<spin::Mutex<Inner<T>>>,
+}
+
+impl<T> Sender<T> {
+    /// Sends the value, or hands it back if the receiver is already gone.
+    pub fn send(self, value: T) -> Result<(), T> {
+        let mut inner = self.inner.lock();
+        if !inner.receiver_alive {
+            return Err(value);
+        }
+        inner.value = Some(value);
+        if let Some(waker) = inner.waker.take() {
+            waker.wake();
+        }
+        Ok(())
+    }
+
+    /// Whether the receiver has been dropped, i.e. sending would be pointless.
+    pub fn is_canceled(&self) -> bool {
+        !self.inner.lock().receiver_alive
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut inner = self.inner.lock();
+        inner.sender_alive = false;
+        //wakes the receiver whether or not a value was sent first
+        if let Some(waker) = inner.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// Resolves to the sent value, or `Canceled` if the sender is dropped first.
+pub struct Receiver<T> {
+    inner: Arc<spin::Mutex<Inner<T>>>,
+}
+
+impl<T> Future for Receiver<T> {
+    type Output = Result<T, Canceled>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<T, Canceled>> {
+        let mut inner = self.inner.lock();
+        if let Some(value) = inner.value.take() {
+            return Poll::Ready(Ok(value));
+        }
+        if !inner.sender_alive {
+            return Poll::Ready(Err(Canceled));
+        }
+        inner.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        let mut inner = self.inner.lock();
+        inner.receiver_alive = false;
+        //a value sent but never received is dropped along with the channel
+        inner.value = None;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::executor::{Executor, Task};
+
+    fn receive<T: Send + 'static>(
+        receiver: Receiver<T>,
+    ) -> Arc<spin::Mutex<Option<Result<T, Canceled>>>> {
+        let result = Arc::new(spin::Mutex::new(None));
+        let out = result.clone();
+        let mut executor = Executor::new();
+        executor.spawn(Task::new(async move {
+            *out.lock() = Some(receiver.await);
+        }));
+        executor.test_run();
+        result
+    }
+
+    #[test_case]
+    fn value_is_received() {
+        let (sender, receiver) = oneshot();
+        let mut executor = Executor::new();
+        executor.spawn(Task::new(async move {
+            sender.send(42).unwrap();
+        }));
+        executor.test_run();
+        assert_eq!(*receive(receiver).lock(), Some(Ok(42)));
+    }
+
+    #[test_case]
+    fn dropped_sender_cancels() {
+        let (sender, receiver) = oneshot::<u32>();
+        drop(sender);
+        assert_eq!(*receive(receiver).lock(), Some(Err(Canceled)));
+    }
+
+    #[test_case]
+    fn send_after_receiver_dropped() {
+        let (sender, receiver) = oneshot();
+        drop(receiver);
+        assert!(sender.is_canceled());
+        assert_eq!(sender.send(1), Err(1));
+    }
+}
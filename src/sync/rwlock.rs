@@ -0,0 +1,321 @@
+use alloc::{collections::VecDeque, vec::Vec};
+use core::{
+    cell::UnsafeCell,
+    future::Future,
+    ops::{Deref, DerefMut},
+    pin::Pin,
+    task::{Context, Poll, Waker},
+};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Access {
+    Read,
+    Write,
+}
+
+struct Waiter {
+    id: u64,
+    access: Access,
+    waker: Waker,
+}
+
+struct State {
+    readers: usize,
+    writer: bool,
+    waiters: VecDeque<Waiter>,
+    //waiters that were let in while they weren't looking, already counted in readers or writer
+    granted: Vec<u64>,
+    next_id: u64,
+}
+
+impl State {
+    //lets in the longest waiting writer, or every reader queued ahead of the next writer
+    fn grant(&mut self) {
+        while let Some(waiter) = self.waiters.front() {
+            let admitted = match waiter.access {
+                Access::Write if !self.writer && self.readers == 0 => {
+                    self.writer = true;
+                    true
+                }
+                Access::Read if !self.writer => {
+                    self.readers += 1;
+                    true
+                }
+                _ => false,
+            };
+            if !admitted {
+                break;
+            }
+            let waiter = self.waiters.pop_front().unwrap();
+            self.granted.push(waiter.id);
+            waiter.waker.wake();
+            if waiter.access == Access::Write {
+                break;
+            }
+        }
+    }
+
+    fn release(&mut self, access: Access) {
+        match access {
+            Access::Read => self.readers -= 1,
+            Access::Write => self.writer = false,
+        }
+        self.grant();
+    }
+}
+
+/// A reader/writer lock that parks tasks instead of spinning: any number of readers, or a single
+/// writer. Waiters are let in in the order they arrived, so once a writer is waiting new readers
+/// queue up behind it rather than starving it.
+///
+/// A read guard can't be upgraded to a write guard - two readers both waiting to upgrade would
+/// deadlock. Drop the read guard and `write().await` instead, re-checking whatever was read.
+pub struct AsyncRwLock<T> {
+    state: spin::Mutex<State>,
+    value: UnsafeCell<T>,
+}
+
+//Readers share the value across tasks, so it has to be Sync as well as Send
+unsafe impl<T: Send> Send for AsyncRwLock<T> {}
+unsafe impl<T: Send + Sync> Sync for AsyncRwLock<T> {}
+
+impl<T> AsyncRwLock<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            state: spin::Mutex::new(State {
+                readers: 0,
+                writer: false,
+                waiters: VecDeque::new(),
+                granted: Vec::new(),
+                next_id: 0,
+            }),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    pub fn read(&self) -> Read<'_, T> {
+        Read(Acquire::new(self, Access::Read))
+    }
+
+    pub fn write(&self) -> Write<'_, T> {
+        Write(Acquire::new(self, Access::Write))
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+}
+
+//The queueing shared by Read and Write
+struct Acquire<'a, T> {
+    lock: &'a AsyncRwLock<T>,
+    access: Access,
+    id: Option<u64>, //set once queued
+}
+
+impl<'a, T> Acquire<'a, T> {
+    fn new(lock: &'a AsyncRwLock<T>, access: Access) -> Self {
+        Self {
+            lock,
+            access,
+            id: None,
+        }
+    }
+
+    fn poll_acquire(&mut self, cx: &mut Context) -> Poll<()> {
+        let mut state = self.lock.state.lock();
+        match self.id {
+            //Anyone already waiting goes first, which is what keeps a waiting writer from starving
+            None if state.waiters.is_empty() => {
+                let free = match self.access {
+                    Access::Read => !state.writer,
+                    Access::Write => !state.writer && state.readers == 0,
+                };
+                if free {
+                    match self.access {
+                        Access::Read => state.readers += 1,
+                        Access::Write => state.writer = true,
+                    }
+                    return Poll::Ready(());
+                }
+            }
+            Some(id) => {
+                if let Some(index) = state.granted.iter().position(|&granted| granted == id) {
+                    state.granted.swap_remove(index);
+                    drop(state);
+                    self.id = None;
+                    return Poll::Ready(());
+                }
+                //polled again before its turn - keep the newest waker
+                if let Some(waiter) = state.waiters.iter_mut().find(|waiter| waiter.id == id) {
+                    waiter.waker = cx.waker().clone();
+                }
+                return Poll::Pending;
+            }
+            None => {}
+        }
+        let id = state.next_id;
+        state.next_id += 1;
+        state.waiters.push_back(Waiter {
+            id,
+            access: self.access,
+            waker: cx.waker().clone(),
+        });
+        drop(state);
+        self.id = Some(id);
+        Poll::Pending
+    }
+}
+
+impl<T> Drop for Acquire<'_, T> {
+    fn drop(&mut self) {
+        let id = match self.id {
+            Some(id) => id,
+            None => return,
+        };
+        let mut state = self.lock.state.lock();
+        match state.granted.iter().position(|&granted| granted == id) {
+            //let in but never took it - give it back rather than holding it forever
+            Some(index) => {
+                state.granted.swap_remove(index);
+                state.release(self.access);
+            }
+            None => {
+                state.waiters.retain(|waiter| waiter.id != id);
+                //a writer leaving the front of the queue may unblock the readers behind it
+                state.grant();
+            }
+        }
+    }
+}
+
+/// Future returned by `AsyncRwLock::read`.
+pub struct Read<'a, T>(Acquire<'a, T>);
+
+impl<'a, T> Future for Read<'a, T> {
+    type Output = ReadGuard<'a, T>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<ReadGuard<'a, T>> {
+        let lock = self.0.lock;
+        self.0.poll_acquire(cx).map(|()| ReadGuard { lock })
+    }
+}
+
+/// Future returned by `AsyncRwLock::write`.
+pub struct Write<'a, T>(Acquire<'a, T>);
+
+impl<'a, T> Future for Write<'a, T> {
+    type Output = WriteGuard<'a, T>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<WriteGuard<'a, T>> {
+        let lock = self.0.lock;
+        self.0.poll_acquire(cx).map(|()| WriteGuard { lock })
+    }
+}
+
+pub struct ReadGuard<'a, T> {
+    lock: &'a AsyncRwLock<T>,
+}
+
+impl<T> Deref for ReadGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+impl<T> Drop for ReadGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.state.lock().release(Access::Read);
+    }
+}
+
+pub struct WriteGuard<'a, T> {
+    lock: &'a AsyncRwLock<T>,
+}
+
+impl<T> Deref for WriteGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+impl<T> DerefMut for WriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.value.get() }
+    }
+}
+
+impl<T> Drop for WriteGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.state.lock().release(Access::Write);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use futures_util::task::noop_waker;
+
+    fn poll<F: Future + Unpin>(future: &mut F) -> Poll<F::Output> {
+        let waker = noop_waker();
+        Pin::new(future).poll(&mut Context::from_waker(&waker))
+    }
+
+    #[test_case]
+    fn writer_waits_for_readers() {
+        let lock = AsyncRwLock::new(1);
+        let first = match poll(&mut lock.read()) {
+            Poll::Ready(guard) => guard,
+            Poll::Pending => panic!("free lock not read"),
+        };
+        let second = match poll(&mut lock.read()) {
+            Poll::Ready(guard) => guard,
+            Poll::Pending => panic!("readers don't share the lock"),
+        };
+        assert_eq!(*first + *second, 2);
+
+        let mut write = lock.write();
+        assert!(poll(&mut write).is_pending());
+        //The writer is waiting, so a new reader queues behind it
+        let mut late_read = lock.read();
+        assert!(poll(&mut late_read).is_pending());
+
+        drop(first);
+        assert!(poll(&mut write).is_pending());
+        drop(second);
+        let mut guard = match poll(&mut write) {
+            Poll::Ready(guard) => guard,
+            Poll::Pending => panic!("writer not let in after the readers left"),
+        };
+        *guard = 5;
+        assert!(poll(&mut late_read).is_pending());
+        drop(guard);
+        match poll(&mut late_read) {
+            Poll::Ready(guard) => assert_eq!(*guard, 5),
+            Poll::Pending => panic!("reader not let in after the writer"),
+        };
+    }
+
+    #[test_case]
+    fn dropped_writer_unblocks_readers() {
+        let lock = AsyncRwLock::new(());
+        let reader = match poll(&mut lock.read()) {
+            Poll::Ready(guard) => guard,
+            Poll::Pending => panic!("free lock not read"),
+        };
+        let mut write = lock.write();
+        let mut read = lock.read();
+        assert!(poll(&mut write).is_pending());
+        assert!(poll(&mut read).is_pending());
+        //The writer gives up, so nothing is left ahead of the reader
+        drop(write);
+        assert!(poll(&mut read).is_ready());
+        drop(reader);
+        let state = lock.state.lock();
+        assert!(state.waiters.is_empty() && state.granted.is_empty());
+    }
+}
@@ -0,0 +1,166 @@
+use core::{
+    cell::UnsafeCell,
+    hint,
+    ops::{Deref, DerefMut},
+    sync::atomic::{AtomicBool, Ordering},
+};
+use x86_64::instructions::interrupts;
+
+/// A lock that busy-waits until it's free.
+pub struct SpinLock<T> {
+    locked: AtomicBool,
+    value: UnsafeCell<T>,
+}
+
+//The value is only ever reached through a guard, of which there is at most one
+unsafe impl<T: Send> Send for SpinLock<T> {}
+unsafe impl<T: Send> Sync for SpinLock<T> {}
+
+impl<T> SpinLock<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            locked: AtomicBool::new(false),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    pub fn lock(&self) -> SpinLockGuard<'_, T> {
+        loop {
+            if let Some(guard) = self.try_lock() {
+                return guard;
+            }
+            //Wait on a plain load so the cache line isn't bounced around by failed swaps
+            while self.locked.load(Ordering::Relaxed) {
+                hint::spin_loop();
+            }
+        }
+    }
+
+    pub fn try_lock(&self) -> Option<SpinLockGuard<'_, T>> {
+        self.locked
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .ok()
+            .map(|_| SpinLockGuard { lock: self })
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+}
+
+pub struct SpinLockGuard<'a, T> {
+    lock: &'a SpinLock<T>,
+}
+
+impl<T> Deref for SpinLockGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+impl<T> DerefMut for SpinLockGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.value.get() }
+    }
+}
+
+impl<T> Drop for SpinLockGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.locked.store(false, Ordering::Release);
+    }
+}
+
+/// A `SpinLock` that keeps interrupts disabled while it's held, for data shared with interrupt
+/// handlers - otherwise a handler taking the lock the interrupted code holds spins forever.
+pub struct IrqSpinLock<T> {
+    inner: SpinLock<T>,
+}
+
+impl<T> IrqSpinLock<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            inner: SpinLock::new(value),
+        }
+    }
+
+    pub fn lock(&self) -> IrqSpinLockGuard<'_, T> {
+        let were_enabled = interrupts::are_enabled();
+        interrupts::disable();
+        IrqSpinLockGuard {
+            guard: Some(self.inner.lock()),
+            were_enabled,
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner.into_inner()
+    }
+}
+
+pub struct IrqSpinLockGuard<'a, T> {
+    guard: Option<SpinLockGuard<'a, T>>, //taken on drop so the lock is released before interrupts come back on
+    were_enabled: bool,
+}
+
+impl<T> Deref for IrqSpinLockGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.guard.as_ref().unwrap()
+    }
+}
+
+impl<T> DerefMut for IrqSpinLockGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.guard.as_mut().unwrap()
+    }
+}
+
+impl<T> Drop for IrqSpinLockGuard<'_, T> {
+    fn drop(&mut self) {
+        drop(self.guard.take());
+        //Only re-enable if they were on before - a lock taken inside an interrupt handler or
+        //another critical section must leave them off
+        if self.were_enabled {
+            interrupts::enable();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test_case]
+    fn spin_lock_exclusive() {
+        let lock = SpinLock::new(1);
+        {
+            let mut guard = lock.lock();
+            *guard += 1;
+            assert!(lock.try_lock().is_none());
+        }
+        assert_eq!(*lock.try_lock().unwrap(), 2);
+    }
+
+    #[test_case]
+    fn irq_guard_restores_interrupts() {
+        let lock = IrqSpinLock::new(0);
+        assert!(interrupts::are_enabled());
+        {
+            let _guard = lock.lock();
+            assert!(!interrupts::are_enabled());
+            //Nested inside another critical section, dropping the inner guard keeps them off
+            let other = IrqSpinLock::new(0);
+            drop(other.lock());
+            assert!(!interrupts::are_enabled());
+        }
+        assert!(interrupts::are_enabled());
+
+        interrupts::without_interrupts(|| {
+            drop(lock.lock());
+            assert!(!interrupts::are_enabled());
+        });
+    }
+}
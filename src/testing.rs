@@ -0,0 +1,197 @@
+//! The test runner, with a watchdog failing tests that hang instead of letting them stall the run
+//! and support for tests that have to panic to pass.
+use crate::{exit_qemu, hlt_loop, serial_print, serial_println, QemuExitCode, Testable};
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+/// About a minute of PIT ticks.
+pub const DEFAULT_TEST_TIMEOUT: u64 = 1092;
+
+const NOT_RUNNING: u64 = u64::MAX;
+
+//Tick the current test started at, NOT_RUNNING between tests
+static TEST_START: AtomicU64 = AtomicU64::new(NOT_RUNNING);
+static TEST_TIMEOUT: AtomicU64 = AtomicU64::new(DEFAULT_TEST_TIMEOUT);
+static CURRENT_TEST: Mutex<&str> = Mutex::new("");
+static TIMEOUT_HANDLER: Mutex<fn(&str)> = Mutex::new(default_timeout_handler);
+
+fn default_timeout_handler(name: &str) {
+    serial_println!("[timed out]\n");
+    serial_println!(
+        "Error: {} did not finish within {} ticks\n",
+        name,
+        TEST_TIMEOUT.load(Ordering::Relaxed)
+    );
+    exit_qemu(QemuExitCode::Failed);
+}
+
+/// Sets how many timer ticks a test gets before the watchdog fires, applying to the running test too.
+pub fn set_test_timeout(ticks: u64) {
+    TEST_TIMEOUT.store(ticks, Ordering::Relaxed);
+}
+
+/// Replaces the function run when a test times out, called from the timer interrupt with the test's
+/// name. The kernel halts once it returns.
+pub fn set_timeout_handler(handler: fn(&str)) {
+    *TIMEOUT_HANDLER.lock() = handler;
+}
+
+pub(crate) fn arm(name: &'static str) {
+    *CURRENT_TEST.lock() = name;
+    TEST_START.store(crate::time::ticks(), Ordering::Relaxed);
+}
+
+pub(crate) fn disarm() {
+    TEST_START.store(NOT_RUNNING, Ordering::Relaxed);
+}
+
+/// Called from the timer interrupt with the new tick count.
+pub fn watchdog_tick(now: u64) {
+    let start = TEST_START.load(Ordering::Relaxed);
+    if start == NOT_RUNNING || now - start <= TEST_TIMEOUT.load(Ordering::Relaxed) {
+        return;
+    }
+    disarm();
+    //The test may have been interrupted holding either lock
+    let name = CURRENT_TEST
+        .try_lock()
+        .map_or("<unknown test>", |name| *name);
+    let handler = TIMEOUT_HANDLER
+        .try_lock()
+        .map_or(default_timeout_handler as fn(&str), |handler| *handler);
+    handler(name);
+    hlt_loop();
+}
+
+//The test list and how far the runner got through it, so a should_panic test that panicked
+//can carry on with the next test. Only valid while test_runner is on the stack, i.e. forever
+struct TestList(*const [&'static dyn Testable]);
+unsafe impl Send for TestList {}
+
+static TESTS: Mutex<Option<TestList>> = Mutex::new(None);
+//Only tests whose name contains it run, e.g. `TEST_FILTER=allocator cargo test`
+const TEST_FILTER: Option<&str> = option_env!("TEST_FILTER");
+static NEXT_TEST: AtomicUsize = AtomicUsize::new(0);
+static EXPECTING_PANIC: AtomicBool = AtomicBool::new(false);
+
+//The tests, not benches, the filter lets through, with their index in the full list
+fn selected<'a>(
+    tests: &'a [&'a dyn Testable],
+    filter: Option<&'a str>,
+) -> impl Iterator<Item = (usize, &'a dyn Testable)> + 'a {
+    tests.iter().copied().enumerate().filter(move |(_, test)| {
+        !test.is_bench() && filter.is_none_or(|filter| test.name().contains(filter))
+    })
+}
+
+pub(crate) fn run_tests(tests: &[&dyn Testable]) -> ! {
+    let benches = tests.iter().filter(|test| test.is_bench()).count();
+    let count = selected(tests, TEST_FILTER).count();
+    serial_println!("Running {} tests", count);
+    if count < tests.len() - benches {
+        serial_println!(
+            "{} filtered out by \"{}\"",
+            tests.len() - benches - count,
+            TEST_FILTER.unwrap_or_default()
+        );
+    }
+    *TESTS.lock() = Some(TestList(tests as *const [&dyn Testable] as *const _));
+    run_from(0)
+}
+
+fn run_from(start: usize) -> ! {
+    let tests = unsafe { &*TESTS.lock().as_ref().expect("no tests registered").0 };
+    for (i, test) in selected(tests, TEST_FILTER).skip_while(|&(i, _)| i < start) {
+        NEXT_TEST.store(i + 1, Ordering::Relaxed);
+        EXPECTING_PANIC.store(false, Ordering::Relaxed);
+        arm(test.name());
+        test.run();
+        disarm();
+    }
+    //Benches are only run when nothing is filtered out
+    let benches = tests.iter().filter(|test| test.is_bench()).count();
+    if benches > 0 && TEST_FILTER.is_none() {
+        serial_println!("Running {} benches", benches);
+        for bench in tests.iter().filter(|test| test.is_bench()) {
+            bench.run();
+        }
+    }
+    exit_qemu(QemuExitCode::Success);
+    hlt_loop()
+}
+
+/// Called by the test panic handler - if the running test was expected to panic it passed, and the
+/// remaining tests run on top of the panicked one's stack. Returns if the panic is a real failure.
+pub(crate) fn resume_after_expected_panic() {
+    if !EXPECTING_PANIC.swap(false, Ordering::Relaxed) {
+        return;
+    }
+    disarm();
+    serial_println!("[ok]");
+    //The test may have panicked with interrupts disabled
+    interrupts::enable();
+    run_from(NEXT_TEST.load(Ordering::Relaxed))
+}
+
+/// A test that passes by panicking, declared as a `#[test_case]` static:
+/// ```ignore
+/// #[test_case]
+/// static ZERO_DIVISOR: ShouldPanic = ShouldPanic::new("zero_divisor", || divide(1, 0));
+/// ```
+///Nothing the test held is cleaned up after the panic, so it shouldn't panic holding a lock
+pub struct ShouldPanic {
+    name: &'static str,
+    f: fn(),
+}
+
+impl ShouldPanic {
+    pub const fn new(name: &'static str, f: fn()) -> Self {
+        Self { name, f }
+    }
+}
+
+impl Testable for ShouldPanic {
+    fn run(&self) {
+        serial_print!("{} (should panic)...\t", self.name);
+        EXPECTING_PANIC.store(true, Ordering::Relaxed);
+        (self.f)();
+        EXPECTING_PANIC.store(false, Ordering::Relaxed);
+        serial_println!("[failed]\n");
+        serial_println!("Error: {} did not panic\n", self.name);
+        exit_qemu(QemuExitCode::Failed);
+        hlt_loop();
+    }
+
+    fn name(&self) -> &'static str {
+        self.name
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    static RAN: AtomicUsize = AtomicUsize::new(0);
+
+    fn filter_alpha() {
+        RAN.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn filter_beta() {
+        RAN.fetch_add(10, Ordering::Relaxed);
+    }
+
+    #[test_case]
+    fn filter_by_name() {
+        let tests: [&dyn Testable; 2] = [&filter_alpha, &filter_beta];
+        assert_eq!(selected(&tests, None).count(), 2);
+        assert_eq!(selected(&tests, Some("no_such_test")).count(), 0);
+
+        let matching: alloc::vec::Vec<_> = selected(&tests, Some("alpha")).collect();
+        assert_eq!(matching.len(), 1);
+        assert_eq!(matching[0].0, 0);
+        matching[0].1.run();
+        assert_eq!(RAN.load(Ordering::Relaxed), 1);
+    }
+}
@@ -0,0 +1,216 @@
+//! Preemptive kernel threads, switched round-robin on every timer tick.
+//!
+//! The thread that booted the kernel (and runs the executor) is a thread too, it just keeps the
+//! bootloader's stack. Switches happen inside the timer interrupt handler: the outgoing thread's
+//! interrupt frame stays on its own stack, so when it's switched back to it returns from the handler
+//! with `iretq` as if nothing happened.
+use alloc::{boxed::Box, vec, vec::Vec};
+use core::arch::global_asm;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+const STACK_SIZE: usize = 16 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ThreadId(u64);
+
+type Entry = Box<dyn FnOnce() + Send>;
+
+struct Thread {
+    id: ThreadId,
+    _stack: Option<Vec<u8>>, //keeps the memory rsp points into alive - None for the boot thread
+    rsp: u64,                //saved while the thread isn't running
+    next: Option<Box<Thread>>,
+}
+
+//Intrusive lists of threads, so moving threads around never allocates. Nothing touching the scheduler
+//may allocate: it runs with interrupts off, and a preempted thread could be holding the heap lock
+type ThreadList = Option<Box<Thread>>;
+
+fn push_back(list: &mut ThreadList, mut thread: Box<Thread>) {
+    thread.next = None;
+    let mut slot = list;
+    while slot.is_some() {
+        slot = &mut slot.as_mut().unwrap().next;
+    }
+    *slot = Some(thread);
+}
+
+fn pop_front(list: &mut ThreadList) -> Option<Box<Thread>> {
+    let mut thread = list.take()?;
+    *list = thread.next.take();
+    Some(thread)
+}
+
+struct Scheduler {
+    current: Option<Box<Thread>>, //None until the first spawn
+    ready: ThreadList,
+    //threads that have exited - their stacks are freed on the next spawn, never by the interrupt handler
+    finished: ThreadList,
+}
+
+//Only ever locked with interrupts disabled, so the timer interrupt can't find it held
+static SCHEDULER: Mutex<Scheduler> = Mutex::new(Scheduler {
+    current: None,
+    ready: None,
+    finished: None,
+});
+//Lets the timer interrupt skip the scheduler entirely until there's a thread to switch to
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+extern "C" {
+    fn switch_context(old_rsp: *mut u64, new_rsp: u64);
+    fn thread_trampoline();
+}
+
+//Saves the callee-saved registers on the old stack and restores them from the new one - the caller
+//saved ones are already on the stack, since switch_context is only called from Rust code
+global_asm!(
+    ".global switch_context",
+    "switch_context:",
+    "push rbp",
+    "push rbx",
+    "push r12",
+    "push r13",
+    "push r14",
+    "push r15",
+    "mov [rdi], rsp",
+    "mov rsp, rsi",
+    "pop r15",
+    "pop r14",
+    "pop r13",
+    "pop r12",
+    "pop rbx",
+    "pop rbp",
+    "ret",
+    //A new thread's first switch returns here, with its entry in r12
+    ".global thread_trampoline",
+    "thread_trampoline:",
+    "mov rdi, r12",
+    "call {start}",
+    start = sym thread_start,
+);
+
+extern "C" fn thread_start(entry: *mut Entry) -> ! {
+    //Switched to from the timer interrupt, which left interrupts disabled
+    interrupts::enable();
+    let entry = unsafe { Box::from_raw(entry) };
+    entry();
+    exit()
+}
+
+impl Thread {
+    fn new(entry: Entry) -> Box<Thread> {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(1); //0 is the boot thread
+        let mut stack = vec![0u8; STACK_SIZE];
+        //The initial frame is what switch_context pops: six registers, then the return address
+        let top = (stack.as_mut_ptr() as u64 + STACK_SIZE as u64) & !0xF;
+        let frame = (top - 8 * 8) as *mut u64;
+        unsafe {
+            //r15, r14, r13, r12 (the entry), rbx, rbp, return address, padding so the
+            //trampoline's call leaves thread_start with the stack alignment the ABI expects
+            let registers = [0, 0, 0, Box::into_raw(Box::new(entry)) as u64, 0, 0];
+            for (i, value) in registers.iter().enumerate() {
+                frame.add(i).write(*value);
+            }
+            frame
+                .add(6)
+                .write(thread_trampoline as unsafe extern "C" fn() as usize as u64);
+            frame.add(7).write(0);
+        }
+        Box::new(Thread {
+            id: ThreadId(NEXT_ID.fetch_add(1, Ordering::Relaxed)),
+            _stack: Some(stack),
+            rsp: frame as u64,
+            next: None,
+        })
+    }
+}
+
+/// Starts `f` on a new thread. It runs on its next turn of the round-robin schedule.
+pub fn spawn(f: impl FnOnce() + Send + 'static) -> ThreadId {
+    let thread = Thread::new(Box::new(f));
+    let id = thread.id;
+    //Only needed on the first spawn, but can't be allocated once the scheduler is locked
+    let boot_thread = Box::new(Thread {
+        id: ThreadId(0),
+        _stack: None,
+        rsp: 0,
+        next: None,
+    });
+    let (mut finished, unused) = interrupts::without_interrupts(|| {
+        let mut scheduler = SCHEDULER.lock();
+        let unused = match scheduler.current {
+            Some(_) => Some(boot_thread),
+            None => {
+                scheduler.current = Some(boot_thread);
+                None
+            }
+        };
+        push_back(&mut scheduler.ready, thread);
+        ENABLED.store(true, Ordering::Relaxed);
+        (scheduler.finished.take(), unused)
+    });
+    drop(unused);
+    //Nothing runs on these stacks anymore, so they can go - one at a time to keep the drop from recursing
+    while let Some(thread) = pop_front(&mut finished) {
+        drop(thread);
+    }
+    id
+}
+
+/// The id of the thread making the call.
+pub fn current() -> ThreadId {
+    interrupts::without_interrupts(|| {
+        SCHEDULER
+            .lock()
+            .current
+            .as_ref()
+            .map_or(ThreadId(0), |thread| thread.id)
+    })
+}
+
+//Takes the next ready thread and makes it current, returning where to save the old stack pointer
+//and the stack pointer to switch to - None if there's nothing else to run
+fn next(scheduler: &mut Scheduler, requeue: bool) -> Option<(*mut u64, u64)> {
+    let next = pop_front(&mut scheduler.ready)?;
+    let new_rsp = next.rsp;
+    let mut previous = scheduler.current.replace(next)?;
+    //Boxed, so the saved rsp stays where it is when the thread moves between queues
+    let old_rsp = &mut previous.rsp as *mut u64;
+    if requeue {
+        push_back(&mut scheduler.ready, previous);
+    } else {
+        previous.next = scheduler.finished.take();
+        scheduler.finished = Some(previous);
+    }
+    Some((old_rsp, new_rsp))
+}
+
+/// Called from the timer interrupt after the end of interrupt was sent.
+/// Switches to the next ready thread, returning once this thread is scheduled again.
+pub fn preempt() {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    let switch = match SCHEDULER.try_lock() {
+        Some(mut scheduler) => next(&mut scheduler, true),
+        None => None,
+    };
+    if let Some((old_rsp, new_rsp)) = switch {
+        unsafe { switch_context(old_rsp, new_rsp) };
+    }
+}
+
+/// Ends the calling thread. Returning from a thread's function does the same.
+pub fn exit() -> ! {
+    interrupts::disable();
+    let switch = next(&mut SCHEDULER.lock(), false);
+    match switch {
+        Some((old_rsp, new_rsp)) => unsafe { switch_context(old_rsp, new_rsp) },
+        //The boot thread is always either running or ready, so only it can get here
+        None => panic!("boot thread exited"),
+    }
+    unreachable!("finished thread was switched back to");
+}
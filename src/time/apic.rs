@@ -0,0 +1,172 @@
+use super::{ticks, timer_source, TimerSource, PIT_DIVISOR, PIT_FREQUENCY, TIMER_SOURCE};
+use crate::cpu::msr;
+use crate::interrupts::{InterruptIndex, PICS};
+use crate::memory;
+use core::sync::atomic::{AtomicBool, Ordering};
+use x86_64::structures::paging::{
+    mapper::MapToError, Mapper, Page, PageTableFlags, PhysFrame, Size4KiB,
+};
+use x86_64::{PhysAddr, VirtAddr};
+
+//Where the local APIC registers get mapped, away from the heap and anonymous mappings
+const APIC_VIRT: u64 = 0x_3333_0000_0000;
+
+const REG_EOI: usize = 0xB0;
+const REG_SPURIOUS: usize = 0xF0;
+const REG_LVT_TIMER: usize = 0x320;
+const REG_INITIAL_COUNT: usize = 0x380;
+const REG_CURRENT_COUNT: usize = 0x390;
+const REG_DIVIDE: usize = 0x3E0;
+
+const APIC_ENABLE: u32 = 1 << 8; //spurious vector register
+const LVT_MASKED: u32 = 1 << 16;
+const LVT_PERIODIC: u32 = 1 << 17;
+const DIVIDE_BY_16: u32 = 0x3;
+//PIT ticks the APIC timer is measured over, about 165ms
+const CALIBRATION_TICKS: u64 = 3;
+//Upper bound on the spins waiting for a PIT tick, in case the ticks stop anyway
+const TICK_WAIT_SPINS: usize = 500_000_000;
+
+static MAPPED: AtomicBool = AtomicBool::new(false);
+
+/// Whether the CPU has a local APIC, according to CPUID.
+pub fn is_available() -> bool {
+    crate::cpu::features().apic
+}
+
+//maps the APIC register page uncached, once
+fn map() -> bool {
+    if MAPPED.load(Ordering::Relaxed) {
+        return true;
+    }
+    let base = unsafe { msr::read(msr::IA32_APIC_BASE) } & 0xFFFF_F000;
+    let frame = PhysFrame::<Size4KiB>::containing_address(PhysAddr::new(base));
+    let page = Page::containing_address(VirtAddr::new(APIC_VIRT));
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_CACHE;
+    let mapped = memory::with_memory(|memory| unsafe {
+        match memory
+            .mapper
+            .map_to(page, frame, flags, &mut memory.frame_allocator)
+        {
+            Ok(flush) => {
+                flush.flush();
+                true
+            }
+            Err(MapToError::PageAlreadyMapped(_)) => true,
+            Err(_) => false,
+        }
+    })
+    .unwrap_or(false);
+    MAPPED.store(mapped, Ordering::Relaxed);
+    mapped
+}
+
+unsafe fn read(register: usize) -> u32 {
+    ((APIC_VIRT as usize + register) as *const u32).read_volatile()
+}
+
+unsafe fn write(register: usize, value: u32) {
+    ((APIC_VIRT as usize + register) as *mut u32).write_volatile(value)
+}
+
+//spins until `ticks` reaches `target`, false if it never does
+fn wait_for_tick(target: u64) -> bool {
+    for _ in 0..TICK_WAIT_SPINS {
+        if ticks() >= target {
+            return true;
+        }
+        core::hint::spin_loop();
+    }
+    false
+}
+
+/// Measures how many APIC timer ticks (divided by 16) pass per millisecond, using the PIT as reference.
+///Needs interrupts enabled and the PIT still driving `ticks`. `None` if there's no usable APIC,
+///or nothing to measure against
+pub fn calibrate() -> Option<u32> {
+    //Without the PIT interrupt ticks never move. Once start has run IRQ 0 is masked
+    if !x86_64::instructions::interrupts::are_enabled() || timer_source() == TimerSource::Apic {
+        return None;
+    }
+    if !is_available() || !map() {
+        return None;
+    }
+    unsafe {
+        write(
+            REG_SPURIOUS,
+            APIC_ENABLE | InterruptIndex::ApicSpurious as u32,
+        );
+        write(REG_DIVIDE, DIVIDE_BY_16);
+        write(REG_LVT_TIMER, LVT_MASKED);
+    }
+
+    //start right on a tick edge so the whole window is measured
+    if !wait_for_tick(ticks() + 1) {
+        return None;
+    }
+    let start = ticks();
+    unsafe { write(REG_INITIAL_COUNT, u32::MAX) };
+    let measured = wait_for_tick(start + CALIBRATION_TICKS);
+    let elapsed = u32::MAX - unsafe { read(REG_CURRENT_COUNT) };
+    unsafe { write(REG_INITIAL_COUNT, 0) };
+    if !measured {
+        return None;
+    }
+
+    let per_ms = elapsed as u64 * PIT_FREQUENCY / (CALIBRATION_TICKS * PIT_DIVISOR * 1000);
+    Some(per_ms as u32)
+}
+
+/// Moves the tick interrupt from the PIT to the APIC timer, keeping the same tick length.
+///Returns false and leaves the PIT running if the APIC can't be used
+pub fn start() -> bool {
+    let per_ms = match calibrate() {
+        Some(per_ms) if per_ms > 0 => per_ms,
+        _ => return false,
+    };
+    //One APIC period per PIT period, so ticks and uptime_ms mean the same thing on either timer
+    let initial_count = per_ms as u64 * PIT_DIVISOR * 1000 / PIT_FREQUENCY;
+
+    x86_64::instructions::interrupts::without_interrupts(|| unsafe {
+        write(REG_DIVIDE, DIVIDE_BY_16);
+        write(
+            REG_LVT_TIMER,
+            LVT_PERIODIC | InterruptIndex::ApicTimer as u32,
+        );
+        write(REG_INITIAL_COUNT, initial_count as u32);
+
+        //mask IRQ 0 so the PIT stops ticking alongside
+        let mut pics = PICS.lock();
+        let [mask1, mask2] = pics.read_masks();
+        pics.write_masks(mask1 | 1, mask2);
+
+        TIMER_SOURCE.store(TimerSource::Apic as u8, Ordering::Relaxed);
+    });
+    true
+}
+
+/// Signals the end of an APIC interrupt - the PIC's EOI doesn't reach the local APIC.
+pub fn end_of_interrupt() {
+    unsafe { write(REG_EOI, 0) };
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::serial_println;
+
+    #[test_case]
+    fn calibration() {
+        if !is_available() {
+            serial_println!("no local APIC, skipped");
+            return;
+        }
+        let per_ms = calibrate().expect("APIC calibration failed");
+        serial_println!("APIC timer: {} ticks per ms", per_ms);
+        assert!(per_ms > 0);
+
+        //Nothing to measure against with the PIT interrupt off, so no waiting forever either
+        let disabled = x86_64::instructions::interrupts::without_interrupts(calibrate);
+        assert_eq!(disabled, None);
+    }
+}
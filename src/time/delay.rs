@@ -0,0 +1,143 @@
+use super::ticks;
+use core::{
+    future::Future,
+    pin::Pin,
+    sync::atomic::{AtomicU64, Ordering},
+    task::{Context, Poll, Waker},
+};
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+
+//Fixed number of slots b.c. the timer interrupt handler walks this registry and must not allocate
+const MAX_SLEEPERS: usize = 64;
+
+struct Sleeper {
+    owner: u64,
+    deadline: u64,
+    waker: Waker,
+}
+
+const NO_SLEEPER: Option<Sleeper> = None;
+static SLEEPERS: Mutex<[Option<Sleeper>; MAX_SLEEPERS]> = Mutex::new([NO_SLEEPER; MAX_SLEEPERS]);
+
+/// A future that completes once the given number of timer ticks have elapsed.
+pub struct Delay {
+    id: u64,
+    deadline: u64,
+}
+
+impl Delay {
+    pub fn new(ticks: u64) -> Self {
+        //Make sure each ID is unique so a Delay only ever touches its own slot
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+        Self {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            deadline: super::ticks() + ticks,
+        }
+    }
+}
+
+impl Future for Delay {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        //Interrupts stay disabled so a tick can't fire between the deadline check and registering the waker
+        without_interrupts(|| {
+            if ticks() >= self.deadline {
+                return Poll::Ready(());
+            }
+
+            let mut sleepers = SLEEPERS.lock();
+            let slot = match sleepers
+                .iter()
+                .position(|slot| matches!(slot, Some(sleeper) if sleeper.owner == self.id))
+            {
+                Some(index) => Some(index),
+                None => sleepers.iter().position(|slot| slot.is_none()),
+            };
+
+            match slot {
+                Some(index) => {
+                    sleepers[index] = Some(Sleeper {
+                        owner: self.id,
+                        deadline: self.deadline,
+                        waker: cx.waker().clone(),
+                    });
+                }
+                //Registry full - fall back to being re-polled until the deadline passes
+                None => cx.waker().wake_by_ref(),
+            }
+            Poll::Pending
+        })
+    }
+}
+
+impl Drop for Delay {
+    fn drop(&mut self) {
+        //Clearing the slot here means the interrupt handler never holds the last reference to a waker
+        without_interrupts(|| {
+            for slot in SLEEPERS.lock().iter_mut() {
+                if matches!(slot, Some(sleeper) if sleeper.owner == self.id) {
+                    *slot = None;
+                }
+            }
+        });
+    }
+}
+
+/// Called from the timer interrupt handler to wake every Delay whose deadline has passed.
+pub(super) fn wake_sleepers(now: u64) {
+    //try_lock b.c. the lock is only ever held with interrupts disabled, so failing here means something is badly wrong
+    if let Some(mut sleepers) = SLEEPERS.try_lock() {
+        for slot in sleepers.iter_mut() {
+            if matches!(slot, Some(sleeper) if sleeper.deadline <= now) {
+                if let Some(sleeper) = slot.take() {
+                    sleeper.waker.wake();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Delay;
+    use crate::executor::{Executor, Task};
+    use crate::time::ticks;
+    use alloc::sync::Arc;
+    use core::sync::atomic::{AtomicBool, Ordering};
+
+    #[test_case]
+    fn zero_delay_is_immediately_ready() {
+        let done = Arc::new(AtomicBool::new(false));
+        let flag = done.clone();
+        let mut executor = Executor::new();
+        executor.spawn(Task::new(async move {
+            Delay::new(0).await;
+            flag.store(true, Ordering::Relaxed);
+        }));
+        executor.test_run();
+        assert!(done.load(Ordering::Relaxed));
+    }
+
+    #[test_case]
+    fn delay_waits_for_ticks() {
+        let done = Arc::new(AtomicBool::new(false));
+        let flag = done.clone();
+        let start = ticks();
+        let mut executor = Executor::new();
+        executor.spawn(Task::new(async move {
+            Delay::new(2).await;
+            flag.store(true, Ordering::Relaxed);
+        }));
+        executor.test_run();
+        assert!(!done.load(Ordering::Relaxed));
+
+        //The timer interrupt wakes the task once the deadline passes
+        while !done.load(Ordering::Relaxed) {
+            x86_64::instructions::hlt();
+            executor.test_run();
+        }
+        assert!(ticks() - start >= 2);
+    }
+}
@@ -0,0 +1,160 @@
+use core::{
+    pin::Pin,
+    sync::atomic::{AtomicU64, AtomicU8, Ordering},
+    task::{Context, Poll},
+};
+use futures_util::stream::{Stream, StreamExt};
+use futures_util::task::AtomicWaker;
+use lazy_static::lazy_static;
+use spin::Mutex;
+
+pub mod apic;
+mod delay;
+pub mod rtc;
+mod timeout;
+pub mod tsc;
+
+pub use delay::Delay;
+pub use timeout::{run_expired_timeouts, run_timeouts, set_timeout, TimeoutHandle};
+
+lazy_static! {
+    static ref TICKS: Mutex<TickCount> = Mutex::new(TickCount::new());
+}
+static WAKER: AtomicWaker = AtomicWaker::new();
+/// Input clock of the PIT in Hz.
+pub const PIT_FREQUENCY: u64 = 1_193_182;
+//The PIT is left at the BIOS default divisor, giving roughly 18.2 ticks per second
+const PIT_DIVISOR: u64 = 65536;
+
+/// Which device drives `tick`.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimerSource {
+    Pit,
+    Apic,
+}
+
+static TIMER_SOURCE: AtomicU8 = AtomicU8::new(TimerSource::Pit as u8);
+
+pub fn timer_source() -> TimerSource {
+    match TIMER_SOURCE.load(Ordering::Relaxed) {
+        0 => TimerSource::Pit,
+        _ => TimerSource::Apic,
+    }
+}
+//Monotonic count of timer interrupts since boot
+static TICK_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+pub struct TickCount {
+    ticks: usize,
+    new_tick: bool,
+}
+
+impl TickCount {
+    pub fn new() -> Self {
+        Self {
+            ticks: 0,
+            new_tick: false,
+        }
+    }
+
+    pub fn increment(&mut self) {
+        self.ticks += 1;
+        self.new_tick = true;
+        WAKER.wake();
+    }
+
+    pub fn handle(&mut self) -> Option<usize> {
+        if !self.new_tick {
+            return None;
+        }
+        self.new_tick = false;
+        Some(self.ticks)
+    }
+}
+
+pub struct TickStream {
+    _private: (), //field to prevent construction of the struct from outside of the module
+}
+
+impl TickStream {
+    pub fn new() -> Self {
+        Self { _private: () }
+    }
+}
+
+impl Stream for TickStream {
+    type Item = usize;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<usize>> {
+        if let Some(i) = TICKS.lock().handle() {
+            Poll::Ready(Some(i))
+        } else {
+            WAKER.register(cx.waker());
+            Poll::Pending
+        }
+    }
+}
+
+/// Number of timer interrupts since boot.
+pub fn ticks() -> u64 {
+    TICK_COUNTER.load(Ordering::Relaxed)
+}
+
+/// Milliseconds since the timer started ticking, derived from the PIT frequency.
+///The APIC timer is programmed to the same period, so this holds for either source
+pub fn uptime_ms() -> u64 {
+    //u128 keeps the multiplication from overflowing after long uptimes
+    (ticks() as u128 * PIT_DIVISOR as u128 * 1000 / PIT_FREQUENCY as u128) as u64
+}
+
+/// The whole number of ticks closest to `ms` milliseconds.
+pub fn ms_to_ticks(ms: u64) -> u64 {
+    let tick_ns = PIT_DIVISOR as u128 * 1_000_000_000 / PIT_FREQUENCY as u128;
+    ((ms as u128 * 1_000_000 + tick_ns / 2) / tick_ns) as u64
+}
+
+/// Called by the timer interrupt handler - must not block or allocate.
+pub fn tick() {
+    let now = TICK_COUNTER.fetch_add(1, Ordering::Relaxed) + 1;
+    TICKS.lock().increment();
+    delay::wake_sleepers(now);
+    crate::executor::preemption_tick(now);
+    crate::testing::watchdog_tick(now);
+}
+
+pub async fn sleep(ticks: usize) {
+    let mut tick_stream = TickStream::new();
+    for _ in 0..ticks {
+        tick_stream.next().await;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test_case]
+    fn ticks_increase() {
+        let start = ticks();
+        let start_ms = uptime_ms();
+        //A tick is about 55ms, this bounds the wait if the timer isn't running at all
+        for _ in 0..100_000_000u64 {
+            if ticks() > start {
+                break;
+            }
+            core::hint::spin_loop();
+        }
+        assert!(ticks() > start);
+        assert!(uptime_ms() >= start_ms);
+    }
+
+    #[test_case]
+    fn ms_round_to_ticks() {
+        //A tick is 54.9ms
+        assert_eq!(ms_to_ticks(0), 0);
+        assert_eq!(ms_to_ticks(27), 0);
+        assert_eq!(ms_to_ticks(28), 1);
+        assert_eq!(ms_to_ticks(1000), 18);
+    }
+}
@@ -0,0 +1,169 @@
+use x86_64::instructions::interrupts;
+use x86_64::instructions::port::Port;
+
+const CMOS_ADDRESS: u16 = 0x70;
+const CMOS_DATA: u16 = 0x71;
+//Set on every register select so an NMI can't fire between the two port accesses
+const NMI_DISABLE: u8 = 0x80;
+
+const REG_SECONDS: u8 = 0x00;
+const REG_MINUTES: u8 = 0x02;
+const REG_HOURS: u8 = 0x04;
+const REG_DAY: u8 = 0x07;
+const REG_MONTH: u8 = 0x08;
+const REG_YEAR: u8 = 0x09;
+const REG_STATUS_A: u8 = 0x0A;
+const REG_STATUS_B: u8 = 0x0B;
+
+const UPDATE_IN_PROGRESS: u8 = 0x80; //status A
+const HOUR_24: u8 = 0x02; //status B
+const BINARY_MODE: u8 = 0x04; //status B
+const HOUR_PM: u8 = 0x80;
+//An update takes about 2ms, each status read is a port access of around a microsecond
+const UPDATE_SPINS: usize = 100_000;
+//Reads of the whole time that may disagree before giving up
+const READ_ATTEMPTS: usize = 8;
+
+/// Wall clock time as kept by the RTC, which is normally UTC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DateTime {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hours: u8,
+    pub minutes: u8,
+    pub seconds: u8,
+}
+
+fn read_register(register: u8) -> u8 {
+    let mut address = Port::<u8>::new(CMOS_ADDRESS);
+    let mut data = Port::<u8>::new(CMOS_DATA);
+    unsafe {
+        address.write(NMI_DISABLE | register);
+        let value = data.read();
+        //Bit 7 of the address port stays set until written again, so turn NMIs back on
+        address.write(register);
+        value
+    }
+}
+
+fn update_in_progress() -> bool {
+    read_register(REG_STATUS_A) & UPDATE_IN_PROGRESS != 0
+}
+
+//raw register values in the order seconds, minutes, hours, day, month, year.
+//None if the RTC stays mid update
+fn read_raw() -> Option<[u8; 6]> {
+    if !(0..UPDATE_SPINS).any(|_| !update_in_progress()) {
+        return None;
+    }
+    Some([
+        read_register(REG_SECONDS),
+        read_register(REG_MINUTES),
+        read_register(REG_HOURS),
+        read_register(REG_DAY),
+        read_register(REG_MONTH),
+        read_register(REG_YEAR),
+    ])
+}
+
+/// Converts a packed BCD byte (e.g. 0x59) to its value (59).
+pub fn bcd_to_binary(bcd: u8) -> u8 {
+    (bcd >> 4) * 10 + (bcd & 0x0F)
+}
+
+//decodes raw register values according to the format bits in status B
+fn decode(raw: [u8; 6], status_b: u8) -> DateTime {
+    let [seconds, minutes, hours, day, month, year] = raw;
+    let convert = |value: u8| {
+        if status_b & BINARY_MODE != 0 {
+            value
+        } else {
+            bcd_to_binary(value)
+        }
+    };
+
+    //The PM flag sits in the top bit of the hour in either mode, so it's stripped before converting
+    let mut hours_24 = convert(hours & !HOUR_PM);
+    if status_b & HOUR_24 == 0 {
+        //12 hour clock: 12 AM is midnight and 12 PM is noon
+        hours_24 %= 12;
+        if hours & HOUR_PM != 0 {
+            hours_24 += 12;
+        }
+    }
+
+    DateTime {
+        year: 2000 + convert(year) as u16,
+        month: convert(month),
+        day: convert(day),
+        hours: hours_24,
+        minutes: convert(minutes),
+        seconds: convert(seconds),
+    }
+}
+
+/// Reads the current date and time from the CMOS RTC.
+/// `None` if it never settles, say because the update flag is stuck.
+pub fn read() -> Option<DateTime> {
+    interrupts::without_interrupts(|| {
+        //An update can still start between the two reads, so read until two in a row agree
+        let mut raw = read_raw()?;
+        for _ in 0..READ_ATTEMPTS {
+            let again = read_raw()?;
+            if again == raw {
+                return Some(decode(raw, read_register(REG_STATUS_B)));
+            }
+            raw = again;
+        }
+        None
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test_case]
+    fn bcd_decoding() {
+        assert_eq!(bcd_to_binary(0x00), 0);
+        assert_eq!(bcd_to_binary(0x09), 9);
+        assert_eq!(bcd_to_binary(0x10), 10);
+        assert_eq!(bcd_to_binary(0x59), 59);
+        assert_eq!(bcd_to_binary(0x99), 99);
+    }
+
+    #[test_case]
+    fn decode_bcd_12_hour() {
+        //2023-12-31 11:45:30 PM
+        let time = decode([0x30, 0x45, HOUR_PM | 0x11, 0x31, 0x12, 0x23], 0);
+        assert_eq!(
+            time,
+            DateTime {
+                year: 2023,
+                month: 12,
+                day: 31,
+                hours: 23,
+                minutes: 45,
+                seconds: 30,
+            }
+        );
+        //12 AM is midnight
+        assert_eq!(decode([0, 0, 0x12, 1, 1, 0], 0).hours, 0);
+    }
+
+    #[test_case]
+    fn decode_binary_24_hour() {
+        let time = decode([30, 45, 23, 31, 12, 23], BINARY_MODE | HOUR_24);
+        assert_eq!(time.hours, 23);
+        assert_eq!(time.year, 2023);
+    }
+
+    #[test_case]
+    fn read_is_plausible() {
+        let time = read().expect("RTC never settled");
+        assert!((1..=12).contains(&time.month));
+        assert!((1..=31).contains(&time.day));
+        assert!(time.hours < 24 && time.minutes < 60 && time.seconds < 60);
+    }
+}
@@ -0,0 +1,154 @@
+use super::{ticks, Delay};
+use alloc::{boxed::Box, vec::Vec};
+use core::sync::atomic::{AtomicU64, Ordering};
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+
+//Slots in the hashed wheel - a timeout lands in slot deadline % WHEEL_SLOTS, whatever its round
+const WHEEL_SLOTS: usize = 64;
+
+type Callback = Box<dyn FnOnce() + Send>;
+
+struct Timeout {
+    id: u64,
+    deadline: u64,
+    callback: Callback,
+}
+
+struct Wheel {
+    slots: [Vec<Timeout>; WHEEL_SLOTS],
+    processed: u64, //last tick whose slot has been expired
+}
+
+const EMPTY_SLOT: Vec<Timeout> = Vec::new();
+//Only touched outside interrupt context - the timer interrupt merely advances the tick counter
+static WHEEL: Mutex<Wheel> = Mutex::new(Wheel {
+    slots: [EMPTY_SLOT; WHEEL_SLOTS],
+    processed: 0,
+});
+
+/// Returned by `set_timeout`, cancels the callback if it hasn't run yet.
+#[derive(Debug)]
+pub struct TimeoutHandle {
+    id: u64,
+    deadline: u64,
+}
+
+impl TimeoutHandle {
+    /// Returns false if the callback already ran (or is running).
+    pub fn cancel(self) -> bool {
+        without_interrupts(|| {
+            let mut wheel = WHEEL.lock();
+            let slot = &mut wheel.slots[self.deadline as usize % WHEEL_SLOTS];
+            match slot.iter().position(|timeout| timeout.id == self.id) {
+                Some(index) => {
+                    drop(slot.swap_remove(index));
+                    true
+                }
+                None => false,
+            }
+        })
+    }
+}
+
+/// Runs `callback` once `ticks` timer ticks have passed. A delay of 0 fires on the next tick.
+///Callbacks are run by `run_expired_timeouts`, normally from the `run_timeouts` task
+pub fn set_timeout(ticks: u64, callback: impl FnOnce() + Send + 'static) -> TimeoutHandle {
+    static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    let deadline = super::ticks() + ticks.max(1);
+    let timeout = Timeout {
+        id,
+        deadline,
+        callback: Box::new(callback),
+    };
+    without_interrupts(|| WHEEL.lock().slots[deadline as usize % WHEEL_SLOTS].push(timeout));
+    TimeoutHandle { id, deadline }
+}
+
+/// Runs every callback that is due, in deadline order, and returns how many ran.
+pub fn run_expired_timeouts() -> usize {
+    let now = ticks();
+    let mut due = without_interrupts(|| {
+        let mut wheel = WHEEL.lock();
+        let mut due = Vec::new();
+        //Past a full turn every slot has been visited, so lagging behind never costs more than that
+        let steps = (now - wheel.processed).min(WHEEL_SLOTS as u64);
+        for tick in now + 1 - steps..=now {
+            let slot = &mut wheel.slots[tick as usize % WHEEL_SLOTS];
+            let mut i = 0;
+            while i < slot.len() {
+                if slot[i].deadline <= now {
+                    due.push(slot.swap_remove(i));
+                } else {
+                    i += 1;
+                }
+            }
+        }
+        wheel.processed = now;
+        due
+    });
+
+    //Callbacks run without the lock held so they can set further timeouts
+    due.sort_unstable_by_key(|timeout| (timeout.deadline, timeout.id));
+    let count = due.len();
+    for timeout in due {
+        (timeout.callback)();
+    }
+    count
+}
+
+/// Task that runs due timeout callbacks every tick.
+pub async fn run_timeouts() {
+    loop {
+        run_expired_timeouts();
+        Delay::new(1).await;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use alloc::sync::Arc;
+
+    fn wait_ticks(count: u64) {
+        let target = ticks() + count;
+        while ticks() < target {
+            x86_64::instructions::hlt();
+        }
+    }
+
+    #[test_case]
+    fn firing_order() {
+        let fired = Arc::new(Mutex::new(Vec::new()));
+        for (delay, label) in [(3, 'c'), (1, 'a'), (2, 'b')] {
+            let fired = fired.clone();
+            set_timeout(delay, move || fired.lock().push(label));
+        }
+        wait_ticks(4);
+        assert_eq!(run_expired_timeouts(), 3);
+        assert_eq!(*fired.lock(), ['a', 'b', 'c']);
+    }
+
+    #[test_case]
+    fn zero_timeout_fires_next_tick() {
+        let fired = Arc::new(Mutex::new(false));
+        let flag = fired.clone();
+        set_timeout(0, move || *flag.lock() = true);
+        run_expired_timeouts();
+        wait_ticks(1);
+        run_expired_timeouts();
+        assert!(*fired.lock());
+    }
+
+    #[test_case]
+    fn cancel_pending() {
+        let fired = Arc::new(Mutex::new(false));
+        let flag = fired.clone();
+        let handle = set_timeout(1, move || *flag.lock() = true);
+        assert!(handle.cancel());
+        wait_ticks(2);
+        run_expired_timeouts();
+        assert!(!*fired.lock());
+    }
+}
@@ -0,0 +1,127 @@
+//! The time-stamp counter, for timing much finer than a timer tick.
+//!
+//! `calibrate` measures how fast the TSC runs against the PIT. That's only done when CPUID reports an
+//! invariant TSC, since otherwise its rate changes with the CPU's power state and the measurement
+//! goes stale. Without it `uptime_ns` falls back to the tick clock.
+use super::{ticks, uptime_ms, PIT_DIVISOR, PIT_FREQUENCY};
+use core::arch::x86_64::{_mm_lfence, _rdtsc};
+use core::sync::atomic::{AtomicU64, Ordering};
+
+//Ticks of the timer to measure the TSC rate over
+const CALIBRATION_TICKS: u64 = 2;
+
+//TSC cycles per second, 0 until calibrated
+static FREQUENCY: AtomicU64 = AtomicU64::new(0);
+//The TSC and the tick clock at the end of calibration, so uptime_ns counts from the same point
+static BASE_TSC: AtomicU64 = AtomicU64::new(0);
+static BASE_NS: AtomicU64 = AtomicU64::new(0);
+
+/// Reads the time-stamp counter. The LFENCE keeps it from being read before earlier instructions
+/// have finished, so the instructions being timed aren't partly left out.
+pub fn now() -> u64 {
+    unsafe {
+        _mm_lfence();
+        _rdtsc()
+    }
+}
+
+//Counts TSC cycles over a few timer ticks, which need interrupts to be enabled.
+//Returns the cycles per second and the TSC at the end of the measurement
+fn measure() -> Option<(u64, u64)> {
+    if !x86_64::instructions::interrupts::are_enabled() {
+        return None;
+    }
+    //Starting right on a tick, so the measurement covers whole ticks
+    let first = ticks() + 1;
+    while ticks() < first {
+        core::hint::spin_loop();
+    }
+    let start = now();
+    while ticks() < first + CALIBRATION_TICKS {
+        core::hint::spin_loop();
+    }
+    let end = now();
+    let cycles = (end - start) as u128 * PIT_FREQUENCY as u128;
+    let frequency = cycles / (CALIBRATION_TICKS * PIT_DIVISOR) as u128;
+    Some((frequency as u64, end))
+}
+
+/// Measures the TSC rate without checking that it's invariant, in cycles per second.
+/// Good enough for timing something over a short stretch, like a benchmark.
+pub fn measure_frequency() -> Option<u64> {
+    measure().map(|(frequency, _)| frequency)
+}
+
+/// Measures the TSC rate against the PIT, called by `init`. Takes a few ticks, and does nothing
+/// if the TSC isn't invariant or interrupts are disabled. Returns the rate in cycles per second.
+pub fn calibrate() -> Option<u64> {
+    if !crate::cpu::features().invariant_tsc {
+        return None;
+    }
+    let (frequency, end) = measure()?;
+    BASE_TSC.store(end, Ordering::Relaxed);
+    BASE_NS.store(uptime_ms() * 1_000_000, Ordering::Relaxed);
+    FREQUENCY.store(frequency, Ordering::Release);
+    Some(frequency)
+}
+
+/// TSC cycles per second, None if `calibrate` didn't succeed.
+pub fn frequency() -> Option<u64> {
+    match FREQUENCY.load(Ordering::Acquire) {
+        0 => None,
+        frequency => Some(frequency),
+    }
+}
+
+/// How many nanoseconds `delta` TSC cycles take, None if the TSC isn't calibrated.
+pub fn tsc_to_ns(delta: u64) -> Option<u64> {
+    frequency().map(|frequency| (delta as u128 * 1_000_000_000 / frequency as u128) as u64)
+}
+
+/// Nanoseconds since the timer started ticking - measured with the TSC if it's calibrated,
+/// otherwise only as fine as the tick clock.
+pub fn uptime_ns() -> u64 {
+    let base = BASE_TSC.load(Ordering::Relaxed);
+    match tsc_to_ns(now().wrapping_sub(base)) {
+        Some(ns) => BASE_NS.load(Ordering::Relaxed) + ns,
+        None => uptime_ms() * 1_000_000,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test_case]
+    fn now_is_monotonic() {
+        let mut previous = now();
+        for _ in 0..1000 {
+            let current = now();
+            assert!(current > previous);
+            previous = current;
+        }
+    }
+
+    #[test_case]
+    fn uptime_ns_follows_ticks() {
+        let start = uptime_ns();
+        let first = ticks() + 1;
+        while ticks() < first {
+            core::hint::spin_loop();
+        }
+        assert!(uptime_ns() > start);
+        //Whether measured with the TSC or not, it agrees with the tick clock to within a tick
+        let tick_ns = PIT_DIVISOR * 1_000_000_000 / PIT_FREQUENCY;
+        assert!(uptime_ns().abs_diff(uptime_ms() * 1_000_000) < 2 * tick_ns);
+    }
+
+    #[test_case]
+    fn measured_frequency() {
+        let measured = measure_frequency().unwrap();
+        assert!(measured > 0);
+        assert_eq!(tsc_to_ns(measured).is_some(), frequency().is_some());
+        if let Some(calibrated) = frequency() {
+            assert_eq!(tsc_to_ns(calibrated), Some(1_000_000_000));
+        }
+    }
+}
@@ -0,0 +1,42 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(finn_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use finn_os::time::{self, apic, TimerSource};
+use finn_os::{serial_println, InitOptions};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    finn_os::init_with(boot_info, InitOptions { apic_timer: true });
+
+    test_main();
+    finn_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    finn_os::test_panic_handler(info)
+}
+
+#[test_case]
+fn apic_drives_ticks() {
+    if !apic::is_available() {
+        //init falls back to the PIT
+        assert_eq!(time::timer_source(), TimerSource::Pit);
+        serial_println!("no local APIC, skipped");
+        return;
+    }
+    assert_eq!(time::timer_source(), TimerSource::Apic);
+    //With IRQ 0 masked only the APIC timer can move the tick count
+    let start = time::ticks();
+    while time::ticks() < start + 3 {
+        x86_64::instructions::hlt();
+    }
+    //Already running on the APIC, there's nothing left to calibrate against
+    assert_eq!(apic::calibrate(), None);
+}
@@ -0,0 +1,59 @@
+#![no_std]
+#![no_main]
+
+use bootloader::{entry_point, BootInfo};
+use core::fmt::{self, Write};
+use core::panic::PanicInfo;
+use finn_os::{exit_qemu, serial_print, serial_println, QemuExitCode};
+
+entry_point!(main);
+fn main(boot_info: &'static BootInfo) -> ! {
+    serial_print!("guard_page::overflow_reported...\t");
+    finn_os::init(boot_info);
+
+    stack_overflow();
+
+    serial_println!("[execution continued after stack overflow]");
+    exit_qemu(QemuExitCode::Failed);
+    finn_os::hlt_loop();
+}
+
+#[allow(unconditional_recursion)]
+fn stack_overflow() {
+    stack_overflow();
+    volatile::Volatile::new(0).read(); // prevent tail recursion optimizations
+}
+
+//Keeps the start of the panic message, there's no heap to format into once the stack is gone
+struct Prefix {
+    buf: [u8; 64],
+    len: usize,
+}
+
+impl Write for Prefix {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for &byte in s.as_bytes() {
+            if self.len < self.buf.len() {
+                self.buf[self.len] = byte;
+                self.len += 1;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    let mut prefix = Prefix {
+        buf: [0; 64],
+        len: 0,
+    };
+    let _ = write!(prefix, "{}", info.message());
+    //The page fault handler has to have recognised the guard page, not just any fault
+    if prefix.buf[..prefix.len].starts_with(b"EXCEPTION: STACK OVERFLOW") {
+        serial_println!("[ok]");
+        exit_qemu(QemuExitCode::Success);
+        finn_os::hlt_loop();
+    }
+    finn_os::test_panic_handler(info)
+}
@@ -6,10 +6,20 @@
 
 extern crate alloc;
 
-use alloc::{boxed::Box, vec};
+use alloc::{boxed::Box, vec, vec::Vec};
 use bootloader::{entry_point, BootInfo};
-use core::panic::PanicInfo;
-use finn_os::allocator::HEAP_SIZE;
+use core::{
+    alloc::{GlobalAlloc, Layout},
+    arch::x86_64::_rdtsc,
+    hint::black_box,
+    panic::PanicInfo,
+};
+use finn_os::allocator::{
+    self, fixed_size_block::FixedSizeBlockAllocator, linked_list::LinkedListAllocator, HeapKind,
+    Locked, HEAP_SIZE,
+};
+use finn_os::bench::Bench;
+use finn_os::serial_println;
 
 entry_point!(main);
 
@@ -50,3 +60,115 @@ fn many_boxes() {
         assert_eq!(*x, i);
     }
 }
+
+#[test_case]
+fn stats_return_to_baseline() {
+    let baseline = allocator::stats();
+    let value = Box::new([0u8; 64]);
+    let during = allocator::stats();
+    assert_eq!(during.in_use, baseline.in_use + 64);
+    assert_eq!(during.live_allocations, baseline.live_allocations + 1);
+    assert!(during.peak >= during.in_use);
+    drop(value);
+    let after = allocator::stats();
+    assert_eq!(after.in_use, baseline.in_use);
+    assert_eq!(after.live_allocations, baseline.live_allocations);
+    assert_eq!(after.total_allocated, baseline.total_allocated + 64);
+}
+
+#[test_case]
+fn heap_kind_selected() {
+    assert_eq!(allocator::heap_kind(), Some(HeapKind::default()));
+}
+
+const BENCH_REGION: usize = 64 * 1024;
+const BENCH_BOXES: usize = 512;
+
+//Allocates BENCH_BOXES small blocks, frees them and repeats, returning the elapsed cycles
+fn bench_small_boxes(allocator: &impl GlobalAlloc) -> u64 {
+    let layout = Layout::new::<[u64; 2]>();
+    let mut live = Vec::with_capacity(BENCH_BOXES);
+    let start = unsafe { _rdtsc() };
+    for _ in 0..4 {
+        for _ in 0..BENCH_BOXES {
+            let ptr = unsafe { allocator.alloc(layout) };
+            assert!(!ptr.is_null());
+            live.push(ptr);
+        }
+        for ptr in live.drain(..) {
+            unsafe { allocator.dealloc(ptr, layout) };
+        }
+    }
+    unsafe { _rdtsc() - start }
+}
+
+#[test_case]
+fn small_box_throughput() {
+    //Both allocators get their own region carved out of the kernel heap
+    let mut region = vec![0u64; BENCH_REGION / 8];
+    let start = region.as_mut_ptr() as usize;
+
+    let linked_list = Locked::new(LinkedListAllocator::new());
+    unsafe { linked_list.lock().init(start, BENCH_REGION) };
+    let linked_list_cycles = bench_small_boxes(&linked_list);
+
+    //The region is reused - the linked list allocator is done with it
+    let fixed_size_block = Locked::new(FixedSizeBlockAllocator::new());
+    unsafe { fixed_size_block.lock().init(start, BENCH_REGION) };
+    let fixed_size_block_cycles = bench_small_boxes(&fixed_size_block);
+
+    serial_println!(
+        "linked list: {} cycles, fixed size block: {} cycles",
+        linked_list_cycles,
+        fixed_size_block_cycles
+    );
+    drop(region);
+}
+
+#[test_case]
+fn heap_growth() {
+    allocator::enable_growth(HEAP_SIZE * 4);
+    let initial_size = allocator::heap_size();
+    let big = vec![1u8; HEAP_SIZE + HEAP_SIZE / 2];
+    assert!(allocator::heap_size() > initial_size);
+    assert_eq!(big.iter().map(|&b| b as usize).sum::<usize>(), big.len());
+}
+
+#[test_case]
+fn double_free_detected() {
+    allocator::enable_double_free_checks();
+    let detected = allocator::double_frees_detected();
+    let layout = Layout::new::<[u64; 8]>();
+    unsafe {
+        let ptr = alloc::alloc::alloc(layout);
+        //Data that happens to match the poison must not be mistaken for a freed block
+        ptr.write_bytes(0xDD, layout.size());
+        alloc::alloc::dealloc(ptr, layout);
+        assert_eq!(allocator::double_frees_detected(), detected);
+        alloc::alloc::dealloc(ptr, layout);
+    }
+    assert_eq!(allocator::double_frees_detected(), detected + 1);
+
+    //The heap must still be usable afterwards
+    let value = Box::new([7u64; 8]);
+    assert_eq!(value.iter().sum::<u64>(), 56);
+
+    //Off again, so the benches after this don't measure the poisoning
+    allocator::disable_double_free_checks();
+}
+
+fn box_allocation(iterations: u64) {
+    for i in 0..iterations {
+        black_box(Box::new(i));
+    }
+}
+
+#[test_case]
+static BOX_ALLOCATION: Bench = Bench::new("box_allocation", 10_000, box_allocation);
+
+#[test_case]
+fn bench_reports_duration() {
+    let result = Bench::new("box_allocation", 1_000, box_allocation).measure();
+    assert!(result.cycles_per_iter > 0);
+    assert!(result.ns_per_iter.is_some());
+}
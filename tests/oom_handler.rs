@@ -0,0 +1,39 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use bootloader::{entry_point, BootInfo};
+use core::{alloc::Layout, panic::PanicInfo};
+use finn_os::allocator::{self, HEAP_SIZE};
+use finn_os::{exit_qemu, serial_print, serial_println, QemuExitCode};
+
+entry_point!(main);
+fn main(boot_info: &'static BootInfo) -> ! {
+    serial_print!("oom_handler::handler_fires...\t");
+    finn_os::init(boot_info);
+    allocator::set_oom_handler(oom_handler);
+
+    //Growth is off, so this can never fit
+    let huge: Vec<u8> = Vec::with_capacity(HEAP_SIZE * 16);
+    serial_println!("[allocation of {} bytes succeeded]", huge.capacity());
+    exit_qemu(QemuExitCode::Failed);
+    finn_os::hlt_loop();
+}
+
+fn oom_handler(layout: Layout) {
+    if layout.size() == HEAP_SIZE * 16 {
+        serial_println!("[ok]");
+        exit_qemu(QemuExitCode::Success);
+    } else {
+        serial_println!("[failed]\n");
+        serial_println!("Error: unexpected layout {:?}\n", layout);
+        exit_qemu(QemuExitCode::Failed);
+    }
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    finn_os::test_panic_handler(info)
+}
@@ -0,0 +1,66 @@
+#![no_std]
+#![no_main]
+
+use bootloader::{entry_point, BootInfo};
+use core::fmt::{self, Write};
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicBool, Ordering};
+use finn_os::{exit_qemu, serial_print, serial_println, QemuExitCode};
+use x86_64::VirtAddr;
+
+//Set once the demand zero write went through, so a panic before it can't pass the test
+static DEMAND_WRITTEN: AtomicBool = AtomicBool::new(false);
+
+entry_point!(main);
+fn main(boot_info: &'static BootInfo) -> ! {
+    serial_print!("page_fault::unregistered_address_faults...\t");
+    finn_os::init(boot_info);
+
+    let demand = VirtAddr::new(0x_6666_0000_0000);
+    finn_os::memory::register_demand_region(demand, 4096);
+    unsafe { demand.as_mut_ptr::<u64>().write_volatile(1) };
+    DEMAND_WRITTEN.store(true, Ordering::SeqCst);
+
+    //Nothing is registered here, so this has to end up in the panic handler
+    unsafe { (0x_7777_0000_0000 as *mut u64).write_volatile(1) };
+
+    serial_println!("[write to an unmapped address succeeded]");
+    exit_qemu(QemuExitCode::Failed);
+    finn_os::hlt_loop();
+}
+
+//Keeps the start of the panic message without needing the heap
+struct Prefix {
+    buf: [u8; 64],
+    len: usize,
+}
+
+impl Write for Prefix {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for &byte in s.as_bytes() {
+            if self.len < self.buf.len() {
+                self.buf[self.len] = byte;
+                self.len += 1;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    let mut prefix = Prefix {
+        buf: [0; 64],
+        len: 0,
+    };
+    let _ = write!(prefix, "{}", info.message());
+    //Only the fault on the unregistered address counts, not one on the demand region or anything else
+    if DEMAND_WRITTEN.load(Ordering::SeqCst)
+        && prefix.buf[..prefix.len].starts_with(b"EXCEPTION: PAGE FAULT")
+    {
+        serial_println!("[ok]");
+        exit_qemu(QemuExitCode::Success);
+        finn_os::hlt_loop();
+    }
+    finn_os::test_panic_handler(info)
+}
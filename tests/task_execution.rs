@@ -4,10 +4,19 @@
 #![test_runner(finn_os::test_runner)]
 #![reexport_test_harness_main = "test_main"]
 
+extern crate alloc;
+
+use alloc::{sync::Arc, vec::Vec};
 use bootloader::{entry_point, BootInfo};
+use core::future::{pending, poll_fn};
 use core::panic::PanicInfo;
-use finn_os::executor::{Executor, Task};
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use core::task::{Poll, Waker};
+use finn_os::executor::{block_on, join2, select2, yield_now, Either, Executor, Priority, Task};
 use finn_os::serial_print;
+use finn_os::testing::ShouldPanic;
+use finn_os::time::Delay;
+use spin::Mutex;
 
 entry_point!(main);
 
@@ -23,6 +32,15 @@ fn panic(info: &PanicInfo) -> ! {
     finn_os::test_panic_handler(info)
 }
 
+//Sets the flag once dropped, to check that a future's destructor ran
+struct DropFlag(Arc<AtomicBool>);
+
+impl Drop for DropFlag {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
 #[test_case]
 fn test_executor() {
     async fn example_task() {
@@ -33,3 +51,512 @@ fn test_executor() {
     executor.spawn(Task::new(example_task()));
     executor.test_run()
 }
+
+#[test_case]
+fn test_join_handle() {
+    let mut executor = Executor::new();
+    let handle = executor.spawn_with_handle(async { 42 });
+    //Let the task finish and be removed before anything awaits the handle
+    executor.test_run();
+
+    let result = Arc::new(AtomicU64::new(0));
+    let slot = result.clone();
+    executor.spawn(Task::new(async move {
+        slot.store(handle.await, Ordering::Relaxed);
+    }));
+    executor.test_run();
+    assert_eq!(result.load(Ordering::Relaxed), 42);
+}
+
+#[test_case]
+fn test_cancel_task() {
+    let dropped = Arc::new(AtomicBool::new(false));
+    let flag = DropFlag(dropped.clone());
+
+    let mut executor = Executor::new();
+    let handle = executor.spawn_cancellable(Task::new(async move {
+        let _flag = flag;
+        pending::<()>().await;
+    }));
+    executor.test_run();
+    assert!(!dropped.load(Ordering::Relaxed));
+
+    handle.cancel();
+    executor.test_run();
+    assert!(dropped.load(Ordering::Relaxed));
+
+    //Cancelling a task that already completed is a no-op
+    let finished = executor.spawn_cancellable(Task::new(async {}));
+    executor.test_run();
+    finished.cancel();
+    executor.test_run();
+}
+
+#[test_case]
+fn test_many_cancels_kept() {
+    //More cancels at once than any of the fixed size queues hold - none may be dropped
+    let mut executor = Executor::new();
+    let mut tasks = Vec::new();
+    for _ in 0..250 {
+        let dropped = Arc::new(AtomicBool::new(false));
+        let flag = DropFlag(dropped.clone());
+        let handle = executor.spawn_cancellable(Task::new(async move {
+            let _flag = flag;
+            pending::<()>().await;
+        }));
+        tasks.push((dropped, handle));
+    }
+    executor.test_run();
+
+    for (_, handle) in &tasks {
+        handle.cancel();
+    }
+    executor.test_run();
+    assert!(tasks
+        .iter()
+        .all(|(dropped, _)| dropped.load(Ordering::Relaxed)));
+}
+
+#[test_case]
+fn test_priority_scheduling() {
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let mut executor = Executor::new();
+
+    //Spawned first, so FIFO order alone would run it first
+    let low_log = log.clone();
+    executor.spawn(Task::with_priority(
+        async move {
+            for _ in 0..5 {
+                low_log.lock().push("low");
+                yield_now().await;
+            }
+        },
+        Priority::Low,
+    ));
+
+    let high_log = log.clone();
+    executor.spawn(Task::with_priority(
+        async move {
+            for _ in 0..2 {
+                high_log.lock().push("high");
+                yield_now().await;
+            }
+        },
+        Priority::High,
+    ));
+
+    executor.test_run();
+    //Each pass polls the High task before the Low one, and the High task still finishes
+    assert_eq!(
+        *log.lock(),
+        ["high", "low", "high", "low", "low", "low", "low"]
+    );
+}
+
+#[test_case]
+fn test_yield_now() {
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let mut executor = Executor::new();
+
+    for name in ["a", "b"] {
+        let log = log.clone();
+        executor.spawn(Task::new(async move {
+            for _ in 0..3 {
+                serial_print!("{} ", name);
+                log.lock().push(name);
+                yield_now().await;
+            }
+        }));
+    }
+
+    executor.test_run();
+    assert_eq!(*log.lock(), ["a", "b", "a", "b", "a", "b"]);
+}
+
+#[test_case]
+fn test_try_spawn_full_queue() {
+    let mut executor = Executor::new();
+    //Fill the Normal queue without running anything
+    let mut spawned = 0;
+    while executor.try_spawn(Task::new(async {})).is_ok() {
+        spawned += 1;
+    }
+    assert_eq!(spawned, 100);
+
+    //The rejected task comes back and can be queued once there is room again
+    let err = executor.try_spawn(Task::new(async {})).unwrap_err();
+    executor.test_run();
+    assert!(executor.try_spawn(err.into_task()).is_ok());
+    executor.test_run();
+}
+
+#[test_case]
+fn test_spawn_overflow() {
+    let count = Arc::new(AtomicU64::new(0));
+    let mut executor = Executor::new();
+    //The fixed queue holds 100 tasks, so the 101st is queued on the overflow
+    for _ in 0..101 {
+        let count = count.clone();
+        executor.spawn(Task::new(async move {
+            count.fetch_add(1, Ordering::Relaxed);
+        }));
+    }
+    assert!(executor.try_spawn(Task::new(async {})).is_err());
+
+    executor.test_run();
+    assert_eq!(count.load(Ordering::Relaxed), 101);
+}
+
+#[test_case]
+fn test_isolated_task_failure() {
+    let done = Arc::new(AtomicBool::new(false));
+    let mut executor = Executor::new();
+
+    executor.spawn(Task::new_isolated(async { Err::<(), _>("bad task") }));
+    let flag = done.clone();
+    executor.spawn(Task::new_isolated(async move {
+        yield_now().await;
+        flag.store(true, Ordering::Relaxed);
+        Ok::<(), &str>(())
+    }));
+
+    //The failing task is logged and removed, the good one still finishes
+    executor.test_run();
+    assert!(done.load(Ordering::Relaxed));
+}
+
+#[test_case]
+fn test_task_names() {
+    assert_eq!(Task::new(async {}).name(), "<unnamed>");
+
+    let mut executor = Executor::new();
+    executor.spawn(Task::named("stuck", pending()));
+    executor.spawn(Task::named("finished", async {}));
+    executor.test_run();
+    //Only the pending task is left to show up here
+    executor.dump_tasks();
+}
+
+#[test_case]
+fn test_executor_stats() {
+    let mut executor = Executor::new();
+    //Polled once initially and once more after each yield
+    let task = Task::new(async {
+        for _ in 0..4 {
+            yield_now().await;
+        }
+    });
+    let task_id = task.id();
+    executor.spawn(task);
+    executor.test_run();
+
+    let stats = executor.stats();
+    assert_eq!(stats.polls(task_id), 5);
+    assert_eq!(stats.total_polls(), 5);
+    assert_eq!(stats.total_wakeups(), 4);
+
+    executor.reset_stats();
+    assert_eq!(executor.stats().polls(task_id), 0);
+    assert_eq!(executor.stats().total_wakeups(), 0);
+}
+
+#[test_case]
+fn test_finished_task_stats_bounded() {
+    let mut executor = Executor::new();
+    let tasks: Vec<Task> = (0..100).map(|_| Task::new(async {})).collect();
+    let (first, last) = (tasks[0].id(), tasks[99].id());
+    for task in tasks {
+        executor.spawn(task);
+    }
+    executor.test_run();
+    //Only the most recent finished tasks keep their count
+    assert_eq!(executor.stats().polls(first), 0);
+    assert_eq!(executor.stats().polls(last), 1);
+    assert_eq!(executor.stats().total_polls(), 100);
+}
+
+#[test_case]
+fn test_join() {
+    let result = Arc::new(Mutex::new(None));
+    let slot = result.clone();
+    let mut executor = Executor::new();
+    executor.spawn(Task::new(async move {
+        let counter = async {
+            let mut count = 0;
+            for _ in 0..3 {
+                count += 1;
+                yield_now().await;
+            }
+            count
+        };
+        *slot.lock() = Some(join2(Delay::new(2), counter).await);
+    }));
+
+    //The counter finishes first, the Delay needs timer interrupts
+    while result.lock().is_none() {
+        executor.test_run();
+        x86_64::instructions::hlt();
+    }
+    assert_eq!(*result.lock(), Some(((), 3)));
+}
+
+#[test_case]
+fn test_select() {
+    let dropped = Arc::new(AtomicBool::new(false));
+    let flag = DropFlag(dropped.clone());
+    let result = Arc::new(Mutex::new(None));
+    let slot = result.clone();
+
+    let mut executor = Executor::new();
+    executor.spawn(Task::new(async move {
+        let never = async move {
+            let _flag = flag;
+            pending::<()>().await;
+        };
+        *slot.lock() = Some(select2(never, async { 7 }).await);
+    }));
+    executor.test_run();
+
+    assert_eq!(*result.lock(), Some(Either::Right(7)));
+    //The losing branch was dropped
+    assert!(dropped.load(Ordering::Relaxed));
+}
+
+#[test_case]
+fn test_budget() {
+    let polls = Arc::new(AtomicU64::new(0));
+    let mut executor = Executor::with_budget(5);
+    //Tasks that re-wake themselves forever
+    for _ in 0..20 {
+        let polls = polls.clone();
+        executor.spawn(Task::new(async move {
+            loop {
+                polls.fetch_add(1, Ordering::Relaxed);
+                yield_now().await;
+            }
+        }));
+    }
+
+    //Each pass returns control after the budget, leaving the rest queued
+    assert_eq!(executor.run_ready_tasks(), 5);
+    assert_eq!(executor.run_ready_tasks(), 5);
+    assert_eq!(polls.load(Ordering::Relaxed), 10);
+}
+
+#[test_case]
+static ZERO_BUDGET: ShouldPanic = ShouldPanic::new("zero_budget", || {
+    let _ = Executor::with_budget(0);
+});
+
+#[test_case]
+fn test_wakeup_deduplication() {
+    let waker_slot = Arc::new(Mutex::new(None));
+    let slot = waker_slot.clone();
+    let mut executor = Executor::new();
+    let task = Task::new(async move {
+        //Hand out the waker on the first poll, finish on the second
+        poll_fn(|cx| {
+            let mut slot = slot.lock();
+            if slot.is_none() {
+                *slot = Some(cx.waker().clone());
+                Poll::Pending
+            } else {
+                Poll::Ready(())
+            }
+        })
+        .await
+    });
+    let task_id = task.id();
+    executor.spawn(task);
+    executor.test_run();
+
+    let waker: Waker = waker_slot.lock().clone().unwrap();
+    for _ in 0..200 {
+        waker.wake_by_ref();
+    }
+    //Only one queue entry, so the task is polled just once more
+    executor.test_run();
+    assert_eq!(executor.stats().polls(task_id), 2);
+    assert_eq!(executor.stats().total_wakeups(), 200);
+}
+
+#[test_case]
+fn test_block_on() {
+    assert_eq!(block_on(async { 5 }), 5);
+
+    let start = finn_os::time::ticks();
+    block_on(Delay::new(2));
+    assert!(finn_os::time::ticks() - start >= 2);
+}
+
+#[test_case]
+fn test_spawner() {
+    let flags = [
+        Arc::new(AtomicBool::new(false)),
+        Arc::new(AtomicBool::new(false)),
+    ];
+    let mut executor = Executor::new();
+    let spawner = executor.spawner();
+
+    let children = flags.clone();
+    executor.spawn(Task::new(async move {
+        for flag in children {
+            spawner.spawn(async move {
+                flag.store(true, Ordering::Relaxed);
+            });
+        }
+    }));
+    executor.test_run();
+
+    assert!(flags.iter().all(|flag| flag.load(Ordering::Relaxed)));
+}
+
+finn_os::task_local! {
+    static COUNTER: u32 = 0;
+}
+
+#[test_case]
+fn test_task_local() {
+    let totals = Arc::new(Mutex::new(Vec::new()));
+    let mut executor = Executor::new();
+    //Both tasks interleave, but each only sees its own counter
+    for increments in [3, 5] {
+        let totals = totals.clone();
+        executor.spawn(Task::new(async move {
+            for _ in 0..increments {
+                COUNTER.with(|count| *count += 1);
+                yield_now().await;
+            }
+            totals.lock().push(COUNTER.with(|count| *count));
+        }));
+    }
+    executor.test_run();
+
+    assert_eq!(*totals.lock(), [3, 5]);
+}
+
+#[test_case]
+fn test_poll_watchdog() {
+    let mut executor = Executor::new();
+    executor.set_poll_budget(Some(1));
+    let start = finn_os::time::ticks();
+    let task = Task::named("spinner", async move {
+        //Never awaits - only the preemption point gets it out of the loop
+        while !finn_os::executor::should_yield() {
+            assert!(
+                finn_os::time::ticks() - start < 20,
+                "preemption point never set"
+            );
+            core::hint::spin_loop();
+        }
+    });
+    executor.spawn(task);
+    executor.spawn(Task::new(async {}));
+    executor.test_run();
+    assert_eq!(executor.stats().overruns(), 1);
+    assert!(!finn_os::executor::should_yield());
+}
+
+#[test_case]
+fn test_stall_detection() {
+    let mut executor = Executor::new();
+    executor.set_idle_timeout(Some(2));
+    //Pending forever, with no waker stored anywhere
+    executor.spawn(Task::named("stuck", pending()));
+    executor.test_run();
+    assert_eq!(executor.stats().stalls(), 0);
+
+    let start = finn_os::time::ticks();
+    while finn_os::time::ticks() < start + 3 {
+        x86_64::instructions::hlt();
+    }
+    executor.test_run();
+    assert_eq!(executor.stats().stalls(), 1);
+    //Reported once per idle stretch, not on every check
+    executor.test_run();
+    assert_eq!(executor.stats().stalls(), 1);
+
+    //Opt-in, a fresh executor never reports
+    let mut executor = Executor::new();
+    executor.spawn(Task::named("waiting", pending()));
+    let start = finn_os::time::ticks();
+    while finn_os::time::ticks() < start + 3 {
+        x86_64::instructions::hlt();
+    }
+    executor.test_run();
+    assert_eq!(executor.stats().stalls(), 0);
+}
+
+#[test_case]
+fn test_default_executor() {
+    let ran = Arc::new(AtomicBool::new(false));
+    let mut executor = Executor::default();
+    let flag = ran.clone();
+    executor.spawn(Task::new(
+        async move { flag.store(true, Ordering::Relaxed) },
+    ));
+    executor.test_run();
+    assert!(ran.load(Ordering::Relaxed));
+}
+
+#[test_case]
+fn test_task_count() {
+    let mut executor = Executor::new();
+    assert!(executor.is_idle());
+    executor.spawn(Task::new(async {}));
+    executor.spawn(Task::new(async { yield_now().await }));
+    assert_eq!(executor.task_count(), 2);
+    assert!(!executor.is_idle());
+    executor.test_run();
+    assert_eq!(executor.task_count(), 0);
+    assert!(executor.is_idle());
+
+    executor.spawn(Task::new(pending()));
+    executor.test_run();
+    //Still pending, so not idle even though nothing is queued
+    assert_eq!(executor.task_count(), 1);
+    assert!(!executor.is_idle());
+}
+
+#[test_case]
+fn test_run_until_shutdown() {
+    let mut executor = Executor::new();
+    let finished = Arc::new(AtomicU64::new(0));
+    let shutdown = executor.shutdown_handle();
+    let count = finished.clone();
+    executor.spawn(Task::new(async move {
+        Delay::new(1).await;
+        count.fetch_add(1, Ordering::Relaxed);
+        shutdown.request_shutdown();
+    }));
+    //Never finishes, and mustn't keep run_until_shutdown from returning
+    executor.spawn(Task::new(pending()));
+    executor.run_until_shutdown();
+    assert_eq!(finished.load(Ordering::Relaxed), 1);
+    assert_eq!(executor.task_count(), 1);
+
+    //The request was used up, so the next run waits for a new one
+    executor.request_shutdown();
+    executor.run_until_shutdown();
+}
+
+#[test_case]
+fn test_idle_percent() {
+    let mut executor = Executor::new();
+    assert_eq!(executor.idle_percent(), None);
+    let shutdown = executor.shutdown_handle();
+    executor.spawn(Task::new(async move {
+        for _ in 0..4 {
+            //Busy for 2 ticks, then idle for 2
+            let start = finn_os::time::ticks();
+            while finn_os::time::ticks() < start + 2 {
+                core::hint::spin_loop();
+            }
+            Delay::new(2).await;
+        }
+        shutdown.request_shutdown();
+    }));
+    executor.run_until_shutdown();
+    let idle = executor.idle_percent().unwrap();
+    assert!((25..=75).contains(&idle), "idle {}%", idle);
+}
@@ -0,0 +1,43 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(finn_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use finn_os::{exit_qemu, serial_println, testing, QemuExitCode};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    finn_os::init(boot_info);
+
+    test_main();
+    finn_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    finn_os::test_panic_handler(info)
+}
+
+fn timeout_handler(name: &str) {
+    if name.ends_with("hangs_forever") {
+        serial_println!("[ok]");
+        exit_qemu(QemuExitCode::Success);
+    } else {
+        serial_println!("[failed]\n");
+        serial_println!("Error: watchdog fired for {}\n", name);
+        exit_qemu(QemuExitCode::Failed);
+    }
+}
+
+#[test_case]
+fn hangs_forever() {
+    testing::set_timeout_handler(timeout_handler);
+    testing::set_test_timeout(5);
+    loop {
+        core::hint::spin_loop();
+    }
+}
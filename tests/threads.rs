@@ -0,0 +1,77 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(finn_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use finn_os::{thread, time};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    finn_os::init(boot_info);
+
+    test_main();
+    finn_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    finn_os::test_panic_handler(info)
+}
+
+static FIRST: AtomicU64 = AtomicU64::new(0);
+static SECOND: AtomicU64 = AtomicU64::new(0);
+static STOP: AtomicBool = AtomicBool::new(false);
+
+fn count(counter: &'static AtomicU64) {
+    //Never yields - only preemption lets the other threads run
+    while !STOP.load(Ordering::Relaxed) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+#[test_case]
+fn threads_interleave() {
+    let first = thread::spawn(|| count(&FIRST));
+    let second = thread::spawn(|| count(&SECOND));
+    assert_ne!(first, second);
+    assert_ne!(thread::current(), first);
+
+    let start = time::ticks();
+    while FIRST.load(Ordering::Relaxed) == 0 || SECOND.load(Ordering::Relaxed) == 0 {
+        assert!(time::ticks() - start < 100, "threads did not both run");
+        x86_64::instructions::hlt();
+    }
+    //Both keep advancing once they're running
+    let (first_count, second_count) = (
+        FIRST.load(Ordering::Relaxed),
+        SECOND.load(Ordering::Relaxed),
+    );
+    let start = time::ticks();
+    while FIRST.load(Ordering::Relaxed) == first_count
+        || SECOND.load(Ordering::Relaxed) == second_count
+    {
+        assert!(time::ticks() - start < 100, "threads stopped advancing");
+        x86_64::instructions::hlt();
+    }
+    STOP.store(true, Ordering::Relaxed);
+}
+
+#[test_case]
+fn finished_threads_are_reaped() {
+    static RAN: AtomicBool = AtomicBool::new(false);
+    thread::spawn(|| RAN.store(true, Ordering::Relaxed));
+    let start = time::ticks();
+    while !RAN.load(Ordering::Relaxed) {
+        assert!(time::ticks() - start < 100, "thread never ran");
+        x86_64::instructions::hlt();
+    }
+    //Frees the finished threads' stacks
+    thread::spawn(|| {});
+}